@@ -1,19 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use axum::Router;
 use axum_test::TestServer;
-use cursor_usage_dashboard_backend::{
-    handlers::{health, upload, stats},
-    storage::UPLOADED_DATA,
-};
-use tower_http::cors::CorsLayer;
+use cursor_usage_dashboard_backend::{routes::create_router, state::AppState};
 
+// Every app gets its own in-memory store, so iterations never share data
 fn create_test_app() -> Router {
-    Router::new()
-        .route("/api/health", axum::routing::get(health::health_check))
-        .route("/api/upload", axum::routing::post(upload::upload_csv))
-        .route("/api/upload/append", axum::routing::post(upload::append_csv))
-        .route("/api/stats/comprehensive", axum::routing::get(stats::comprehensive_stats))
-        .layer(CorsLayer::permissive())
+    create_router(AppState::in_memory())
 }
 
 fn create_csv_data(num_records: usize) -> String {
@@ -79,12 +71,6 @@ fn bench_upload_endpoint(c: &mut Criterion) {
                         let app = create_test_app();
                         let server = TestServer::new(app).unwrap();
                         
-                        // Clear existing data
-                        {
-                            let mut data_store = UPLOADED_DATA.lock().unwrap();
-                            data_store.clear();
-                        }
-                        
                         let response = server
                             .post("/api/upload")
                             .content_type("multipart/form-data; boundary=----formdata-test-boundary")
@@ -163,12 +149,6 @@ fn bench_memory_usage_large_upload(c: &mut Criterion) {
                 let app = create_test_app();
                 let server = TestServer::new(app).unwrap();
                 
-                // Clear existing data
-                {
-                    let mut data_store = UPLOADED_DATA.lock().unwrap();
-                    data_store.clear();
-                }
-                
                 let response = server
                     .post("/api/upload")
                     .content_type("multipart/form-data; boundary=----formdata-test-boundary")
@@ -19,6 +19,9 @@ fn create_usage_data(num_records: usize) -> Vec<UsageData> {
             output_tokens: 75 + (i % 75) as u32,
             total_tokens: 250 + (i % 250) as u32,
             cost: 0.05 + (i as f64 * 0.001),
+            user: None,
+            requests: 1.0,
+            import_id: None,
         });
     }
     
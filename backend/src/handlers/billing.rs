@@ -0,0 +1,147 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::handlers::datasets::DatasetId;
+use crate::handlers::stats::DateRangeQuery;
+use crate::models::billing::BillingCycleConfig;
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::services::{billing_cycles::BillingCycles, data_processor::DataProcessor};
+use crate::state::AppState;
+
+/// Day the current cycle, forecast or budgets are taken as of
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    /// `YYYY-MM-DD`; the latest record's day when omitted
+    pub as_of: Option<String>,
+}
+
+impl AsOfQuery {
+    /// The requested day, else `latest` (the latest record's day), else today in
+    /// `timezone` when there are no records
+    pub fn resolve(&self, latest: Option<NaiveDate>, timezone: Tz) -> Result<NaiveDate, HandlerError> {
+        match self.as_of.as_deref() {
+            Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                create_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "INVALID_DATE",
+                    &format!("as_of must be a date in YYYY-MM-DD format, got '{}'", value),
+                )
+            }),
+            None => Ok(latest.unwrap_or_else(|| Utc::now().with_timezone(&timezone).date_naive())),
+        }
+    }
+}
+
+/// Billing cycles of a dataset. The configured timezone wins; without one, days
+/// are taken in `timezone` (the request's or the server default). A stored
+/// configuration that no longer resolves is reported as invalid, not as a storage failure.
+pub fn dataset_billing_cycles(state: &AppState, dataset_id: &str, timezone: Tz) -> Result<BillingCycles, HandlerError> {
+    let config = state
+        .store
+        .get_dataset(dataset_id)
+        .map_err(storage_error)?
+        .map(|dataset| dataset.billing_cycle)
+        .unwrap_or_default();
+    BillingCycles::from_config(&config, timezone).map_err(|e| {
+        create_error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "INVALID_BILLING_CYCLE",
+            &format!("Stored billing cycle configuration is invalid: {}", e),
+        )
+    })
+}
+
+/// Billing cycle configuration of the dataset
+pub async fn get_billing_config(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+) -> Result<Json<Value>, HandlerError> {
+    let dataset = state.store.get_dataset(&dataset_id).map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "billing_cycle": dataset.map(|dataset| dataset.billing_cycle).unwrap_or_default()
+    })))
+}
+
+/// Set the dataset's anchor day and billing timezone
+pub async fn update_billing_config(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Json(config): Json<BillingCycleConfig>,
+) -> Result<Json<Value>, HandlerError> {
+    config
+        .validate()
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_BILLING_CYCLE", &e))?;
+
+    let dataset = state
+        .store
+        .set_billing_cycle(&dataset_id, &config)
+        .map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "billing_cycle": dataset.map(|dataset| dataset.billing_cycle).unwrap_or(config)
+    })))
+}
+
+/// Cost, requests and tokens per billing cycle, oldest first
+pub async fn list_cycles(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let cycles = dataset_billing_cycles(&state, &dataset_id, timezone)?;
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(cycles.timezone()))
+        .map_err(storage_error)?;
+    let processor = DataProcessor::new().with_pricing(state.pricing.clone());
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "timezone": cycles.timezone().name(),
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "cycles": cycles.summarize(&filtered_data, &processor)
+    })))
+}
+
+/// Current cycle to date compared with the previous cycle; the current cycle is the one
+/// containing the latest record unless `as_of` asks for another day
+pub async fn current_cycle(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<AsOfQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let cycles = dataset_billing_cycles(&state, &dataset_id, timezone)?;
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(cycles.timezone()))
+        .map_err(storage_error)?;
+    let as_of = options.resolve(cycles.latest_day(&filtered_data), cycles.timezone())?;
+    let processor = DataProcessor::new().with_pricing(state.pricing.clone());
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "timezone": cycles.timezone().name(),
+        "as_of": as_of.to_string(),
+        "comparison": cycles.compare(&filtered_data, as_of, &processor)
+    })))
+}
@@ -0,0 +1,219 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::handlers::billing::{dataset_billing_cycles, AsOfQuery};
+use crate::handlers::datasets::DatasetId;
+use crate::models::budget::{Budget, BudgetAlert, BudgetRequest, BudgetStatus};
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::models::usage_data::UsageData;
+use crate::services::budget_evaluator::{new_alerts, BudgetEvaluator};
+use crate::state::AppState;
+use crate::storage::{BatchWriter, UsageQuery, WriteMode};
+
+fn invalid_budget(e: String) -> HandlerError {
+    create_error_response(StatusCode::BAD_REQUEST, "INVALID_BUDGET", &e)
+}
+
+fn budget_not_found(dataset_id: &str, budget_id: &str) -> HandlerError {
+    create_error_response(
+        StatusCode::NOT_FOUND,
+        "BUDGET_NOT_FOUND",
+        &format!("Budget '{}' does not exist in dataset '{}'", budget_id, dataset_id),
+    )
+}
+
+fn budget_id(params: &HashMap<String, String>) -> String {
+    params.get("budget_id").cloned().unwrap_or_default()
+}
+
+fn dataset_evaluator(state: &AppState, dataset_id: &str) -> Result<BudgetEvaluator, HandlerError> {
+    let cycles = dataset_billing_cycles(state, dataset_id, state.timezone)?;
+    Ok(BudgetEvaluator::with_billing_cycles(cycles))
+}
+
+/// Status of one budget as of the dataset's latest record
+fn current_status(state: &AppState, dataset_id: &str, budget: &Budget) -> Result<Option<BudgetStatus>, HandlerError> {
+    let data = state.store.load_all(dataset_id).map_err(storage_error)?;
+    let evaluator = dataset_evaluator(state, dataset_id)?;
+    Ok(evaluator
+        .latest_day(&data)
+        .map(|as_of| evaluator.evaluate(budget, &data, as_of)))
+}
+
+/// Budgets of a dataset, checked around an import so that only the thresholds the
+/// import itself crosses are reported afterwards
+pub struct BudgetCheck {
+    evaluator: BudgetEvaluator,
+    budgets: Vec<Budget>,
+}
+
+impl BudgetCheck {
+    pub fn for_dataset(state: &AppState, dataset_id: &str) -> Result<Self, HandlerError> {
+        Ok(Self {
+            evaluator: dataset_evaluator(state, dataset_id)?,
+            budgets: state.store.list_budgets(dataset_id).map_err(storage_error)?,
+        })
+    }
+
+    /// Local day of the latest dated record in `data`
+    pub fn latest_day(&self, data: &[UsageData]) -> Option<NaiveDate> {
+        self.evaluator.latest_day(data)
+    }
+
+    /// Statuses over the records the dataset held when `writer` began, as of the day
+    /// its latest record falls on once the import, whose latest record falls on
+    /// `import_latest`, is committed. Each budget reads only the records of its own
+    /// period and scope, through the write's transaction.
+    pub fn before_import(
+        &self,
+        writer: &mut dyn BatchWriter,
+        mode: WriteMode,
+        import_latest: NaiveDate,
+    ) -> Result<Vec<BudgetStatus>, String> {
+        if self.budgets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let timezone = self.evaluator.timezone();
+        let as_of = match mode {
+            WriteMode::Replace => import_latest,
+            WriteMode::Append => {
+                let later = writer.prior_records(&UsageQuery {
+                    start_date: Some(import_latest),
+                    timezone: Some(timezone),
+                    ..Default::default()
+                })?;
+                self.evaluator.latest_day(&later).map_or(import_latest, |day| day.max(import_latest))
+            }
+        };
+
+        self.budgets
+            .iter()
+            .map(|budget| {
+                let period = self.evaluator.period_containing(budget.period, as_of);
+                let records = writer.prior_records(&UsageQuery {
+                    start_date: Some(period.start),
+                    end_date: Some(as_of),
+                    model: budget.scope.model.clone(),
+                    kind: budget.scope.kind.clone(),
+                    user: budget.scope.user.clone(),
+                    timezone: Some(timezone),
+                })?;
+                Ok(self.evaluator.evaluate(budget, &records, as_of))
+            })
+            .collect()
+    }
+
+    /// Statuses as of the latest record in `after`, and the thresholds reached since
+    /// `before`; without statuses from before the import nothing counts as newly reached
+    pub fn evaluate(
+        &self,
+        before: Option<&[BudgetStatus]>,
+        after: &[UsageData],
+    ) -> (Vec<BudgetStatus>, Vec<BudgetAlert>) {
+        let Some(as_of) = self.evaluator.latest_day(after) else {
+            return (Vec::new(), Vec::new());
+        };
+        let after = self.evaluator.evaluate_all(&self.budgets, after, as_of);
+        let alerts = before.map(|before| new_alerts(before, &after)).unwrap_or_default();
+        (after, alerts)
+    }
+}
+
+/// Budgets of the dataset with their spend as of `as_of` (the latest record's day by
+/// default)
+pub async fn list_budgets(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(options): Query<AsOfQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let budgets = state.store.list_budgets(&dataset_id).map_err(storage_error)?;
+    let data = state.store.load_all(&dataset_id).map_err(storage_error)?;
+    let evaluator = dataset_evaluator(&state, &dataset_id)?;
+
+    let as_of = options.resolve(evaluator.latest_day(&data), evaluator.timezone())?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "currency": state.pricing.currency(),
+        "as_of": as_of.to_string(),
+        "budgets": budgets,
+        "statuses": evaluator.evaluate_all(&budgets, &data, as_of)
+    })))
+}
+
+pub async fn create_budget(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Json(request): Json<BudgetRequest>,
+) -> Result<(StatusCode, Json<Value>), HandlerError> {
+    let budget = Budget::new(request).map_err(invalid_budget)?;
+    state.store.save_budget(&dataset_id, &budget).map_err(storage_error)?;
+    let status = current_status(&state, &dataset_id, &budget)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "success": true,
+            "dataset_id": dataset_id,
+            "budget": budget,
+            "status": status
+        })),
+    ))
+}
+
+/// Replace a budget's definition, keeping its id
+pub async fn update_budget(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Path(params): Path<HashMap<String, String>>,
+    Json(request): Json<BudgetRequest>,
+) -> Result<Json<Value>, HandlerError> {
+    let budget_id = budget_id(&params);
+    let mut budget = state
+        .store
+        .list_budgets(&dataset_id)
+        .map_err(storage_error)?
+        .into_iter()
+        .find(|budget| budget.id == budget_id)
+        .ok_or_else(|| budget_not_found(&dataset_id, &budget_id))?;
+
+    budget.update(request).map_err(invalid_budget)?;
+    state.store.save_budget(&dataset_id, &budget).map_err(storage_error)?;
+    let status = current_status(&state, &dataset_id, &budget)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "budget": budget,
+        "status": status
+    })))
+}
+
+pub async fn delete_budget(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<Json<Value>, HandlerError> {
+    let budget_id = budget_id(&params);
+    if !state
+        .store
+        .delete_budget(&dataset_id, &budget_id)
+        .map_err(storage_error)?
+    {
+        return Err(budget_not_found(&dataset_id, &budget_id));
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Budget '{}' deleted", budget_id),
+        "dataset_id": dataset_id
+    })))
+}
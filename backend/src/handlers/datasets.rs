@@ -0,0 +1,116 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, RawPathParams, State},
+    http::{request::Parts, StatusCode},
+    response::Json,
+};
+use serde_json::{json, Value};
+
+use crate::models::dataset::{DatasetRequest, DEFAULT_DATASET_ID};
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::state::AppState;
+use crate::storage::validate_dataset_name;
+
+fn dataset_not_found(dataset_id: &str) -> HandlerError {
+    create_error_response(
+        StatusCode::NOT_FOUND,
+        "DATASET_NOT_FOUND",
+        &format!("Dataset '{}' does not exist", dataset_id),
+    )
+}
+
+/// Dataset targeted by a request: the `:dataset_id` path segment for routes nested
+/// under `/api/datasets/:dataset_id`, or the default dataset for the unscoped routes
+pub struct DatasetId(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for DatasetId {
+    type Rejection = HandlerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let dataset_id = RawPathParams::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|params| {
+                params
+                    .iter()
+                    .find(|(key, _)| *key == "dataset_id")
+                    .map(|(_, value)| value.to_string())
+            })
+            .unwrap_or_else(|| DEFAULT_DATASET_ID.to_string());
+
+        match state.store.get_dataset(&dataset_id).map_err(storage_error)? {
+            Some(_) => Ok(DatasetId(dataset_id)),
+            None => Err(dataset_not_found(&dataset_id)),
+        }
+    }
+}
+
+pub async fn list_datasets(State(state): State<AppState>) -> Result<Json<Value>, HandlerError> {
+    let datasets = state.store.list_datasets().map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "datasets": datasets
+    })))
+}
+
+pub async fn create_dataset(
+    State(state): State<AppState>,
+    Json(request): Json<DatasetRequest>,
+) -> Result<(StatusCode, Json<Value>), HandlerError> {
+    let name = validate_dataset_name(&request.name)
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_DATASET_NAME", &e))?;
+
+    let dataset = state.store.create_dataset(&name).map_err(storage_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "success": true,
+            "dataset": dataset
+        })),
+    ))
+}
+
+pub async fn rename_dataset(
+    State(state): State<AppState>,
+    Path(dataset_id): Path<String>,
+    Json(request): Json<DatasetRequest>,
+) -> Result<Json<Value>, HandlerError> {
+    let name = validate_dataset_name(&request.name)
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_DATASET_NAME", &e))?;
+
+    let dataset = state
+        .store
+        .rename_dataset(&dataset_id, &name)
+        .map_err(storage_error)?
+        .ok_or_else(|| dataset_not_found(&dataset_id))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset": dataset
+    })))
+}
+
+pub async fn delete_dataset(
+    State(state): State<AppState>,
+    Path(dataset_id): Path<String>,
+) -> Result<Json<Value>, HandlerError> {
+    if dataset_id == DEFAULT_DATASET_ID {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "DEFAULT_DATASET",
+            "The default dataset cannot be deleted",
+        ));
+    }
+
+    if !state.store.delete_dataset(&dataset_id).map_err(storage_error)? {
+        return Err(dataset_not_found(&dataset_id));
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Dataset '{}' deleted", dataset_id)
+    })))
+}
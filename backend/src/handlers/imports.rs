@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::handlers::datasets::DatasetId;
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::services::data_processor::DataProcessor;
+use crate::state::AppState;
+
+/// Import history of the dataset, oldest first
+pub async fn list_imports(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+) -> Result<Json<Value>, HandlerError> {
+    let imports = state.store.list_imports(&dataset_id).map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "imports": imports
+    })))
+}
+
+/// Roll back one import: delete it and the rows it stored, then recompute the summary
+pub async fn delete_import(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<Json<Value>, HandlerError> {
+    let import_id = params.get("import_id").cloned().unwrap_or_default();
+
+    let deleted_records = state
+        .store
+        .delete_import(&dataset_id, &import_id)
+        .map_err(storage_error)?
+        .ok_or_else(|| {
+            create_error_response(
+                StatusCode::NOT_FOUND,
+                "IMPORT_NOT_FOUND",
+                &format!("Import '{}' does not exist in dataset '{}'", import_id, dataset_id),
+            )
+        })?;
+
+    let usage_data = state.store.load_all(&dataset_id).map_err(storage_error)?;
+    let summary = DataProcessor::new()
+        .with_pricing(state.pricing.clone())
+        .calculate_summary(&usage_data);
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Import '{}' deleted", import_id),
+        "dataset_id": dataset_id,
+        "deleted_records": deleted_records,
+        "total_records": usage_data.len(),
+        "data": usage_data,
+        "summary": summary
+    })))
+}
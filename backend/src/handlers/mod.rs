@@ -1,3 +1,8 @@
 pub mod upload;
 pub mod health;
-pub mod stats;
\ No newline at end of file
+pub mod stats;
+pub mod datasets;
+pub mod imports;
+pub mod billing;
+pub mod budgets;
+pub mod webhooks;
@@ -1,68 +1,165 @@
-use axum::{extract::Query, http::StatusCode, response::Json};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use chrono::{DateTime, NaiveDate};
+use std::collections::HashMap;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
 
-use crate::models::usage_data::UsageData;
-use crate::services::{data_processor::DataProcessor, stats_calculator::StatsCalculator};
-use crate::storage::UPLOADED_DATA;
+use crate::handlers::billing::{dataset_billing_cycles, AsOfQuery};
+use crate::handlers::datasets::DatasetId;
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::models::ranking::RankingMetric;
+use crate::models::stats::Granularity;
+use crate::services::{
+    anomaly_detector::{AnomalyDetector, AnomalyThresholds},
+    cost_audit::{CostAuditor, DEFAULT_TOLERANCE},
+    data_processor::DataProcessor,
+    forecaster::{Forecaster, DEFAULT_HISTORY_DAYS},
+    model_simulator::ModelSimulator,
+    ranker::Ranker,
+    sessionizer::{Sessionizer, DEFAULT_IDLE_GAP_MINUTES},
+    stats_calculator::StatsCalculator,
+    time_series::TimeSeriesAggregator,
+};
+use crate::state::AppState;
+use crate::storage::UsageQuery;
+use crate::utils::date_utils::parse_timezone;
+
+/// Rows listed by the cost audit, anomaly, session and ranking endpoints unless `limit` says otherwise,
+/// and the most they list
+const DEFAULT_ROW_LIMIT: usize = 20;
+const MAX_ROW_LIMIT: usize = 1000;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DateRangeQuery {
     pub start_date: Option<String>,
     pub end_date: Option<String>,
+    /// Only include records of this kind, e.g. "Usage-based"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Only include records of this user (team-admin exports)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// IANA timezone for days and hours, e.g. "Asia/Tokyo"; the server default otherwise
+    #[serde(alias = "tz", skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// Bucketing options of the time-series endpoint; filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct TimeSeriesQuery {
+    /// `daily` (default), `hourly` or `10min`
+    pub granularity: Option<String>,
+    /// Also return one aligned series per model
+    #[serde(default)]
+    pub by_model: bool,
+}
+
+/// Options of the cost audit endpoint; filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct CostAuditQuery {
+    /// Most divergent rows to list (default 20, at most 1000)
+    pub limit: Option<usize>,
+    /// Relative deviation tolerated before a row is flagged (default 0.05)
+    pub tolerance: Option<f64>,
+}
+
+/// Options of the forecast endpoint; filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct ForecastQuery {
+    /// Days of history to fit on (default 28)
+    pub history_days: Option<u32>,
+}
+
+/// Options of the anomaly endpoint; filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct AnomalyQuery {
+    /// Days before each day that form its baseline (default 14)
+    pub window_days: Option<u32>,
+    /// Scaled MADs above the baseline median a day must reach (default 3.5)
+    pub mad_threshold: Option<f64>,
+    /// Multiple of the model's p99 a request must exceed (default 2)
+    pub request_multiplier: Option<f64>,
+    /// Most extreme requests to list (default 20, at most 1000)
+    pub limit: Option<usize>,
+}
+
+/// Options of the session endpoint; filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    /// Idle minutes that end a session (default 30)
+    pub idle_gap_minutes: Option<u32>,
+    /// Most expensive sessions to list (default 20, at most 1000)
+    pub limit: Option<usize>,
+}
+
+/// Options of the ranking endpoint; date, kind and user filters come from `DateRangeQuery`
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    /// `cost` (default) or `tokens`
+    pub metric: Option<String>,
+    /// Entries per ranking (default 20, at most 1000)
+    pub limit: Option<usize>,
+    /// Only include requests to this model
+    pub model: Option<String>,
+    /// Only include requests with (or without) Max mode
+    pub max_mode: Option<bool>,
+}
+
+/// Body of the simulation endpoint
+#[derive(Debug, Deserialize)]
+pub struct SimulationRequest {
+    /// Actual model -> model it is replaced with, e.g. `{"claude-4-opus": "claude-4-sonnet"}`
+    pub substitutions: HashMap<String, String>,
 }
 
-/// Filter usage data by date range
-/// Requirements: 10.1, 10.2, 10.5
-fn filter_by_date_range(data: &[UsageData], query: &DateRangeQuery) -> Vec<UsageData> {
-    if query.start_date.is_none() && query.end_date.is_none() {
-        return data.to_vec();
+impl DateRangeQuery {
+    /// The requested timezone, or `default` when none was given
+    pub fn resolve_timezone(&self, default: Tz) -> Result<Tz, HandlerError> {
+        match self.timezone.as_deref() {
+            Some(name) => parse_timezone(name)
+                .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_TIMEZONE", &e)),
+            None => Ok(default),
+        }
     }
 
-    data.iter()
-        .filter(|usage| {
-            // Parse the usage date
-            let usage_date = match DateTime::parse_from_rfc3339(&usage.date) {
-                Ok(dt) => dt.date_naive(),
-                Err(_) => return false,
-            };
-
-            // Check start date
-            if let Some(ref start) = query.start_date {
-                if let Ok(start_date) = NaiveDate::parse_from_str(start, "%Y-%m-%d") {
-                    if usage_date < start_date {
-                        return false;
-                    }
-                }
-            }
-
-            // Check end date
-            if let Some(ref end) = query.end_date {
-                if let Ok(end_date) = NaiveDate::parse_from_str(end, "%Y-%m-%d") {
-                    if usage_date > end_date {
-                        return false;
-                    }
-                }
-            }
-
-            true
-        })
-        .cloned()
-        .collect()
+    /// Convert to a store query whose dates are days in `timezone`; unparseable dates
+    /// are ignored rather than rejected
+    /// Requirements: 10.1, 10.2, 10.5
+    pub fn to_usage_query(&self, timezone: Tz) -> UsageQuery {
+        let parse = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok())
+        };
+
+        UsageQuery {
+            start_date: parse(&self.start_date),
+            end_date: parse(&self.end_date),
+            kind: self.kind.clone(),
+            user: self.user.clone(),
+            timezone: Some(timezone),
+            ..Default::default()
+        }
+    }
 }
 
 /// Comprehensive statistics endpoint with date filtering
 /// Requirements: 7.1, 7.2, 7.3, 7.4, 7.5, 7.6, 10.1, 10.2, 10.5
 pub async fn comprehensive_stats(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
     Query(date_range): Query<DateRangeQuery>,
-) -> Result<Json<Value>, StatusCode> {
-    let data = {
-        let data_store = UPLOADED_DATA.lock().unwrap();
-        data_store.clone()
-    };
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+
+    let total_records = state.store.count(&dataset_id).map_err(storage_error)?;
 
-    if data.is_empty() {
+    if total_records == 0 {
         return Ok(Json(json!({
             "success": true,
             "message": "No data available. Please upload a CSV file first.",
@@ -71,7 +168,10 @@ pub async fn comprehensive_stats(
     }
 
     // Filter data by date range if provided
-    let filtered_data = filter_by_date_range(&data, &date_range);
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
 
     if filtered_data.is_empty() {
         return Ok(Json(json!({
@@ -83,12 +183,12 @@ pub async fn comprehensive_stats(
     }
 
     // Calculate basic summary and model stats
-    let processor = DataProcessor::new();
+    let processor = DataProcessor::new().with_pricing(state.pricing.clone());
     let summary = processor.calculate_summary(&filtered_data);
     let model_stats = processor.calculate_model_stats(&filtered_data);
 
     // Calculate comprehensive statistics
-    let stats_calculator = StatsCalculator::new();
+    let stats_calculator = StatsCalculator::with_timezone(timezone).with_pricing(state.pricing.clone());
     let comprehensive_stats = stats_calculator.calculate_comprehensive_stats(&filtered_data);
 
     Ok(Json(json!({
@@ -96,9 +196,282 @@ pub async fn comprehensive_stats(
         "summary": summary,
         "model_stats": model_stats,
         "comprehensive_stats": comprehensive_stats,
+        "dataset_id": dataset_id,
         "record_count": filtered_data.len(),
-        "total_records": data.len(),
+        "total_records": total_records,
         "date_range": date_range,
+        "timezone": timezone.name(),
         "message": "Comprehensive statistics calculated successfully."
     })))
-}
\ No newline at end of file
+}
+
+/// Token, cost and request totals bucketed by day, hour or 10 minutes
+pub async fn timeseries_stats(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<TimeSeriesQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let granularity = match options.granularity.as_deref() {
+        Some(value) => Granularity::parse(value)
+            .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_GRANULARITY", &e))?,
+        None => Granularity::Daily,
+    };
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
+
+    let series = TimeSeriesAggregator::with_timezone(timezone)
+        .aggregate(&filtered_data, granularity, options.by_model)
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "TOO_MANY_BUCKETS", &e))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "timeseries": series,
+    })))
+}
+
+/// Compare reported costs with list prices per model and day, listing the most
+/// divergent rows
+pub async fn cost_audit(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<CostAuditQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let tolerance = options.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_TOLERANCE",
+            "tolerance must be a non-negative number, e.g. 0.05 for 5%",
+        ));
+    }
+    let limit = options.limit.unwrap_or(DEFAULT_ROW_LIMIT).min(MAX_ROW_LIMIT);
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
+
+    let audit = CostAuditor::with_timezone(timezone)
+        .with_pricing(state.pricing.clone())
+        .audit(&filtered_data, tolerance, limit);
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "audit": audit,
+    })))
+}
+
+/// Project the cost of the filtered usage had some models been replaced by others
+pub async fn simulate_substitutions(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Json(request): Json<SimulationRequest>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    if request.substitutions.is_empty() {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_SUBSTITUTIONS",
+            "substitutions must map at least one model to its replacement",
+        ));
+    }
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
+
+    let simulation = ModelSimulator::with_timezone(timezone)
+        .with_pricing(state.pricing.clone())
+        .simulate(&filtered_data, &request.substitutions)
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "UNKNOWN_MODEL", &e))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "substitutions": request.substitutions,
+        "simulation": simulation,
+    })))
+}
+
+/// Project spend and tokens through the end of the current billing cycle, the one
+/// containing the latest record unless `as_of` asks for another day
+pub async fn forecast(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<ForecastQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let history_days = options.history_days.unwrap_or(DEFAULT_HISTORY_DAYS);
+    if history_days == 0 {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_HISTORY_DAYS",
+            "history_days must be at least 1",
+        ));
+    }
+
+    let cycles = dataset_billing_cycles(&state, &dataset_id, timezone)?;
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(cycles.timezone()))
+        .map_err(storage_error)?;
+
+    let as_of = as_of.resolve(cycles.latest_day(&filtered_data), cycles.timezone())?;
+    let timezone = cycles.timezone();
+
+    let forecaster = Forecaster::with_billing_cycles(cycles);
+    let Some(forecast) = forecaster.forecast_as_of(&filtered_data, as_of, history_days) else {
+        return Ok(Json(json!({
+            "success": true,
+            "message": "No data available to forecast from.",
+            "forecast": null,
+            "date_range": date_range
+        })));
+    };
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "timezone": timezone.name(),
+        "as_of": as_of.to_string(),
+        "forecast": forecast,
+    })))
+}
+
+/// Days far above their rolling baseline and requests far above their model's p99
+pub async fn anomalies(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<AnomalyQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let defaults = AnomalyThresholds::default();
+    let thresholds = AnomalyThresholds {
+        window_days: options.window_days.unwrap_or(defaults.window_days),
+        mad_threshold: options.mad_threshold.unwrap_or(defaults.mad_threshold),
+        request_multiplier: options.request_multiplier.unwrap_or(defaults.request_multiplier),
+    };
+    if thresholds.window_days == 0
+        || !(thresholds.mad_threshold.is_finite() && thresholds.mad_threshold > 0.0)
+        || !(thresholds.request_multiplier.is_finite() && thresholds.request_multiplier > 0.0)
+    {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_THRESHOLD",
+            "window_days, mad_threshold and request_multiplier must be positive",
+        ));
+    }
+    let limit = options.limit.unwrap_or(DEFAULT_ROW_LIMIT).min(MAX_ROW_LIMIT);
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
+
+    let anomalies = AnomalyDetector::with_timezone(timezone).detect(&filtered_data, &thresholds, limit);
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "timezone": timezone.name(),
+        "anomalies": anomalies,
+    })))
+}
+
+/// Requests grouped into sessions by idle gaps, with the most expensive sessions
+/// Requirements: 3.5
+pub async fn sessions(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<SessionQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let idle_gap_minutes = options.idle_gap_minutes.unwrap_or(DEFAULT_IDLE_GAP_MINUTES);
+    if idle_gap_minutes == 0 {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_IDLE_GAP",
+            "idle_gap_minutes must be positive",
+        ));
+    }
+    let limit = options.limit.unwrap_or(DEFAULT_ROW_LIMIT).min(MAX_ROW_LIMIT);
+
+    let filtered_data = state
+        .store
+        .query(&dataset_id, &date_range.to_usage_query(timezone))
+        .map_err(storage_error)?;
+
+    let sessions = Sessionizer::with_timezone(timezone).sessionize(&filtered_data, idle_gap_minutes, limit);
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "timezone": timezone.name(),
+        "sessions": sessions,
+    })))
+}
+
+/// Most expensive or largest requests, hours and days
+/// Requirements: 3.5
+pub async fn top_usage(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(date_range): Query<DateRangeQuery>,
+    Query(options): Query<TopQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let timezone = date_range.resolve_timezone(state.timezone)?;
+    let metric = RankingMetric::parse(options.metric.as_deref().unwrap_or("cost"))
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_METRIC", &e))?;
+    let limit = options.limit.unwrap_or(DEFAULT_ROW_LIMIT).min(MAX_ROW_LIMIT);
+
+    let query = UsageQuery {
+        model: options.model.clone(),
+        ..date_range.to_usage_query(timezone)
+    };
+    let mut filtered_data = state
+        .store
+        .query(&dataset_id, &query)
+        .map_err(storage_error)?;
+    if let Some(max_mode) = options.max_mode {
+        filtered_data.retain(|usage| usage.max_mode == max_mode);
+    }
+
+    let rankings = Ranker::with_timezone(timezone).rank(&filtered_data, metric, limit);
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "record_count": filtered_data.len(),
+        "date_range": date_range,
+        "timezone": timezone.name(),
+        "top": rankings,
+    })))
+}
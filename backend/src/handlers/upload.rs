@@ -1,119 +1,377 @@
 use axum::{
-    extract::Multipart,
-    http::StatusCode,
+    extract::{multipart::Field, FromRequest, Multipart, Query, Request, State},
+    http::{header, StatusCode},
     response::Json,
 };
+use bytes::Bytes;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::io::Read;
+use std::sync::Arc;
 use serde_json::{json, Value};
-use crate::models::error::{ErrorResponse, ErrorDetails};
-use crate::services::{csv_parser::CsvParser, data_processor::DataProcessor};
-use crate::storage::UPLOADED_DATA;
+use crate::handlers::budgets::BudgetCheck;
+use crate::handlers::datasets::DatasetId;
+use crate::handlers::webhooks::{has_subscribers, notify_webhooks};
+use crate::models::budget::BudgetStatus;
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::models::import::ImportRecord;
+use crate::models::parse_report::{ParseMode, ParseReport};
+use crate::models::usage_data::{DateRange, UsageData};
+use crate::models::webhook::{WebhookEvent, WebhookEventKind};
+use crate::services::{
+    anomaly_detector::{AnomalyDetector, AnomalyThresholds},
+    csv_parser::{CsvParser, DEFAULT_BATCH_SIZE},
+    data_processor::DataProcessor,
+    json_importer::{JsonFormat, JsonImporter},
+    record_identity::ImportDeduplicator,
+    upload_preview::UploadPreview,
+};
+use crate::state::AppState;
+use crate::storage::{UsageStore, WriteMode};
+use crate::utils::archive::{for_each_csv, UnpackError, UploadFormat};
+use crate::utils::spool::UploadSpool;
+use crate::utils::content_hash::HashingReader;
 
-const MAX_FILE_SIZE: usize = 100 * 1024 * 1024; // 100MB
+pub const MAX_FILE_SIZE: usize = 100 * 1024 * 1024; // 100MB
 
-pub async fn upload_csv(mut multipart: Multipart) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
-    let mut csv_content = String::new();
-    let mut file_received = false;
+/// Spooled upload handed to the parser
+type UploadReader = std::io::BufReader<std::fs::File>;
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        create_error_response(
-            StatusCode::BAD_REQUEST,
-            "MULTIPART_ERROR",
-            &format!("Error processing multipart data: {}", e),
-        )
-    })? {
-        let field_name = field.name().unwrap_or("");
-        
-        if field_name == "csvFile" {
-            let file_name = field.file_name().unwrap_or("unknown").to_string();
-            
-            // Validate file extension
-            if !file_name.to_lowercase().ends_with(".csv") {
-                return Err(create_error_response(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_FILE_TYPE",
-                    "Only CSV files are allowed",
-                ));
-            }
+/// Query parameters accepted by the upload endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadOptions {
+    /// `strict` (default) rejects the whole file on the first invalid row;
+    /// `lenient` imports the valid rows and reports the rejected ones
+    #[serde(default)]
+    pub mode: ParseMode,
+}
+
+/// Name and format of the uploaded data
+struct UploadSource {
+    file_name: String,
+    kind: UploadKind,
+}
+
+enum UploadKind {
+    /// A CSV export, possibly compressed or archived
+    Csv(UploadFormat),
+    /// JSON usage events
+    Json(JsonFormat),
+}
+
+/// Where upload bytes come from: a multipart file field or a raw request body
+enum ChunkSource<'a> {
+    Field(Box<Field<'a>>),
+    Body(axum::body::BodyDataStream),
+}
 
-            let data = field.bytes().await.map_err(|e| {
-                create_error_response(
+impl ChunkSource<'_> {
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, String> {
+        match self {
+            ChunkSource::Field(field) => field.chunk().await.map_err(|e| e.to_string()),
+            ChunkSource::Body(stream) => stream.next().await.transpose().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Failure raised while parsing and storing an upload on the blocking thread
+enum IngestError {
+    Parse(String),
+    /// A JSON or NDJSON upload could not be parsed
+    JsonParse(String),
+    Validation(String),
+    Storage(String),
+    NoValidRows(ParseReport),
+    InvalidArchive(String),
+    TooLarge(String),
+}
+
+impl IngestError {
+    fn into_response(self) -> HandlerError {
+        match self {
+            IngestError::Parse(e) => create_error_response(StatusCode::BAD_REQUEST, "CSV_PARSE_ERROR", &e),
+            IngestError::JsonParse(e) => create_error_response(StatusCode::BAD_REQUEST, "JSON_PARSE_ERROR", &e),
+            IngestError::Validation(e) => {
+                create_error_response(StatusCode::BAD_REQUEST, "DATA_VALIDATION_ERROR", &e)
+            }
+            IngestError::Storage(e) => storage_error(e),
+            IngestError::InvalidArchive(e) => {
+                create_error_response(StatusCode::BAD_REQUEST, "INVALID_ARCHIVE", &e)
+            }
+            IngestError::TooLarge(e) => {
+                create_error_response(StatusCode::PAYLOAD_TOO_LARGE, "FILE_TOO_LARGE", &e)
+            }
+            IngestError::NoValidRows(report) => {
+                let (status, mut body) = create_error_response(
                     StatusCode::BAD_REQUEST,
-                    "FILE_READ_ERROR",
-                    &format!("Error reading file: {}", e),
-                )
-            })?;
-
-            // Check file size
-            if data.len() > MAX_FILE_SIZE {
-                return Err(create_error_response(
-                    StatusCode::PAYLOAD_TOO_LARGE,
-                    "FILE_TOO_LARGE",
-                    &format!("File size ({} bytes) exceeds maximum allowed size ({} bytes)", data.len(), MAX_FILE_SIZE),
-                ));
+                    "NO_VALID_ROWS",
+                    "CSV file contains no valid data rows",
+                );
+                body.error.details = serde_json::to_value(report).ok();
+                (status, body)
             }
+        }
+    }
+}
 
-            csv_content = String::from_utf8(data.to_vec()).map_err(|_| {
-                create_error_response(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_ENCODING",
-                    "File must be UTF-8 encoded",
-                )
-            })?;
+/// What storing an upload did to the dataset
+enum IngestOutcome {
+    Imported {
+        report: ParseReport,
+        import: ImportRecord,
+        /// Accepted rows an earlier import had already stored
+        skipped_rows: usize,
+        /// Budget statuses over the dataset as it was before the import, read in the
+        /// import's transaction; unset when the import holds no dated record
+        budgets_before: Option<Vec<BudgetStatus>>,
+    },
+    /// The file is identical to an earlier import, so nothing was changed
+    Duplicate { report: ParseReport, previous: ImportRecord },
+}
 
-            file_received = true;
-            break;
+impl IngestOutcome {
+    /// Response fields shared by the upload endpoints
+    fn response_fields(&self, message: &str) -> Value {
+        match self {
+            IngestOutcome::Imported { report, import, skipped_rows, .. } => json!({
+                "message": message,
+                "duplicate": false,
+                "import": import,
+                "duplicate_records": skipped_rows,
+                "schema_version": report.schema_version,
+                "parse_report": report
+            }),
+            IngestOutcome::Duplicate { report, previous } => json!({
+                "message": format!(
+                    "File is identical to import {} from {}; nothing was changed",
+                    previous.id, previous.created_at
+                ),
+                "duplicate": true,
+                "duplicate_of": previous,
+                "schema_version": report.schema_version,
+                "parse_report": report
+            }),
         }
     }
 
-    if !file_received {
-        return Err(create_error_response(
-            StatusCode::BAD_REQUEST,
-            "NO_FILE",
-            "No CSV file provided. Please upload a file with field name 'csvFile'",
-        ));
+    /// Budget statuses to compare the dataset after the import against
+    fn budgets_before(&self) -> Option<&[BudgetStatus]> {
+        match self {
+            IngestOutcome::Imported { budgets_before, .. } => budgets_before.as_deref(),
+            IngestOutcome::Duplicate { .. } => None,
+        }
     }
 
-    // Parse CSV content
-    let parser = CsvParser::new();
-    let usage_data = parser.parse_csv(&csv_content).map_err(|e| {
-        create_error_response(
-            StatusCode::BAD_REQUEST,
-            "CSV_PARSE_ERROR",
-            &e,
-        )
-    })?;
+    /// Rows added to the dataset
+    fn imported_rows(&self) -> usize {
+        match self {
+            IngestOutcome::Imported { import, .. } => import.record_count,
+            IngestOutcome::Duplicate { .. } => 0,
+        }
+    }
+}
 
-    // Validate parsed data
-    let processor = DataProcessor::new();
-    processor.validate_usage_data(&usage_data).map_err(|e| {
-        create_error_response(
-            StatusCode::BAD_REQUEST,
-            "DATA_VALIDATION_ERROR",
-            &e,
-        )
-    })?;
+pub async fn upload_csv(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(options): Query<UploadOptions>,
+    request: Request,
+) -> Result<Json<Value>, HandlerError> {
+    // Stream the file into storage (replace existing data)
+    let budget_check = Arc::new(BudgetCheck::for_dataset(&state, &dataset_id)?);
+    let outcome = ingest_upload(
+        &state,
+        &dataset_id,
+        request,
+        WriteMode::Replace,
+        options.mode,
+        budget_check.clone(),
+    )
+    .await?;
 
-    // Store data in memory (replace existing data)
-    {
-        let mut data_store = UPLOADED_DATA.lock().unwrap();
-        *data_store = usage_data.clone();
-    }
+    let usage_data = state.store.load_all(&dataset_id).map_err(storage_error)?;
 
     // Calculate summary using DataProcessor
+    let processor = DataProcessor::new().with_pricing(state.pricing.clone());
     let summary = processor.calculate_summary(&usage_data);
+    let (budgets, budget_alerts) = budget_check.evaluate(outcome.budgets_before(), &usage_data);
 
-    Ok(Json(json!({
+    let mut response = json!({
         "success": true,
-        "message": "CSV file uploaded and parsed successfully",
+        "dataset_id": dataset_id,
         "data": usage_data,
         "summary": summary,
-        "record_count": usage_data.len()
+        "record_count": outcome.imported_rows(),
+        "budgets": budgets,
+        "budget_alerts": budget_alerts
+    });
+    merge_fields(&mut response, outcome.response_fields("CSV file uploaded and parsed successfully"));
+    Ok(Json(response))
+}
+
+pub async fn append_csv(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(options): Query<UploadOptions>,
+    request: Request,
+) -> Result<Json<Value>, HandlerError> {
+    // Stream the new records in after the existing ones
+    let budget_check = Arc::new(BudgetCheck::for_dataset(&state, &dataset_id)?);
+    let outcome = ingest_upload(
+        &state,
+        &dataset_id,
+        request,
+        WriteMode::Append,
+        options.mode,
+        budget_check.clone(),
+    )
+    .await?;
+
+    // Rows already stored by an earlier import were skipped while streaming
+    let combined_data = state.store.load_all(&dataset_id).map_err(storage_error)?;
+
+    // Calculate summary for combined data using DataProcessor
+    let processor = DataProcessor::new().with_pricing(state.pricing.clone());
+    let summary = processor.calculate_summary(&combined_data);
+    let (budgets, budget_alerts) = budget_check.evaluate(outcome.budgets_before(), &combined_data);
+    let mut events: Vec<WebhookEvent> = budget_alerts
+        .iter()
+        .map(|alert| WebhookEvent::budget_threshold(&dataset_id, alert, state.pricing.currency()))
+        .collect();
+    let import = match outcome {
+        IngestOutcome::Imported { ref import, .. } => Some(import),
+        IngestOutcome::Duplicate { .. } => None,
+    };
+    if let Some(import) = import.filter(|_| has_subscribers(&state, &dataset_id, WebhookEventKind::Anomaly)) {
+        // Only anomalies the appended rows are part of are new
+        let detector = AnomalyDetector::with_timezone(state.timezone);
+        let report = detector.detect(&combined_data, &AnomalyThresholds::default(), usize::MAX);
+        let report = detector.restrict_to_import(report, &combined_data, &import.id);
+        events.extend(report.daily.iter().map(|anomaly| WebhookEvent::daily_anomaly(&dataset_id, anomaly)));
+        events.extend(report.requests.iter().map(|anomaly| WebhookEvent::request_anomaly(&dataset_id, anomaly)));
+    }
+    notify_webhooks(&state, &dataset_id, events);
+
+    let mut response = json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "data": combined_data,
+        "summary": summary,
+        "new_records": outcome.imported_rows(),
+        "total_records": combined_data.len(),
+        "budgets": budgets,
+        "budget_alerts": budget_alerts
+    });
+    merge_fields(&mut response, outcome.response_fields("CSV file appended successfully"));
+    Ok(Json(response))
+}
+
+fn merge_fields(response: &mut Value, fields: Value) {
+    if let (Value::Object(response), Value::Object(fields)) = (response, fields) {
+        response.extend(fields);
+    }
+}
+
+/// Dry run of an upload: parse and validate the file, report what it contains and how
+/// it overlaps the dataset's existing records, but store nothing
+pub async fn validate_csv(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(options): Query<UploadOptions>,
+    request: Request,
+) -> Result<Json<Value>, HandlerError> {
+    let existing = state.store.load_all(&dataset_id).map_err(storage_error)?;
+    let imports = state.store.list_imports(&dataset_id).map_err(storage_error)?;
+
+    // Always parse leniently so every problem in the file is reported at once; rows that
+    // parse are also run through the validation an import applies
+    let (report, preview, content_hash) = stream_upload(&state, request, move |reader, source| {
+        let mut preview = UploadPreview::new(&existing);
+        let mut reader = HashingReader::new(reader);
+        let report = parse_upload(&mut reader, &source, ParseMode::Lenient, |batch| {
+            preview.add_batch(&batch);
+            Ok(())
+        })?;
+        let content_hash = reader
+            .finish()
+            .map_err(|e| IngestError::Parse(format!("Error reading upload: {}", e)))?;
+        Ok((report, preview, content_hash))
+    })
+    .await?;
+
+    let previous_import = find_identical_import(&imports, &content_hash, WriteMode::Append);
+
+    // An import aborts on the first row failing validation, whatever the parse mode
+    let would_succeed = preview.valid_rows > 0
+        && preview.invalid_rows == 0
+        && (options.mode == ParseMode::Lenient || report.rejected_rows == 0);
+
+    Ok(Json(json!({
+        "success": true,
+        "dry_run": true,
+        "message": "CSV file validated; no data was stored",
+        "dataset_id": dataset_id,
+        "mode": options.mode,
+        "would_succeed": would_succeed,
+        "total_rows": report.accepted_rows + report.rejected_rows,
+        "schema_version": report.schema_version,
+        "content_hash": content_hash,
+        "previous_import": previous_import,
+        "preview": preview,
+        "parse_report": report
     })))
 }
 
-pub async fn append_csv(mut multipart: Multipart) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
-    let mut csv_content = String::new();
-    let mut file_received = false;
+/// Stream the uploaded data into the dataset through a store transaction, so the
+/// file is never held in memory as a whole.
+async fn ingest_upload(
+    state: &AppState,
+    dataset_id: &str,
+    request: Request,
+    mode: WriteMode,
+    parse_mode: ParseMode,
+    budget_check: Arc<BudgetCheck>,
+) -> Result<IngestOutcome, HandlerError> {
+    let store = state.store.clone();
+    let target = dataset_id.to_string();
+    stream_upload(state, request, move |reader, source| {
+        store_upload(store.as_ref(), &target, reader, &source, mode, parse_mode, &budget_check)
+    })
+    .await
+}
+
+/// Locate the uploaded data and hand it to `process` on a blocking thread. JSON and
+/// NDJSON request bodies are read directly, chosen by the request's content type;
+/// otherwise the request must be a multipart form with a `csvFile` field, whose format
+/// is taken from the field's content type or file extension.
+async fn stream_upload<T, F>(state: &AppState, request: Request, process: F) -> Result<T, HandlerError>
+where
+    T: Send + 'static,
+    F: FnOnce(UploadReader, UploadSource) -> Result<T, IngestError> + Send + 'static,
+{
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    if let Some(format) = JsonFormat::from_content_type(&content_type) {
+        let source = UploadSource {
+            file_name: "request body".to_string(),
+            kind: UploadKind::Json(format),
+        };
+        let body = request.into_body().into_data_stream();
+        return spool_chunks(ChunkSource::Body(body), source, process).await;
+    }
+
+    let mut multipart = Multipart::from_request(request, state).await.map_err(|e| {
+        create_error_response(
+            StatusCode::BAD_REQUEST,
+            "MULTIPART_ERROR",
+            &format!("Expected a multipart form or a JSON body: {}", e),
+        )
+    })?;
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         create_error_response(
@@ -124,122 +382,250 @@ pub async fn append_csv(mut multipart: Multipart) -> Result<Json<Value>, (Status
     })? {
         let field_name = field.name().unwrap_or("");
         
-        if field_name == "csvFile" {
-            let file_name = field.file_name().unwrap_or("unknown").to_string();
-            
-            // Validate file extension
-            if !file_name.to_lowercase().ends_with(".csv") {
-                return Err(create_error_response(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_FILE_TYPE",
-                    "Only CSV files are allowed",
-                ));
-            }
+        if field_name != "csvFile" {
+            continue;
+        }
 
-            let data = field.bytes().await.map_err(|e| {
-                create_error_response(
-                    StatusCode::BAD_REQUEST,
-                    "FILE_READ_ERROR",
-                    &format!("Error reading file: {}", e),
-                )
-            })?;
-
-            // Check file size
-            if data.len() > MAX_FILE_SIZE {
-                return Err(create_error_response(
-                    StatusCode::PAYLOAD_TOO_LARGE,
-                    "FILE_TOO_LARGE",
-                    &format!("File size ({} bytes) exceeds maximum allowed size ({} bytes)", data.len(), MAX_FILE_SIZE),
-                ));
-            }
+        let file_name = field.file_name().unwrap_or("unknown").to_string();
+        let field_json_format = field.content_type().and_then(JsonFormat::from_content_type);
 
-            csv_content = String::from_utf8(data.to_vec()).map_err(|_| {
-                create_error_response(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_ENCODING",
-                    "File must be UTF-8 encoded",
-                )
-            })?;
+        // Validate file type
+        let kind = match field_json_format.or_else(|| JsonFormat::from_file_name(&file_name)) {
+            Some(format) => UploadKind::Json(format),
+            None => match UploadFormat::from_file_name(&file_name) {
+                Some(format) => UploadKind::Csv(format),
+                None => {
+                    return Err(create_error_response(
+                        StatusCode::BAD_REQUEST,
+                        "INVALID_FILE_TYPE",
+                        "Only CSV files are allowed (.csv, .csv.gz or a .zip of CSV files), or JSON usage events (.json, .ndjson)",
+                    ))
+                }
+            },
+        };
 
-            file_received = true;
-            break;
-        }
+        let source = UploadSource { file_name, kind };
+        return spool_chunks(ChunkSource::Field(Box::new(field)), source, process).await;
     }
 
-    if !file_received {
-        return Err(create_error_response(
-            StatusCode::BAD_REQUEST,
-            "NO_FILE",
-            "No CSV file provided. Please upload a file with field name 'csvFile'",
-        ));
-    }
+    Err(create_error_response(
+        StatusCode::BAD_REQUEST,
+        "NO_FILE",
+        "No CSV file provided. Please upload a file with field name 'csvFile'",
+    ))
+}
 
-    // Parse new CSV content
-    let parser = CsvParser::new();
-    let new_usage_data = parser.parse_csv(&csv_content).map_err(|e| {
+/// Spool the upload to a temporary file, enforcing the upload size limit while it
+/// arrives, then hand it to `process` on a blocking thread. Nothing is parsed or
+/// stored until the client has sent the whole upload, so a slow client never keeps a
+/// store transaction open.
+async fn spool_chunks<T, F>(mut chunks: ChunkSource<'_>, source: UploadSource, process: F) -> Result<T, HandlerError>
+where
+    T: Send + 'static,
+    F: FnOnce(UploadReader, UploadSource) -> Result<T, IngestError> + Send + 'static,
+{
+    let spool_error = |e: std::io::Error| {
         create_error_response(
-            StatusCode::BAD_REQUEST,
-            "CSV_PARSE_ERROR",
-            &e,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL_ERROR",
+            &format!("Error buffering upload: {}", e),
         )
-    })?;
+    };
 
-    // Validate new data
-    let processor = DataProcessor::new();
-    processor.validate_usage_data(&new_usage_data).map_err(|e| {
+    let mut spool = UploadSpool::new().map_err(spool_error)?;
+    while let Some(chunk) = chunks.next_chunk().await.map_err(|e| {
         create_error_response(
             StatusCode::BAD_REQUEST,
-            "DATA_VALIDATION_ERROR",
-            &e,
+            "FILE_READ_ERROR",
+            &format!("Error reading file: {}", e),
         )
-    })?;
+    })? {
+        // Check file size
+        if spool.len() + chunk.len() > MAX_FILE_SIZE {
+            return Err(create_error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "FILE_TOO_LARGE",
+                &format!("File size exceeds maximum allowed size ({} bytes)", MAX_FILE_SIZE),
+            ));
+        }
+        spool.write(&chunk).await.map_err(spool_error)?;
+    }
+    let reader = spool.into_reader().await.map_err(spool_error)?;
 
-    // Merge with existing data using DataProcessor
-    let combined_data = {
-        let existing_data = {
-            let data_store = UPLOADED_DATA.lock().unwrap();
-            data_store.clone()
-        };
-        
-        let merged_data = processor.merge_data(existing_data, new_usage_data.clone());
-        
-        // Update stored data
-        {
-            let mut data_store = UPLOADED_DATA.lock().unwrap();
-            *data_store = merged_data.clone();
+    let result = tokio::task::spawn_blocking(move || process(reader, source))
+        .await
+        .map_err(|e| {
+            create_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL_ERROR",
+                &format!("Upload processing failed: {}", e),
+            )
+        })?;
+
+    result.map_err(IngestError::into_response)
+}
+
+/// Parse, validate and store uploaded records batch by batch. In strict mode nothing is
+/// kept unless the whole upload is valid; in lenient mode invalid rows are skipped, but
+/// at least one row must be accepted.
+/// The upload is recorded as an import under the SHA-256 of its bytes. Since the hash is
+/// only known once the whole file has been read, a re-upload of a file the dataset
+/// already holds is detected at the end and its transaction rolled back.
+fn store_upload(
+    store: &dyn UsageStore,
+    dataset_id: &str,
+    reader: UploadReader,
+    source: &UploadSource,
+    mode: WriteMode,
+    parse_mode: ParseMode,
+    budget_check: &BudgetCheck,
+) -> Result<IngestOutcome, IngestError> {
+    let processor = DataProcessor::new();
+    let mut deduplicator = ImportDeduplicator::default();
+    let mut writer = store.begin_write(dataset_id, mode).map_err(IngestError::Storage)?;
+
+    let import_id = ImportRecord::generate_id();
+    let mut reader = HashingReader::new(reader);
+    let mut offset = 0;
+    let mut skipped_rows = 0;
+    let mut date_range = None;
+    let mut latest_day = None;
+    let report = parse_upload(&mut reader, source, parse_mode, |batch| {
+        processor
+            .validate_usage_batch(&batch, offset)
+            .map_err(IngestError::Validation)?;
+        offset += batch.len();
+        latest_day = latest_day.max(budget_check.latest_day(&batch));
+
+        // Rows an earlier import already stored are skipped rather than written twice.
+        // Stored counts are read inside the write's transaction, so a concurrent append
+        // cannot slip the same rows in between.
+        let batch_len = batch.len();
+        let mut fresh = deduplicator
+            .retain_new(batch, |fingerprints| writer.stored_counts(fingerprints))
+            .map_err(IngestError::Storage)?;
+        skipped_rows += batch_len - fresh.len();
+
+        for usage in &mut fresh {
+            DateRange::include(&mut date_range, &usage.date);
+            usage.import_id = Some(import_id.clone());
         }
-        
-        merged_data
-    };
+        writer.write(&fresh).map_err(IngestError::Storage)
+    })?;
 
-    // Calculate summary for combined data using DataProcessor
-    let summary = processor.calculate_summary(&combined_data);
+    let content_hash = reader
+        .finish()
+        .map_err(|e| IngestError::Parse(format!("Error reading upload: {}", e)))?;
 
-    Ok(Json(json!({
-        "success": true,
-        "message": "CSV file appended successfully",
-        "data": combined_data,
-        "summary": summary,
-        "new_records": new_usage_data.len(),
-        "total_records": combined_data.len()
-    })))
+    if let Some(previous) = find_identical_import(writer.prior_imports(), &content_hash, mode) {
+        return Ok(IngestOutcome::Duplicate {
+            report,
+            previous: previous.clone(),
+        });
+    }
+
+    if report.accepted_rows == 0 {
+        return Err(IngestError::NoValidRows(report));
+    }
+
+    let import = ImportRecord::new(
+        import_id,
+        &source.file_name,
+        &content_hash,
+        report.accepted_rows - skipped_rows,
+        date_range,
+    );
+    // Read before commit so the spend the import adds to is exactly what it was written over
+    let budgets_before = latest_day
+        .map(|day| budget_check.before_import(writer.as_mut(), mode, day))
+        .transpose()
+        .map_err(IngestError::Storage)?;
+    writer
+        .record_import(&import, &deduplicator.skipped())
+        .map_err(IngestError::Storage)?;
+    writer.commit().map_err(IngestError::Storage)?;
+    Ok(IngestOutcome::Imported { report, import, skipped_rows, budgets_before })
 }
 
-fn create_error_response(
-    status: StatusCode,
-    code: &str,
-    message: &str,
-) -> (StatusCode, Json<ErrorResponse>) {
-    (
-        status,
-        Json(ErrorResponse {
-            success: false,
-            error: ErrorDetails {
-                code: code.to_string(),
-                message: message.to_string(),
-                details: None,
-            },
-        }),
-    )
+/// The earlier import that makes storing a file with `content_hash` a no-op: any import
+/// of the same file when appending, or, when replacing, the same file being the only
+/// thing the dataset holds
+fn find_identical_import<'a>(
+    prior_imports: &'a [ImportRecord],
+    content_hash: &str,
+    mode: WriteMode,
+) -> Option<&'a ImportRecord> {
+    match (mode, prior_imports) {
+        (WriteMode::Append, imports) => imports.iter().find(|import| import.content_hash == content_hash),
+        (WriteMode::Replace, [only]) if only.content_hash == content_hash => Some(only),
+        (WriteMode::Replace, _) => None,
+    }
 }
 
+/// Parse every record in the upload, handing them to `on_batch` in bounded batches.
+/// The reports of the CSV files in a zip archive are merged into one.
+fn parse_upload<R, F>(
+    reader: R,
+    source: &UploadSource,
+    parse_mode: ParseMode,
+    mut on_batch: F,
+) -> Result<ParseReport, IngestError>
+where
+    R: Read,
+    F: FnMut(Vec<UsageData>) -> Result<(), IngestError>,
+{
+    let mut failure = None;
+    let mut forward = |batch| {
+        on_batch(batch).map_err(|e| {
+            failure = Some(e);
+            "upload aborted".to_string()
+        })
+    };
+
+    let format = match source.kind {
+        UploadKind::Json(format) => {
+            let result = JsonImporter::with_mode(parse_mode)
+                .parse_reader_with_report(reader, format, DEFAULT_BATCH_SIZE, &mut forward);
+            return match result {
+                Ok(mut report) => {
+                    report.files.push(source.file_name.clone());
+                    Ok(report)
+                }
+                Err(e) => Err(failure.unwrap_or(IngestError::JsonParse(e))),
+            };
+        }
+        UploadKind::Csv(format) => format,
+    };
+
+    let parser = CsvParser::with_mode(parse_mode);
+    let mut report: Option<ParseReport> = None;
+    // Compressed uploads may not expand past what an uncompressed upload may hold
+    let result = for_each_csv(reader, format, &source.file_name, MAX_FILE_SIZE as u64, |name, csv| {
+        let file_report = parser.parse_reader_with_report(csv, DEFAULT_BATCH_SIZE, &mut forward)?;
+
+        match report.as_mut() {
+            Some(combined) => combined.merge(file_report, name),
+            None if format == UploadFormat::Zip => {
+                let mut combined = ParseReport::new(parse_mode, file_report.schema_version, Vec::new());
+                combined.merge(file_report, name);
+                report = Some(combined);
+            }
+            None => {
+                let mut single = file_report;
+                single.files.push(name.to_string());
+                report = Some(single);
+            }
+        }
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        return Err(failure.unwrap_or(match e {
+            UnpackError::Invalid(e) => IngestError::InvalidArchive(e),
+            UnpackError::TooLarge(e) => IngestError::TooLarge(e),
+            UnpackError::Entry(e) => IngestError::Parse(e),
+        }));
+    }
+
+    report.ok_or_else(|| IngestError::InvalidArchive("Upload contains no CSV files".to_string()))
+}
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::handlers::datasets::DatasetId;
+use crate::models::error::{create_error_response, storage_error, HandlerError};
+use crate::models::webhook::{Webhook, WebhookEvent, WebhookEventKind, WebhookRequest};
+use crate::state::AppState;
+
+/// Deliveries listed unless the request asks for another number
+const DEFAULT_DELIVERY_LIMIT: usize = 50;
+const MAX_DELIVERY_LIMIT: usize = 500;
+
+/// Whether any webhook of the dataset wants `kind` events, so work to produce them can
+/// be skipped otherwise
+pub fn has_subscribers(state: &AppState, dataset_id: &str, kind: WebhookEventKind) -> bool {
+    state
+        .store
+        .list_webhooks(dataset_id)
+        .is_ok_and(|webhooks| webhooks.iter().any(|webhook| webhook.events.contains(&kind)))
+}
+
+/// Post `events` to every webhook of the dataset subscribed to them, in the background
+/// so the request that caused them does not wait on retries. Each finished delivery is
+/// added to the dataset's delivery log.
+pub fn notify_webhooks(state: &AppState, dataset_id: &str, events: Vec<WebhookEvent>) {
+    if events.is_empty() {
+        return;
+    }
+    let webhooks = match state.store.list_webhooks(dataset_id) {
+        Ok(webhooks) if !webhooks.is_empty() => webhooks,
+        Ok(_) => return,
+        Err(e) => {
+            tracing::error!("Failed to load webhooks of dataset '{}': {}", dataset_id, e);
+            return;
+        }
+    };
+
+    let store = state.store.clone();
+    let dispatcher = state.webhooks.clone();
+    let dataset_id = dataset_id.to_string();
+    tokio::spawn(async move {
+        for event in &events {
+            for webhook in webhooks.iter().filter(|webhook| webhook.events.contains(&event.event)) {
+                let delivery = dispatcher.deliver(webhook, event).await;
+                if let Err(e) = store.record_delivery(&dataset_id, &delivery) {
+                    tracing::error!("Failed to record webhook delivery: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Number of deliveries to list
+#[derive(Debug, Deserialize)]
+pub struct DeliveryLogQuery {
+    pub limit: Option<usize>,
+}
+
+/// Webhooks of the dataset; secrets are never returned
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+) -> Result<Json<Value>, HandlerError> {
+    let webhooks = state.store.list_webhooks(&dataset_id).map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "webhooks": webhooks
+    })))
+}
+
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Json(request): Json<WebhookRequest>,
+) -> Result<(StatusCode, Json<Value>), HandlerError> {
+    let webhook = Webhook::new(request)
+        .map_err(|e| create_error_response(StatusCode::BAD_REQUEST, "INVALID_WEBHOOK", &e))?;
+    state.store.save_webhook(&dataset_id, &webhook).map_err(storage_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "success": true,
+            "dataset_id": dataset_id,
+            "webhook": webhook
+        })),
+    ))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<Json<Value>, HandlerError> {
+    let webhook_id = params.get("webhook_id").cloned().unwrap_or_default();
+    if !state
+        .store
+        .delete_webhook(&dataset_id, &webhook_id)
+        .map_err(storage_error)?
+    {
+        return Err(create_error_response(
+            StatusCode::NOT_FOUND,
+            "WEBHOOK_NOT_FOUND",
+            &format!("Webhook '{}' does not exist in dataset '{}'", webhook_id, dataset_id),
+        ));
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Webhook '{}' deleted", webhook_id),
+        "dataset_id": dataset_id
+    })))
+}
+
+/// Most recent webhook deliveries of the dataset, newest first
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    DatasetId(dataset_id): DatasetId,
+    Query(options): Query<DeliveryLogQuery>,
+) -> Result<Json<Value>, HandlerError> {
+    let limit = options.limit.unwrap_or(DEFAULT_DELIVERY_LIMIT).min(MAX_DELIVERY_LIMIT);
+    let deliveries = state
+        .store
+        .list_deliveries(&dataset_id, limit)
+        .map_err(storage_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "dataset_id": dataset_id,
+        "deliveries": deliveries
+    })))
+}
@@ -1,5 +1,7 @@
 pub mod handlers;
 pub mod models;
+pub mod routes;
 pub mod services;
+pub mod state;
 pub mod storage;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
@@ -1,28 +1,24 @@
-mod handlers;
-mod models;
-mod services;
-mod storage;
-mod utils;
-
-use axum::{
-    routing::{get, post},
-    Router,
+use cursor_usage_dashboard_backend::{
+    routes, services::pricing, state::AppState, storage, utils::date_utils,
 };
 use std::net::SocketAddr;
-use tower_http::cors::CorsLayer;
 
 #[tokio::main]
 async fn main() {
     // Initialize tracing
     tracing_subscriber::fmt().init();
 
+    // Open the configured store up front so a bad configuration fails at startup
+    let store = storage::open_from_env().unwrap_or_else(|e| panic!("{}", e));
+    let timezone = date_utils::timezone_from_env().unwrap_or_else(|e| panic!("{}", e));
+    let pricing = pricing::catalog_from_env().unwrap_or_else(|e| panic!("{}", e));
+
     // Build our application with routes
-    let app = Router::new()
-        .route("/api/health", get(handlers::health::health_check))
-        .route("/api/upload", post(handlers::upload::upload_csv))
-        .route("/api/upload/append", post(handlers::upload::append_csv))
-        .route("/api/stats/comprehensive", get(handlers::stats::comprehensive_stats))
-        .layer(CorsLayer::permissive());
+    let app = routes::create_router(
+        AppState::new(store)
+            .with_timezone(timezone)
+            .with_pricing(pricing),
+    );
 
     // Run it
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
@@ -30,4 +26,4 @@ async fn main() {
     
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+}
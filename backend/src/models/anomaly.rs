@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// Quantity an anomaly was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMetric {
+    Cost,
+    Tokens,
+}
+
+/// A day whose total is far above the days before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyAnomaly {
+    /// Local day, `YYYY-MM-DD`
+    pub date: String,
+    pub metric: AnomalyMetric,
+    pub value: f64,
+    /// Median of the preceding window
+    pub baseline: f64,
+    /// Median absolute deviation of the preceding window
+    pub mad: f64,
+    /// Value above which the day is flagged
+    pub threshold: f64,
+    /// Distance above the median in scaled MADs
+    pub score: f64,
+    pub reason: String,
+}
+
+/// A single request far above what its model usually uses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestAnomaly {
+    pub date: String,
+    pub model: String,
+    pub kind: String,
+    pub max_mode: bool,
+    pub metric: AnomalyMetric,
+    pub value: f64,
+    /// 99th percentile of the model's requests
+    pub baseline: f64,
+    pub threshold: f64,
+    /// `value` as a multiple of `baseline`
+    pub ratio: f64,
+    pub import_id: Option<String>,
+    pub reason: String,
+}
+
+/// Unusual days and requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnomalyReport {
+    /// Days of history each day is compared with
+    pub window_days: u32,
+    /// Scaled MADs above the median a day must reach
+    pub mad_threshold: f64,
+    /// Multiple of the model's p99 a request must exceed
+    pub request_multiplier: f64,
+    /// Oldest first
+    pub daily: Vec<DailyAnomaly>,
+    /// Most extreme first
+    pub requests: Vec<RequestAnomaly>,
+    /// Flagged requests before `requests` was truncated
+    pub request_count: usize,
+}
@@ -0,0 +1,70 @@
+use crate::models::usage_data::UsageSummary;
+use crate::utils::date_utils::parse_timezone;
+use serde::{Deserialize, Serialize};
+
+/// How a dataset's usage is split into billing cycles. Cursor bills on the
+/// subscription anniversary, so cycles run from one anchor day to the next.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BillingCycleConfig {
+    /// Day of the month cycles start on (1-31); in shorter months the cycle starts on
+    /// the month's last day
+    pub anchor_day: u32,
+    /// IANA timezone of cycle boundaries; the server default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl Default for BillingCycleConfig {
+    fn default() -> Self {
+        Self {
+            anchor_day: 1,
+            timezone: None,
+        }
+    }
+}
+
+impl BillingCycleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=31).contains(&self.anchor_day) {
+            return Err(format!("anchor_day must be between 1 and 31, got {}", self.anchor_day));
+        }
+        if let Some(ref timezone) = self.timezone {
+            parse_timezone(timezone)?;
+        }
+        Ok(())
+    }
+}
+
+/// Usage within one billing cycle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CycleSummary {
+    /// First day of the cycle, `YYYY-MM-DD`
+    pub start: String,
+    /// Last day of the cycle (inclusive); for a cycle-to-date, the last day counted
+    pub end: String,
+    pub record_count: usize,
+    pub total_cost: f64,
+    pub total_tokens: u64,
+    pub total_requests: f64,
+    pub summary: UsageSummary,
+}
+
+/// Current cycle to date against the previous cycle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CycleComparison {
+    /// Day the comparison was made for
+    pub as_of: String,
+    /// Start of the current cycle up to and including `as_of`
+    pub current: CycleSummary,
+    /// Last day of the current cycle
+    pub current_end: String,
+    /// The same number of days at the start of the previous cycle
+    pub previous_to_date: CycleSummary,
+    /// The whole previous cycle
+    pub previous: CycleSummary,
+    /// Percent change of cost, tokens and requests against `previous_to_date`;
+    /// `None` when the previous cycle had nothing to compare with
+    pub cost_change: Option<f64>,
+    pub tokens_change: Option<f64>,
+    pub requests_change: Option<f64>,
+}
@@ -0,0 +1,174 @@
+use crate::models::usage_data::UsageData;
+use crate::storage::UsageQuery;
+use serde::{Deserialize, Serialize};
+
+/// Percentages of a budget reported when spend reaches them, unless the budget sets its own
+pub const DEFAULT_THRESHOLDS: [u32; 3] = [50, 80, 100];
+
+/// Stretch of time a budget's amount covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    /// Monday to Sunday
+    Weekly,
+    /// The dataset's billing cycle
+    Monthly,
+}
+
+impl BudgetPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Weekly => "weekly",
+            BudgetPeriod::Monthly => "monthly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "daily" => Ok(BudgetPeriod::Daily),
+            "weekly" => Ok(BudgetPeriod::Weekly),
+            "monthly" => Ok(BudgetPeriod::Monthly),
+            other => Err(format!(
+                "Unknown budget period '{}'. Expected 'daily', 'weekly' or 'monthly'",
+                other
+            )),
+        }
+    }
+}
+
+/// Records a budget counts; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetScope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl BudgetScope {
+    pub fn matches(&self, usage: &UsageData) -> bool {
+        UsageQuery {
+            model: self.model.clone(),
+            kind: self.kind.clone(),
+            user: self.user.clone(),
+            ..Default::default()
+        }
+        .matches(usage)
+    }
+}
+
+/// A spend limit on a dataset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub id: String,
+    pub name: String,
+    /// Limit per period, in the pricing catalog's currency
+    pub amount: f64,
+    pub period: BudgetPeriod,
+    pub scope: BudgetScope,
+    /// Percentages of `amount` to alert at, ascending
+    pub thresholds: Vec<u32>,
+    pub created_at: String,
+}
+
+impl Budget {
+    /// A new budget from a validated request, with a fresh id
+    pub fn new(request: BudgetRequest) -> Result<Self, String> {
+        let mut budget = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: String::new(),
+            amount: 0.0,
+            period: request.period,
+            scope: BudgetScope::default(),
+            thresholds: Vec::new(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        budget.update(request)?;
+        Ok(budget)
+    }
+
+    /// Replace the definition with `request`, keeping the id and creation time
+    pub fn update(&mut self, request: BudgetRequest) -> Result<(), String> {
+        let name = request.name.trim();
+        if name.is_empty() {
+            return Err("Budget name must not be empty".to_string());
+        }
+        if !request.amount.is_finite() || request.amount <= 0.0 {
+            return Err(format!("Budget amount must be positive, got {}", request.amount));
+        }
+        let mut thresholds = request.thresholds.unwrap_or_else(|| DEFAULT_THRESHOLDS.to_vec());
+        if thresholds.is_empty() || thresholds.iter().any(|&t| t == 0 || t > 1000) {
+            return Err("Budget thresholds must be percentages between 1 and 1000".to_string());
+        }
+        thresholds.sort_unstable();
+        thresholds.dedup();
+
+        let trimmed = |value: Option<String>| {
+            value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+        };
+        self.name = name.to_string();
+        self.amount = request.amount;
+        self.period = request.period;
+        self.scope = BudgetScope {
+            model: trimmed(request.scope.model),
+            kind: trimmed(request.scope.kind),
+            user: trimmed(request.scope.user),
+        };
+        self.thresholds = thresholds;
+        Ok(())
+    }
+}
+
+/// Body of the budget create and update endpoints
+#[derive(Debug, Deserialize)]
+pub struct BudgetRequest {
+    pub name: String,
+    pub amount: f64,
+    pub period: BudgetPeriod,
+    #[serde(default)]
+    pub scope: BudgetScope,
+    /// Defaults to 50, 80 and 100 percent
+    #[serde(default)]
+    pub thresholds: Option<Vec<u32>>,
+}
+
+/// Spend against a budget in the period containing `as_of`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetStatus {
+    pub budget_id: String,
+    pub name: String,
+    pub period: BudgetPeriod,
+    /// First and last day of the period (inclusive), `YYYY-MM-DD`
+    pub period_start: String,
+    pub period_end: String,
+    /// Day spend was counted up to
+    pub as_of: String,
+    pub amount: f64,
+    pub spent: f64,
+    pub percent_used: f64,
+    /// Thresholds `spent` has reached
+    pub thresholds_crossed: Vec<u32>,
+    /// Spend by the end of the period at the period-to-date daily rate
+    pub projected_spend: f64,
+    /// How far `projected_spend` exceeds the budget; zero when it stays within
+    pub projected_overrun: f64,
+}
+
+/// A threshold reached by an import that had not been reached before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetAlert {
+    pub budget_id: String,
+    pub name: String,
+    pub threshold: u32,
+    pub period_start: String,
+    pub period_end: String,
+    pub amount: f64,
+    pub spent: f64,
+    pub percent_used: f64,
+    pub projected_spend: f64,
+    pub projected_overrun: f64,
+}
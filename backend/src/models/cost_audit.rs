@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// Expected (list price) vs. charged cost of one model on one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostAuditGroup {
+    /// Local day, `YYYY-MM-DD`
+    pub date: String,
+    pub model: String,
+    pub record_count: usize,
+    pub expected_cost: f64,
+    pub charged_cost: f64,
+    /// Charged minus expected; positive means billed above list price
+    pub difference: f64,
+}
+
+/// A single record whose charged cost deviates from its list price
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostAuditRow {
+    pub date: String,
+    pub model: String,
+    pub kind: String,
+    pub max_mode: bool,
+    pub total_tokens: u32,
+    pub expected_cost: f64,
+    pub charged_cost: f64,
+    pub difference: f64,
+    /// `difference` relative to the expected cost; `None` when nothing was expected
+    pub relative_difference: Option<f64>,
+}
+
+/// Result of recomputing every record's cost from the pricing catalog
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CostAudit {
+    pub currency: String,
+    /// Relative deviation a row may have before it is flagged
+    pub tolerance: f64,
+    pub expected_total: f64,
+    pub charged_total: f64,
+    pub difference: f64,
+    /// Priced records whose deviation exceeds the tolerance
+    pub flagged_count: usize,
+    /// Per day and model, sorted by day then model
+    pub groups: Vec<CostAuditGroup>,
+    /// The most divergent flagged rows, largest absolute difference first
+    pub top_rows: Vec<CostAuditRow>,
+    /// Records of models missing from the catalog are left out of every total
+    pub unpriced_records: usize,
+    pub unpriced_models: Vec<String>,
+    /// Included and errored records, which are not billed and so are left out of every total
+    pub no_charge_records: usize,
+}
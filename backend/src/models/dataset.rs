@@ -0,0 +1,20 @@
+use crate::models::billing::BillingCycleConfig;
+use serde::{Deserialize, Serialize};
+
+/// Identifier of the dataset used by the unscoped `/api/upload` and `/api/stats` routes
+pub const DEFAULT_DATASET_ID: &str = "default";
+
+/// A named collection of uploaded usage data, e.g. one team member's export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dataset {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub record_count: usize,
+    pub billing_cycle: BillingCycleConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DatasetRequest {
+    pub name: String,
+}
@@ -1,3 +1,4 @@
+use axum::{http::StatusCode, response::Json};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -11,4 +12,31 @@ pub struct ErrorDetails {
     pub code: String,
     pub message: String,
     pub details: Option<serde_json::Value>,
-}
\ No newline at end of file
+}
+
+/// Error half of every handler's result
+pub type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+pub fn create_error_response(
+    status: StatusCode,
+    code: &str,
+    message: &str,
+) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            success: false,
+            error: ErrorDetails {
+                code: code.to_string(),
+                message: message.to_string(),
+                details: None,
+            },
+        }),
+    )
+}
+
+/// 500 response for a failed store call; the cause is logged as well
+pub fn storage_error(e: String) -> HandlerError {
+    tracing::error!("Failed to access storage: {}", e);
+    create_error_response(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", &e)
+}
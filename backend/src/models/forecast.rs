@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// Forecasting method
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastMethod {
+    /// Least-squares trend line over the daily history
+    Linear,
+    /// Simple exponential smoothing of the daily level
+    ExponentialSmoothing,
+}
+
+/// Projected period total with its confidence band
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectedTotal {
+    /// Actual to date plus the forecast for the remaining days
+    pub projected: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Forecast of one remaining day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    /// Local day, `YYYY-MM-DD`
+    pub date: String,
+    pub cost: f64,
+    pub tokens: f64,
+}
+
+/// Projection of one method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MethodForecast {
+    pub method: ForecastMethod,
+    pub cost: ProjectedTotal,
+    pub tokens: ProjectedTotal,
+    pub daily: Vec<ForecastPoint>,
+}
+
+/// Spend and token projection through the end of a period
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Forecast {
+    pub period_start: String,
+    pub period_end: String,
+    /// Day the forecast is made on, the latest day with data unless one was asked for;
+    /// the forecast covers the days after it
+    pub as_of: String,
+    pub days_elapsed: u32,
+    pub days_remaining: u32,
+    /// Days of history the methods were fitted on
+    pub history_days: u32,
+    /// Two-sided confidence level of the bands, e.g. 0.95
+    pub confidence: f64,
+    pub actual_cost: f64,
+    pub actual_tokens: u64,
+    pub forecasts: Vec<MethodForecast>,
+}
@@ -0,0 +1,36 @@
+use crate::models::usage_data::DateRange;
+use serde::{Deserialize, Serialize};
+
+/// One upload stored into a dataset, identified by the SHA-256 of the uploaded bytes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportRecord {
+    pub id: String,
+    pub file_name: String,
+    /// Hex-encoded SHA-256 of the file exactly as uploaded (before decompression)
+    pub content_hash: String,
+    pub created_at: String,
+    /// Rows the import stored
+    pub record_count: usize,
+    /// Earliest and latest record date; `None` when no rows were stored
+    pub date_range: Option<DateRange>,
+}
+
+impl ImportRecord {
+    /// A fresh random import id, assigned before the import's rows are written so they
+    /// can be tagged with it
+    pub fn generate_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    /// An import of `file_name` timestamped now
+    pub fn new(id: String, file_name: &str, content_hash: &str, record_count: usize, date_range: Option<DateRange>) -> Self {
+        Self {
+            id,
+            file_name: file_name.to_string(),
+            content_hash: content_hash.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            record_count,
+            date_range,
+        }
+    }
+}
@@ -1,3 +1,15 @@
 pub mod usage_data;
 pub mod stats;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod dataset;
+pub mod parse_report;
+pub mod import;
+pub mod cost_audit;
+pub mod simulation;
+pub mod forecast;
+pub mod billing;
+pub mod budget;
+pub mod webhook;
+pub mod anomaly;
+pub mod ranking;
+pub mod session;
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Maximum number of rejected rows listed individually in a `ParseReport`
+pub const MAX_REPORTED_ROW_ERRORS: usize = 1000;
+
+/// How strictly an upload is parsed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseMode {
+    /// Abort the whole upload on the first invalid row
+    #[default]
+    Strict,
+    /// Import every valid row and report the rejected ones
+    Lenient,
+}
+
+/// Export layout detected from the header row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchemaVersion {
+    /// The original ten-column export in its original order
+    #[serde(rename = "v1")]
+    V1,
+    /// Team-admin export: the v1 columns plus user and/or request count columns
+    #[serde(rename = "v2")]
+    V2,
+    /// Any other layout whose required columns could be matched by name
+    #[serde(rename = "custom")]
+    Custom,
+    /// JSON usage events (a JSON array or newline-delimited JSON) rather than CSV
+    #[serde(rename = "json")]
+    Json,
+}
+
+/// A single row rejected while parsing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowError {
+    /// CSV file within an archive upload the row came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Line of the file; for JSON arrays the 1-based position of the record
+    pub line: usize,
+    pub column: Option<String>,
+    pub value: Option<String>,
+    pub reason: String,
+}
+
+impl RowError {
+    /// A cell whose raw value could not be interpreted
+    pub fn invalid_value(line: usize, column: &str, value: &str, reason: &str) -> Self {
+        Self {
+            file: None,
+            line,
+            column: Some(column.to_string()),
+            value: Some(value.to_string()),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// A problem with the row as a whole, optionally attributed to one column
+    pub fn invalid_row(line: usize, column: Option<&str>, reason: String) -> Self {
+        Self {
+            file: None,
+            line,
+            column: column.map(str::to_string),
+            value: None,
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.column, &self.value) {
+            (Some(column), Some(value)) => write!(
+                f,
+                "Invalid {} value '{}' on line {}. {}",
+                column, value, self.line, self.reason
+            ),
+            _ => write!(f, "{} on line {}", self.reason, self.line),
+        }
+    }
+}
+
+/// Outcome of parsing an upload: how many rows were accepted and which were rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseReport {
+    pub mode: ParseMode,
+    pub schema_version: SchemaVersion,
+    /// Header columns that were not recognized and therefore skipped
+    pub ignored_columns: Vec<String>,
+    /// CSV files parsed; more than one for zip archives
+    pub files: Vec<String>,
+    pub accepted_rows: usize,
+    pub rejected_rows: usize,
+    /// The first `MAX_REPORTED_ROW_ERRORS` rejected rows
+    pub errors: Vec<RowError>,
+    /// Whether more rows were rejected than are listed in `errors`
+    pub truncated: bool,
+}
+
+impl ParseReport {
+    pub fn new(mode: ParseMode, schema_version: SchemaVersion, ignored_columns: Vec<String>) -> Self {
+        Self {
+            mode,
+            schema_version,
+            ignored_columns,
+            files: Vec::new(),
+            accepted_rows: 0,
+            rejected_rows: 0,
+            errors: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// Fold the report of one file of a multi-file upload into this one, tagging its
+    /// errors with the file name. Files with differing layouts report `Custom`.
+    pub fn merge(&mut self, other: ParseReport, file: &str) {
+        if self.schema_version != other.schema_version {
+            self.schema_version = SchemaVersion::Custom;
+        }
+        for column in other.ignored_columns {
+            if !self.ignored_columns.contains(&column) {
+                self.ignored_columns.push(column);
+            }
+        }
+        self.files.push(file.to_string());
+        self.accepted_rows += other.accepted_rows;
+        self.truncated |= other.truncated;
+
+        // Rows the other report counted but did not list still count as rejected
+        self.rejected_rows += other.rejected_rows - other.errors.len();
+        for mut error in other.errors {
+            error.file = Some(file.to_string());
+            self.reject(error);
+        }
+    }
+
+    pub fn reject(&mut self, error: RowError) {
+        self.rejected_rows += 1;
+        if self.errors.len() < MAX_REPORTED_ROW_ERRORS {
+            self.errors.push(error);
+        } else {
+            self.truncated = true;
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Quantity requests, hours and days are ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingMetric {
+    Cost,
+    Tokens,
+}
+
+impl RankingMetric {
+    /// Parse the `metric` query value: `cost` or `tokens`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "cost" => Ok(Self::Cost),
+            "tokens" => Ok(Self::Tokens),
+            _ => Err(format!("Unknown metric '{}'. Expected 'cost' or 'tokens'", value)),
+        }
+    }
+}
+
+/// One of the costliest or largest requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedRequest {
+    pub date: String,
+    pub model: String,
+    pub kind: String,
+    pub max_mode: bool,
+    /// Team-admin exports only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub total_tokens: u32,
+    pub cost: f64,
+    pub import_id: Option<String>,
+}
+
+/// Totals of one of the costliest or largest hours or days
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedPeriod {
+    /// Local start of the hour or day, with offset
+    pub start: String,
+    pub record_count: usize,
+    pub requests: f64,
+    pub total_tokens: u64,
+    pub cost: f64,
+}
+
+/// Top requests, hours and days, highest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rankings {
+    pub metric: RankingMetric,
+    pub limit: usize,
+    pub requests: Vec<RankedRequest>,
+    pub hours: Vec<RankedPeriod>,
+    pub days: Vec<RankedPeriod>,
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Consecutive requests of one user, each less than the idle gap after the one before
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageSession {
+    /// Timestamps of the first and last request, in the requested timezone
+    pub start: String,
+    pub end: String,
+    pub duration_minutes: f64,
+    /// Team-admin exports only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Rows in the session
+    pub record_count: usize,
+    /// Sum of the rows' request counts
+    pub requests: f64,
+    pub total_tokens: u64,
+    pub total_cost: f64,
+    /// Model that used the most tokens in the session
+    pub dominant_model: String,
+    /// Distinct models used
+    pub model_count: usize,
+}
+
+/// Sessions found in a dataset
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionReport {
+    pub idle_gap_minutes: u32,
+    pub session_count: usize,
+    pub average_duration_minutes: f64,
+    pub average_cost: f64,
+    /// Oldest first
+    pub sessions: Vec<UsageSession>,
+    /// Costliest first
+    pub most_expensive: Vec<UsageSession>,
+}
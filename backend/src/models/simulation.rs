@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// Actual vs. projected cost of one model's records on one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationGroup {
+    /// Local day, `YYYY-MM-DD`
+    pub date: String,
+    /// Model the records actually used
+    pub model: String,
+    /// Model the records are projected onto; `None` when left unchanged
+    pub projected_model: Option<String>,
+    pub record_count: usize,
+    pub actual_cost: f64,
+    pub projected_cost: f64,
+    /// Projected minus actual; negative means the substitution would have saved money
+    pub difference: f64,
+}
+
+/// Projected cost of the stored usage had some models been replaced by others
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Simulation {
+    pub currency: String,
+    pub actual_cost: f64,
+    pub projected_cost: f64,
+    pub difference: f64,
+    /// Records moved onto another model
+    pub substituted_records: usize,
+    /// Records due for substitution whose own model has no price; kept at actual cost
+    pub unpriced_records: usize,
+    pub unpriced_models: Vec<String>,
+    /// Per day and actual model, sorted by day then model
+    pub groups: Vec<SimulationGroup>,
+}
@@ -13,7 +13,11 @@ pub struct PeakUsageStats {
 pub struct CostEfficiencyStats {
     pub cost_per_token: f64,
     pub cost_per_request: f64,
+    /// Uncached minus cached list price, in `currency`
     pub cache_savings: f64,
+    pub currency: String,
+    /// Models missing from the pricing catalog, left out of `cache_savings`
+    pub unpriced_models: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,4 +39,60 @@ pub struct ComprehensiveStats {
     pub peak_usage: PeakUsageStats,
     pub cost_efficiency: CostEfficiencyStats,
     pub usage_trends: UsageTrendStats,
-}
\ No newline at end of file
+}
+
+/// Width of the buckets of a time series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Granularity {
+    #[serde(rename = "daily")]
+    Daily,
+    #[serde(rename = "hourly")]
+    Hourly,
+    #[serde(rename = "10min")]
+    TenMinutes,
+}
+
+impl Granularity {
+    /// Parse the `granularity` query value: `daily`, `hourly` or `10min`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "daily" | "day" => Ok(Self::Daily),
+            "hourly" | "hour" => Ok(Self::Hourly),
+            "10min" | "10m" => Ok(Self::TenMinutes),
+            _ => Err(format!(
+                "Unknown granularity '{}'. Expected 'daily', 'hourly' or '10min'",
+                value
+            )),
+        }
+    }
+}
+
+/// Usage within one bucket, starting at `start` (local time with offset)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeSeriesBucket {
+    pub start: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub total_tokens: u64,
+    pub cost: f64,
+    pub requests: f64,
+}
+
+/// Buckets of a single model, aligned with the overall series
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelTimeSeries {
+    pub model: String,
+    pub buckets: Vec<TimeSeriesBucket>,
+}
+
+/// Usage bucketed over time; every bucket between the first and last record is present
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeSeries {
+    pub granularity: Granularity,
+    pub timezone: String,
+    pub buckets: Vec<TimeSeriesBucket>,
+    /// Present when the series was split by model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_model: Option<Vec<ModelTimeSeries>>,
+}
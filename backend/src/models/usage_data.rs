@@ -13,6 +13,36 @@ pub struct UsageData {
     pub output_tokens: u32,
     pub total_tokens: u32,
     pub cost: f64,
+    /// User email from team-admin exports; absent in personal exports
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Request count from exports that include it; one request per row otherwise
+    #[serde(default = "default_requests")]
+    pub requests: f64,
+    /// Import that stored the row; absent for rows stored before imports were tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub import_id: Option<String>,
+}
+
+fn default_requests() -> f64 {
+    1.0
+}
+
+impl UsageData {
+    /// Whether the row is an errored request, e.g. kind "Errored, No Charge"
+    pub fn is_errored(&self) -> bool {
+        self.kind.to_lowercase().starts_with("errored")
+    }
+
+    /// Whether the row was covered by the plan allowance ("Included") and so not billed
+    pub fn is_included(&self) -> bool {
+        self.kind.trim().eq_ignore_ascii_case("included")
+    }
+
+    /// Whether the row was billed outside the plan allowance ("Usage-based")
+    pub fn is_usage_based(&self) -> bool {
+        self.kind.to_lowercase().replace(['-', '_'], " ") == "usage based"
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,6 +53,9 @@ pub struct UsageSummary {
     pub most_used_model: String,
     pub date_range: DateRange,
     pub model_breakdown: Vec<ModelStats>,
+    pub kind_breakdown: Vec<GroupStats>,
+    /// Empty unless the data carries user emails
+    pub user_breakdown: Vec<GroupStats>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,8 +69,41 @@ pub struct ModelStats {
     pub cache_savings: f64, // monetary savings from cache usage
 }
 
+/// Totals for one value of a grouping key such as kind or user
 #[derive(Debug, Serialize, Deserialize)]
+pub struct GroupStats {
+    pub key: String,
+    pub record_count: u32,
+    pub requests: f64,
+    pub total_tokens: u64,
+    pub total_cost: f64,
+    pub errored_count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DateRange {
     pub start: String,
     pub end: String,
+}
+
+impl DateRange {
+    /// Widen `range` to include `date`, starting a range if there is none yet
+    pub fn include(range: &mut Option<DateRange>, date: &str) {
+        match range {
+            Some(range) => {
+                if date < range.start.as_str() {
+                    range.start = date.to_string();
+                }
+                if date > range.end.as_str() {
+                    range.end = date.to_string();
+                }
+            }
+            None => {
+                *range = Some(DateRange {
+                    start: date.to_string(),
+                    end: date.to_string(),
+                })
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,201 @@
+use crate::models::anomaly::{DailyAnomaly, RequestAnomaly};
+use crate::models::budget::BudgetAlert;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+/// Events a webhook can be notified of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// An import took a budget past one of its thresholds
+    BudgetThreshold,
+    /// An import added usage flagged as unusual
+    Anomaly,
+}
+
+impl WebhookEventKind {
+    pub const ALL: [WebhookEventKind; 2] = [WebhookEventKind::BudgetThreshold, WebhookEventKind::Anomaly];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::BudgetThreshold => "budget_threshold",
+            WebhookEventKind::Anomaly => "anomaly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("Unknown webhook event '{}'", value))
+    }
+}
+
+/// Body shape posted to a webhook
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The event as JSON
+    #[default]
+    Json,
+    /// A Slack incoming-webhook message (`{"text": ...}`)
+    Slack,
+}
+
+impl WebhookFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookFormat::Json => "json",
+            WebhookFormat::Slack => "slack",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "json" => Ok(WebhookFormat::Json),
+            "slack" => Ok(WebhookFormat::Slack),
+            other => Err(format!("Unknown webhook format '{}'. Expected 'json' or 'slack'", other)),
+        }
+    }
+}
+
+/// Secrets are write-only: responses only show whether one is set
+fn mask_secret<S: Serializer>(secret: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(secret.is_some())
+}
+
+/// A URL notified of a dataset's events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Key of the HMAC-SHA256 signature sent with every delivery; unsigned when unset
+    #[serde(rename = "signed", serialize_with = "mask_secret")]
+    pub secret: Option<String>,
+    /// Events delivered to the URL
+    pub events: Vec<WebhookEventKind>,
+    pub created_at: String,
+}
+
+impl Webhook {
+    /// A new webhook from a request, with a fresh id
+    pub fn new(request: WebhookRequest) -> Result<Self, String> {
+        let url = request.url.trim();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("Webhook URL must start with http:// or https://, got '{}'", url));
+        }
+        let mut events = request.events.unwrap_or_else(|| WebhookEventKind::ALL.to_vec());
+        if events.is_empty() {
+            return Err("A webhook must subscribe to at least one event".to_string());
+        }
+        events.sort_by_key(|kind| kind.as_str());
+        events.dedup();
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            format: request.format.unwrap_or_default(),
+            secret: request.secret.filter(|secret| !secret.is_empty()),
+            events,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+/// Body of the webhook create endpoint
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    #[serde(default)]
+    pub format: Option<WebhookFormat>,
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Defaults to every event
+    #[serde(default)]
+    pub events: Option<Vec<WebhookEventKind>>,
+}
+
+/// Something that happened to a dataset, as posted in the JSON format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    pub event: WebhookEventKind,
+    pub dataset_id: String,
+    pub created_at: String,
+    /// One-line description, used as the Slack message
+    pub summary: String,
+    pub data: Value,
+}
+
+impl WebhookEvent {
+    pub fn new(event: WebhookEventKind, dataset_id: &str, summary: String, data: Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            event,
+            dataset_id: dataset_id.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            summary,
+            data,
+        }
+    }
+
+    /// Event for a budget threshold crossed by an import; amounts are in `currency`
+    pub fn budget_threshold(dataset_id: &str, alert: &BudgetAlert, currency: &str) -> Self {
+        let summary = format!(
+            "Budget '{}' in dataset '{}' reached {}%: {:.2} of {:.2} {} spent for {} to {} (projected {:.2})",
+            alert.name,
+            dataset_id,
+            alert.threshold,
+            alert.spent,
+            alert.amount,
+            currency,
+            alert.period_start,
+            alert.period_end,
+            alert.projected_spend,
+        );
+        Self::new(
+            WebhookEventKind::BudgetThreshold,
+            dataset_id,
+            summary,
+            serde_json::to_value(alert).unwrap_or(Value::Null),
+        )
+    }
+
+    /// Event for an unusual day an import stored records on
+    pub fn daily_anomaly(dataset_id: &str, anomaly: &DailyAnomaly) -> Self {
+        Self::new(
+            WebhookEventKind::Anomaly,
+            dataset_id,
+            format!("Unusual usage in dataset '{}' on {}: {}", dataset_id, anomaly.date, anomaly.reason),
+            serde_json::json!({ "scope": "day", "anomaly": anomaly }),
+        )
+    }
+
+    /// Event for an unusual request stored by an import
+    pub fn request_anomaly(dataset_id: &str, anomaly: &RequestAnomaly) -> Self {
+        Self::new(
+            WebhookEventKind::Anomaly,
+            dataset_id,
+            format!("Unusual request in dataset '{}' at {}: {}", dataset_id, anomaly.date, anomaly.reason),
+            serde_json::json!({ "scope": "request", "anomaly": anomaly }),
+        )
+    }
+}
+
+/// Outcome of posting one event to one webhook, after any retries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event_id: String,
+    pub event: WebhookEventKind,
+    pub url: String,
+    pub success: bool,
+    pub attempts: u32,
+    /// HTTP status of the last attempt; `None` when no response was received
+    pub status_code: Option<u16>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub created_at: String,
+}
@@ -0,0 +1,76 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{delete, get, patch, post, put},
+    Router,
+};
+use tower_http::cors::CorsLayer;
+
+use crate::handlers;
+use crate::state::AppState;
+
+/// Routes that operate on a single dataset; mounted both at `/api` (default dataset)
+/// and at `/api/datasets/:dataset_id`
+fn dataset_routes() -> Router<AppState> {
+    // Upload size is enforced while streaming (see MAX_FILE_SIZE), not by the body limit
+    Router::new()
+        .route(
+            "/upload",
+            post(handlers::upload::upload_csv).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/upload/append",
+            post(handlers::upload::append_csv).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/upload/validate",
+            post(handlers::upload::validate_csv).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/imports", get(handlers::imports::list_imports))
+        .route("/imports/:import_id", delete(handlers::imports::delete_import))
+        .route(
+            "/billing/config",
+            get(handlers::billing::get_billing_config).put(handlers::billing::update_billing_config),
+        )
+        .route("/billing/cycles", get(handlers::billing::list_cycles))
+        .route("/billing/current", get(handlers::billing::current_cycle))
+        .route(
+            "/budgets",
+            get(handlers::budgets::list_budgets).post(handlers::budgets::create_budget),
+        )
+        .route(
+            "/budgets/:budget_id",
+            put(handlers::budgets::update_budget).delete(handlers::budgets::delete_budget),
+        )
+        .route(
+            "/webhooks",
+            get(handlers::webhooks::list_webhooks).post(handlers::webhooks::create_webhook),
+        )
+        .route("/webhooks/deliveries", get(handlers::webhooks::list_deliveries))
+        .route("/webhooks/:webhook_id", delete(handlers::webhooks::delete_webhook))
+        .route("/stats/comprehensive", get(handlers::stats::comprehensive_stats))
+        .route("/stats/timeseries", get(handlers::stats::timeseries_stats))
+        .route("/stats/cost-audit", get(handlers::stats::cost_audit))
+        .route("/stats/forecast", get(handlers::stats::forecast))
+        .route("/stats/anomalies", get(handlers::stats::anomalies))
+        .route("/stats/sessions", get(handlers::stats::sessions))
+        .route("/stats/top", get(handlers::stats::top_usage))
+        .route("/stats/simulate", post(handlers::stats::simulate_substitutions))
+}
+
+/// Build the API router with every endpoint wired to the given state
+pub fn create_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/health", get(handlers::health::health_check))
+        .route(
+            "/api/datasets",
+            get(handlers::datasets::list_datasets).post(handlers::datasets::create_dataset),
+        )
+        .route(
+            "/api/datasets/:dataset_id",
+            patch(handlers::datasets::rename_dataset).delete(handlers::datasets::delete_dataset),
+        )
+        .nest("/api", dataset_routes())
+        .nest("/api/datasets/:dataset_id", dataset_routes())
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+}
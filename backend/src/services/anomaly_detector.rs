@@ -0,0 +1,318 @@
+use crate::models::{
+    anomaly::{AnomalyMetric, AnomalyReport, DailyAnomaly, RequestAnomaly},
+    usage_data::UsageData,
+};
+use crate::utils::date_utils::to_local;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub const DEFAULT_WINDOW_DAYS: u32 = 14;
+pub const DEFAULT_MAD_THRESHOLD: f64 = 3.5;
+pub const DEFAULT_REQUEST_MULTIPLIER: f64 = 2.0;
+
+/// Days of history a day needs before it is judged
+const MIN_HISTORY_DAYS: usize = 7;
+/// Requests a model needs before its p99 is trusted
+const MIN_MODEL_REQUESTS: usize = 20;
+/// Makes the MAD comparable to a standard deviation for normally distributed data
+const MAD_SCALE: f64 = 1.4826;
+
+/// How far from normal usage has to be before it is flagged
+#[derive(Debug, Clone, Copy)]
+pub struct AnomalyThresholds {
+    /// Days before each day that form its baseline
+    pub window_days: u32,
+    /// Scaled MADs above the baseline median
+    pub mad_threshold: f64,
+    /// Multiple of the model's p99
+    pub request_multiplier: f64,
+}
+
+impl Default for AnomalyThresholds {
+    fn default() -> Self {
+        Self {
+            window_days: DEFAULT_WINDOW_DAYS,
+            mad_threshold: DEFAULT_MAD_THRESHOLD,
+            request_multiplier: DEFAULT_REQUEST_MULTIPLIER,
+        }
+    }
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let len = sorted.len();
+    if len.is_multiple_of(2) {
+        (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+    } else {
+        sorted[len / 2]
+    }
+}
+
+fn sorted(values: impl IntoIterator<Item = f64>) -> Vec<f64> {
+    let mut values: Vec<f64> = values.into_iter().collect();
+    values.sort_unstable_by(f64::total_cmp);
+    values
+}
+
+/// Nearest-rank 99th percentile, as in `StatsCalculator`
+fn p99(sorted: &[f64]) -> f64 {
+    let index = ((sorted.len() as f64) * 0.99).ceil() as usize - 1;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+fn metric_name(metric: AnomalyMetric) -> &'static str {
+    match metric {
+        AnomalyMetric::Cost => "Cost",
+        AnomalyMetric::Tokens => "Tokens",
+    }
+}
+
+fn metric_value(usage: &UsageData, metric: AnomalyMetric) -> f64 {
+    match metric {
+        AnomalyMetric::Cost => usage.cost,
+        AnomalyMetric::Tokens => usage.total_tokens as f64,
+    }
+}
+
+/// Flags days whose cost or tokens exceed a rolling median + MAD threshold, and
+/// requests far above their model's 99th percentile. Days are local to `timezone`.
+#[derive(Default)]
+pub struct AnomalyDetector {
+    timezone: Tz,
+}
+
+impl AnomalyDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detector whose days are local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self { timezone }
+    }
+
+    fn local_day(&self, usage: &UsageData) -> Option<NaiveDate> {
+        to_local(&usage.date, self.timezone).map(|local| local.date_naive())
+    }
+
+    /// Anomalies in `data`, listing at most `limit` requests
+    pub fn detect(&self, data: &[UsageData], thresholds: &AnomalyThresholds, limit: usize) -> AnomalyReport {
+        let mut daily = self.daily_anomalies(data, thresholds, AnomalyMetric::Cost);
+        daily.extend(self.daily_anomalies(data, thresholds, AnomalyMetric::Tokens));
+        daily.sort_by(|a, b| a.date.cmp(&b.date));
+
+        let mut requests = Self::request_anomalies(data, thresholds, AnomalyMetric::Cost);
+        requests.extend(Self::request_anomalies(data, thresholds, AnomalyMetric::Tokens));
+        requests.sort_by(|a, b| b.ratio.total_cmp(&a.ratio));
+        let request_count = requests.len();
+        requests.truncate(limit);
+
+        AnomalyReport {
+            window_days: thresholds.window_days,
+            mad_threshold: thresholds.mad_threshold,
+            request_multiplier: thresholds.request_multiplier,
+            daily,
+            requests,
+            request_count,
+        }
+    }
+
+    /// Only the anomalies an import is part of: its own requests, and the days it
+    /// stored records on
+    pub fn restrict_to_import(&self, mut report: AnomalyReport, data: &[UsageData], import_id: &str) -> AnomalyReport {
+        let import_days: HashSet<String> = data
+            .iter()
+            .filter(|usage| usage.import_id.as_deref() == Some(import_id))
+            .filter_map(|usage| self.local_day(usage))
+            .map(|day| day.to_string())
+            .collect();
+        report.daily.retain(|anomaly| import_days.contains(&anomaly.date));
+        report
+            .requests
+            .retain(|anomaly| anomaly.import_id.as_deref() == Some(import_id));
+        report.request_count = report.requests.len();
+        report
+    }
+
+    fn daily_anomalies(&self, data: &[UsageData], thresholds: &AnomalyThresholds, metric: AnomalyMetric) -> Vec<DailyAnomaly> {
+        let mut totals: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+        for usage in data {
+            if let Some(day) = self.local_day(usage) {
+                *totals.entry(day).or_default() += metric_value(usage, metric);
+            }
+        }
+        let (Some(&first), Some(&last)) = (totals.keys().next(), totals.keys().next_back()) else {
+            return Vec::new();
+        };
+
+        // Zero-filled so idle days count towards the baseline
+        let days: Vec<(NaiveDate, f64)> = first
+            .iter_days()
+            .take_while(|day| *day <= last)
+            .map(|day| (day, totals.get(&day).copied().unwrap_or(0.0)))
+            .collect();
+
+        let window = thresholds.window_days.max(1) as usize;
+        let mut anomalies = Vec::new();
+        for (index, &(day, value)) in days.iter().enumerate().skip(MIN_HISTORY_DAYS) {
+            let history = &days[index.saturating_sub(window)..index];
+            let values = sorted(history.iter().map(|(_, value)| *value));
+            let baseline = median(&values);
+            let mad = median(&sorted(values.iter().map(|value| (value - baseline).abs())));
+            let spread = MAD_SCALE * mad;
+            // A window without variation gives no scale to judge the day by
+            if spread <= 0.0 {
+                continue;
+            }
+            let threshold = baseline + thresholds.mad_threshold * spread;
+            if value > threshold {
+                anomalies.push(DailyAnomaly {
+                    date: day.to_string(),
+                    metric,
+                    value,
+                    baseline,
+                    mad,
+                    threshold,
+                    score: (value - baseline) / spread,
+                    reason: format!(
+                        "{} {:.2} is above the {}-day median {:.2} + {} scaled MADs ({:.2})",
+                        metric_name(metric),
+                        value,
+                        history.len(),
+                        baseline,
+                        thresholds.mad_threshold,
+                        threshold,
+                    ),
+                });
+            }
+        }
+        anomalies
+    }
+
+    fn request_anomalies(data: &[UsageData], thresholds: &AnomalyThresholds, metric: AnomalyMetric) -> Vec<RequestAnomaly> {
+        let mut by_model: HashMap<&str, Vec<&UsageData>> = HashMap::new();
+        for usage in data {
+            by_model.entry(usage.model.as_str()).or_default().push(usage);
+        }
+
+        let mut anomalies = Vec::new();
+        for (model, requests) in by_model {
+            if requests.len() < MIN_MODEL_REQUESTS {
+                continue;
+            }
+            let baseline = p99(&sorted(requests.iter().map(|usage| metric_value(usage, metric))));
+            if baseline <= 0.0 {
+                continue;
+            }
+            let threshold = thresholds.request_multiplier * baseline;
+            for usage in requests {
+                let value = metric_value(usage, metric);
+                if value > threshold {
+                    anomalies.push(RequestAnomaly {
+                        date: usage.date.clone(),
+                        model: model.to_string(),
+                        kind: usage.kind.clone(),
+                        max_mode: usage.max_mode,
+                        metric,
+                        value,
+                        baseline,
+                        threshold,
+                        ratio: value / baseline,
+                        import_id: usage.import_id.clone(),
+                        reason: format!(
+                            "{} {:.2} is {:.1}x the p99 of {} requests ({:.2})",
+                            metric_name(metric),
+                            value,
+                            value / baseline,
+                            model,
+                            baseline,
+                        ),
+                    });
+                }
+            }
+        }
+        anomalies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, cost: f64, total_tokens: u32) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: total_tokens,
+            cache_read: 0,
+            output_tokens: 0,
+            total_tokens,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_day_above_rolling_median_and_mad_is_flagged() {
+        // Ten ordinary days around 1.00, then a 10.00 day
+        let costs = [1.0, 1.2, 0.8, 1.1, 0.9, 1.0, 1.3, 0.7, 1.0, 1.1, 10.0];
+        let data: Vec<UsageData> = costs
+            .iter()
+            .enumerate()
+            .map(|(i, cost)| create_test_record(&format!("2024-05-{:02}T12:00:00Z", i + 1), "auto", *cost, 1000))
+            .collect();
+        let report = AnomalyDetector::new().detect(&data, &AnomalyThresholds::default(), 10);
+
+        // Tokens are constant, so only cost can be flagged
+        assert_eq!(report.daily.len(), 1);
+        let anomaly = &report.daily[0];
+        assert_eq!(anomaly.date, "2024-05-11");
+        assert_eq!(anomaly.metric, AnomalyMetric::Cost);
+        assert!((anomaly.baseline - 1.0).abs() < 1e-9);
+        assert!((anomaly.mad - 0.1).abs() < 1e-9);
+        assert!(anomaly.value > anomaly.threshold);
+        assert!(anomaly.reason.contains("10-day median"));
+    }
+
+    #[test]
+    fn test_request_far_above_model_p99_is_flagged() {
+        let mut data: Vec<UsageData> = (0..99)
+            .map(|i| create_test_record(&format!("2024-05-01T10:{:02}:00Z", i % 60), "gpt-4", 1.0 + (i % 5) as f64 * 0.1, 1000))
+            .collect();
+        data.push(create_test_record("2024-05-01T11:00:00Z", "gpt-4", 20.0, 1000));
+        // Too few requests for a trustworthy p99
+        data.push(create_test_record("2024-05-01T11:00:00Z", "o1", 500.0, 1000));
+
+        let report = AnomalyDetector::new().detect(&data, &AnomalyThresholds::default(), 10);
+        assert_eq!(report.request_count, 1);
+        let anomaly = &report.requests[0];
+        assert_eq!(anomaly.model, "gpt-4");
+        assert_eq!(anomaly.metric, AnomalyMetric::Cost);
+        assert_eq!(anomaly.value, 20.0);
+        assert!((anomaly.baseline - 1.4).abs() < 1e-9);
+        assert!(anomaly.ratio > 14.0);
+    }
+
+    #[test]
+    fn test_restrict_to_import() {
+        let costs = [1.0, 1.2, 0.8, 1.1, 0.9, 1.0, 1.3, 0.7, 10.0];
+        let mut data: Vec<UsageData> = costs
+            .iter()
+            .enumerate()
+            .map(|(i, cost)| create_test_record(&format!("2024-05-{:02}T12:00:00Z", i + 1), "auto", *cost, 1000))
+            .collect();
+        data[8].import_id = Some("new".to_string());
+
+        let detector = AnomalyDetector::new();
+        let report = detector.detect(&data, &AnomalyThresholds::default(), 10);
+        assert_eq!(report.daily.len(), 1);
+
+        assert_eq!(detector.restrict_to_import(report.clone(), &data, "new").daily.len(), 1);
+        assert!(detector.restrict_to_import(report, &data, "old").daily.is_empty());
+    }
+}
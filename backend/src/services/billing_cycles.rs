@@ -0,0 +1,269 @@
+use crate::models::{
+    billing::{BillingCycleConfig, CycleComparison, CycleSummary},
+    usage_data::UsageData,
+};
+use crate::services::data_processor::DataProcessor;
+use crate::utils::date_utils::{parse_timezone, to_local};
+use chrono::{Datelike, Months, NaiveDate};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// A billing cycle's first and last day (inclusive), in the cycle timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BillingPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// Splits usage into billing cycles starting on an anchor day of the month, with
+/// day boundaries at midnight in `timezone`
+#[derive(Debug, Clone, Copy)]
+pub struct BillingCycles {
+    anchor_day: u32,
+    timezone: Tz,
+}
+
+impl Default for BillingCycles {
+    /// Calendar months in UTC
+    fn default() -> Self {
+        Self {
+            anchor_day: 1,
+            timezone: Tz::UTC,
+        }
+    }
+}
+
+impl BillingCycles {
+    /// Cycles starting on `anchor_day` (clamped to 1-31) in `timezone`
+    pub fn new(anchor_day: u32, timezone: Tz) -> Self {
+        Self {
+            anchor_day: anchor_day.clamp(1, 31),
+            timezone,
+        }
+    }
+
+    /// Cycles of a dataset's configuration; `default_timezone` applies when the
+    /// configuration names none
+    pub fn from_config(config: &BillingCycleConfig, default_timezone: Tz) -> Result<Self, String> {
+        let timezone = match config.timezone {
+            Some(ref name) => parse_timezone(name)?,
+            None => default_timezone,
+        };
+        Ok(Self::new(config.anchor_day, timezone))
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// The anchor day of a month, or its last day when the month is shorter
+    fn cycle_start_in(&self, year: i32, month: u32) -> Option<NaiveDate> {
+        (1..=self.anchor_day)
+            .rev()
+            .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+    }
+
+    /// The cycle that `day` falls in
+    pub fn cycle_containing(&self, day: NaiveDate) -> BillingPeriod {
+        let this_month = self.cycle_start_in(day.year(), day.month());
+        let start = match this_month {
+            Some(start) if start <= day => start,
+            _ => day
+                .checked_sub_months(Months::new(1))
+                .and_then(|previous| self.cycle_start_in(previous.year(), previous.month()))
+                .unwrap_or(day),
+        };
+        let end = start
+            .checked_add_months(Months::new(1))
+            .and_then(|next| self.cycle_start_in(next.year(), next.month()))
+            .and_then(|next_start| next_start.pred_opt())
+            .unwrap_or(start);
+        BillingPeriod { start, end }
+    }
+
+    /// Local day of the latest dated record. Unless a day is asked for, it is the day
+    /// the current cycle, forecasts and budgets are taken as of, so historical exports
+    /// land in the cycle they describe.
+    pub fn latest_day(&self, data: &[UsageData]) -> Option<NaiveDate> {
+        data.iter().filter_map(|usage| self.local_day(usage)).max()
+    }
+
+    /// Local day of a record; `None` when its date cannot be parsed
+    fn local_day(&self, usage: &UsageData) -> Option<NaiveDate> {
+        to_local(&usage.date, self.timezone).map(|local| local.date_naive())
+    }
+
+    /// Summaries of every cycle holding data, oldest first. Records with unparseable
+    /// dates are left out.
+    pub fn summarize(&self, data: &[UsageData], processor: &DataProcessor) -> Vec<CycleSummary> {
+        let mut cycles: BTreeMap<NaiveDate, (BillingPeriod, Vec<UsageData>)> = BTreeMap::new();
+        for usage in data {
+            if let Some(day) = self.local_day(usage) {
+                let period = self.cycle_containing(day);
+                cycles
+                    .entry(period.start)
+                    .or_insert_with(|| (period, Vec::new()))
+                    .1
+                    .push(usage.clone());
+            }
+        }
+
+        cycles
+            .into_values()
+            .map(|(period, records)| summarize_period(period, &records, processor))
+            .collect()
+    }
+
+    /// Cycle-to-date as of `as_of` against the same span of the previous cycle and
+    /// against the whole previous cycle
+    pub fn compare(&self, data: &[UsageData], as_of: NaiveDate, processor: &DataProcessor) -> CycleComparison {
+        let current = self.cycle_containing(as_of);
+        let previous = current
+            .start
+            .pred_opt()
+            .map_or(current, |day| self.cycle_containing(day));
+        let elapsed = as_of - current.start;
+        let previous_to_date = BillingPeriod {
+            start: previous.start,
+            end: (previous.start + elapsed).min(previous.end),
+        };
+
+        let within = |period: BillingPeriod| -> Vec<UsageData> {
+            data.iter()
+                .filter(|usage| {
+                    self.local_day(usage)
+                        .is_some_and(|day| period.start <= day && day <= period.end)
+                })
+                .cloned()
+                .collect()
+        };
+        let to_date = BillingPeriod { start: current.start, end: as_of };
+        let current_summary = summarize_period(to_date, &within(to_date), processor);
+        let previous_to_date_summary = summarize_period(previous_to_date, &within(previous_to_date), processor);
+        let previous_summary = summarize_period(previous, &within(previous), processor);
+
+        CycleComparison {
+            as_of: as_of.to_string(),
+            cost_change: percent_change(current_summary.total_cost, previous_to_date_summary.total_cost),
+            tokens_change: percent_change(
+                current_summary.total_tokens as f64,
+                previous_to_date_summary.total_tokens as f64,
+            ),
+            requests_change: percent_change(
+                current_summary.total_requests,
+                previous_to_date_summary.total_requests,
+            ),
+            current: current_summary,
+            current_end: current.end.to_string(),
+            previous_to_date: previous_to_date_summary,
+            previous: previous_summary,
+        }
+    }
+}
+
+fn summarize_period(period: BillingPeriod, records: &[UsageData], processor: &DataProcessor) -> CycleSummary {
+    CycleSummary {
+        start: period.start.to_string(),
+        end: period.end.to_string(),
+        record_count: records.len(),
+        total_cost: records.iter().map(|usage| usage.cost).sum(),
+        total_tokens: records.iter().map(|usage| usage.total_tokens as u64).sum(),
+        total_requests: records.iter().map(|usage| usage.requests).sum(),
+        summary: processor.calculate_summary(records),
+    }
+}
+
+fn percent_change(current: f64, previous: f64) -> Option<f64> {
+    (previous > 0.0).then(|| (current - previous) / previous * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: "auto".to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens: 250,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_cycles_start_on_the_anchor_day() {
+        let cycles = BillingCycles::new(15, Tz::UTC);
+        assert_eq!(cycles.cycle_containing(day("2024-03-15")), BillingPeriod {
+            start: day("2024-03-15"),
+            end: day("2024-04-14"),
+        });
+        assert_eq!(cycles.cycle_containing(day("2024-03-14")).start, day("2024-02-15"));
+
+        // Anchored on the 31st: short months start their cycle on their last day
+        let cycles = BillingCycles::new(31, Tz::UTC);
+        assert_eq!(cycles.cycle_containing(day("2024-02-29")), BillingPeriod {
+            start: day("2024-02-29"),
+            end: day("2024-03-30"),
+        });
+        assert_eq!(cycles.cycle_containing(day("2024-02-28")).start, day("2024-01-31"));
+
+        let calendar = BillingCycles::default();
+        assert_eq!(calendar.cycle_containing(day("2024-12-31")), BillingPeriod {
+            start: day("2024-12-01"),
+            end: day("2024-12-31"),
+        });
+    }
+
+    #[test]
+    fn test_summarize_uses_cycle_timezone() {
+        // 20:00Z on the 9th is already the 10th in Tokyo
+        let data = vec![
+            create_test_record("2024-03-09T20:00:00Z", 1.0),
+            create_test_record("2024-03-20T10:00:00Z", 2.0),
+        ];
+        let processor = DataProcessor::new();
+
+        let utc = BillingCycles::new(10, Tz::UTC).summarize(&data, &processor);
+        assert_eq!(utc.len(), 2);
+        assert_eq!(utc[0].start, "2024-02-10");
+
+        let tokyo = BillingCycles::new(10, chrono_tz::Asia::Tokyo).summarize(&data, &processor);
+        assert_eq!(tokyo.len(), 1);
+        assert_eq!(tokyo[0].start, "2024-03-10");
+        assert_eq!(tokyo[0].record_count, 2);
+        assert_eq!(tokyo[0].total_cost, 3.0);
+        assert_eq!(tokyo[0].summary.total_cost, 3.0);
+    }
+
+    #[test]
+    fn test_compare_with_previous_cycle() {
+        let data = vec![
+            create_test_record("2024-01-05T10:00:00Z", 1.0),
+            create_test_record("2024-01-20T10:00:00Z", 4.0),
+            create_test_record("2024-02-03T10:00:00Z", 3.0),
+        ];
+        let comparison = BillingCycles::default().compare(&data, day("2024-02-10"), &DataProcessor::new());
+
+        assert_eq!(comparison.current.start, "2024-02-01");
+        assert_eq!(comparison.current.end, "2024-02-10");
+        assert_eq!(comparison.current_end, "2024-02-29");
+        assert_eq!(comparison.previous_to_date.end, "2024-01-10");
+        assert_eq!(comparison.previous_to_date.total_cost, 1.0);
+        assert_eq!(comparison.previous.total_cost, 5.0);
+        assert_eq!(comparison.cost_change, Some(200.0));
+        assert_eq!(comparison.tokens_change, Some(0.0));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::models::{
+    budget::{Budget, BudgetAlert, BudgetPeriod, BudgetStatus},
+    usage_data::UsageData,
+};
+use crate::services::billing_cycles::{BillingCycles, BillingPeriod};
+use crate::utils::date_utils::to_local;
+use chrono::{Datelike, Days, NaiveDate};
+use chrono_tz::Tz;
+
+/// Measures spend against budgets, with days local to the billing cycles' timezone
+/// and monthly budgets following the billing cycle
+#[derive(Default)]
+pub struct BudgetEvaluator {
+    cycles: BillingCycles,
+}
+
+impl BudgetEvaluator {
+    /// Evaluator over calendar months in UTC
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluator over a dataset's billing cycles
+    pub fn with_billing_cycles(cycles: BillingCycles) -> Self {
+        Self { cycles }
+    }
+
+    /// Local day of the latest dated record; budgets are evaluated as of it by default
+    pub fn latest_day(&self, data: &[UsageData]) -> Option<NaiveDate> {
+        self.cycles.latest_day(data)
+    }
+
+    /// Timezone the evaluator's days are local to
+    pub fn timezone(&self) -> Tz {
+        self.cycles.timezone()
+    }
+
+    fn local_day(&self, usage: &UsageData) -> Option<NaiveDate> {
+        to_local(&usage.date, self.cycles.timezone()).map(|local| local.date_naive())
+    }
+
+    /// The period of `period` kind that contains `day`
+    pub fn period_containing(&self, period: BudgetPeriod, day: NaiveDate) -> BillingPeriod {
+        match period {
+            BudgetPeriod::Daily => BillingPeriod { start: day, end: day },
+            BudgetPeriod::Weekly => {
+                let start = day
+                    .checked_sub_days(Days::new(day.weekday().num_days_from_monday() as u64))
+                    .unwrap_or(day);
+                BillingPeriod {
+                    start,
+                    end: start.checked_add_days(Days::new(6)).unwrap_or(start),
+                }
+            }
+            BudgetPeriod::Monthly => self.cycles.cycle_containing(day),
+        }
+    }
+
+    /// Spend against `budget` from the start of its period through `as_of`
+    pub fn evaluate(&self, budget: &Budget, data: &[UsageData], as_of: NaiveDate) -> BudgetStatus {
+        let period = self.period_containing(budget.period, as_of);
+        let spent: f64 = data
+            .iter()
+            .filter(|usage| budget.scope.matches(usage))
+            .filter(|usage| {
+                self.local_day(usage)
+                    .is_some_and(|day| period.start <= day && day <= as_of)
+            })
+            .map(|usage| usage.cost)
+            .sum();
+
+        let percent_used = spent / budget.amount * 100.0;
+        let days_elapsed = ((as_of - period.start).num_days() + 1) as f64;
+        let days_total = ((period.end - period.start).num_days() + 1) as f64;
+        let projected_spend = spent / days_elapsed * days_total;
+
+        BudgetStatus {
+            budget_id: budget.id.clone(),
+            name: budget.name.clone(),
+            period: budget.period,
+            period_start: period.start.to_string(),
+            period_end: period.end.to_string(),
+            as_of: as_of.to_string(),
+            amount: budget.amount,
+            spent,
+            percent_used,
+            thresholds_crossed: budget
+                .thresholds
+                .iter()
+                .copied()
+                .filter(|&threshold| percent_used >= threshold as f64)
+                .collect(),
+            projected_spend,
+            projected_overrun: (projected_spend - budget.amount).max(0.0),
+        }
+    }
+
+    pub fn evaluate_all(&self, budgets: &[Budget], data: &[UsageData], as_of: NaiveDate) -> Vec<BudgetStatus> {
+        budgets
+            .iter()
+            .map(|budget| self.evaluate(budget, data, as_of))
+            .collect()
+    }
+}
+
+/// Thresholds reached in `after` that the same budget and period had not reached in
+/// `before`, e.g. the statuses on either side of an import
+pub fn new_alerts(before: &[BudgetStatus], after: &[BudgetStatus]) -> Vec<BudgetAlert> {
+    let mut alerts = Vec::new();
+    for status in after {
+        let previous = before.iter().find(|previous| {
+            previous.budget_id == status.budget_id && previous.period_start == status.period_start
+        });
+        for &threshold in &status.thresholds_crossed {
+            if previous.is_some_and(|previous| previous.thresholds_crossed.contains(&threshold)) {
+                continue;
+            }
+            alerts.push(BudgetAlert {
+                budget_id: status.budget_id.clone(),
+                name: status.name.clone(),
+                threshold,
+                period_start: status.period_start.clone(),
+                period_end: status.period_end.clone(),
+                amount: status.amount,
+                spent: status.spent,
+                percent_used: status.percent_used,
+                projected_spend: status.projected_spend,
+                projected_overrun: status.projected_overrun,
+            });
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::budget::{BudgetRequest, BudgetScope};
+    use chrono_tz::Tz;
+
+    fn create_test_record(date: &str, model: &str, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: 100,
+            cache_read: 0,
+            output_tokens: 0,
+            total_tokens: 100,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    fn create_test_budget(amount: f64, period: BudgetPeriod, model: Option<&str>) -> Budget {
+        Budget::new(BudgetRequest {
+            name: "Test".to_string(),
+            amount,
+            period,
+            scope: BudgetScope {
+                model: model.map(str::to_string),
+                ..Default::default()
+            },
+            thresholds: None,
+        })
+        .unwrap()
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_monthly_budget_follows_billing_cycle_and_scope() {
+        let data = vec![
+            create_test_record("2024-03-14T10:00:00Z", "gpt-4", 50.0),
+            create_test_record("2024-03-15T10:00:00Z", "gpt-4", 30.0),
+            create_test_record("2024-03-16T10:00:00Z", "gpt-4", 30.0),
+            create_test_record("2024-03-16T11:00:00Z", "auto", 100.0),
+        ];
+        let evaluator = BudgetEvaluator::with_billing_cycles(BillingCycles::new(15, Tz::UTC));
+        let budget = create_test_budget(100.0, BudgetPeriod::Monthly, Some("gpt-4"));
+        let status = evaluator.evaluate(&budget, &data, day("2024-03-16"));
+
+        assert_eq!(status.period_start, "2024-03-15");
+        assert_eq!(status.period_end, "2024-04-14");
+        assert_eq!(status.spent, 60.0);
+        assert_eq!(status.thresholds_crossed, vec![50]);
+        // 30 a day over the cycle's 31 days
+        assert!((status.projected_spend - 930.0).abs() < 1e-9);
+        assert!((status.projected_overrun - 830.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weekly_and_daily_periods() {
+        let evaluator = BudgetEvaluator::new();
+        // 2024-03-13 is a Wednesday
+        let week = evaluator.period_containing(BudgetPeriod::Weekly, day("2024-03-13"));
+        assert_eq!((week.start, week.end), (day("2024-03-11"), day("2024-03-17")));
+
+        let data = vec![
+            create_test_record("2024-03-12T10:00:00Z", "auto", 4.0),
+            create_test_record("2024-03-13T10:00:00Z", "auto", 6.0),
+        ];
+        let budget = create_test_budget(5.0, BudgetPeriod::Daily, None);
+        let status = evaluator.evaluate(&budget, &data, evaluator.latest_day(&data).unwrap());
+        assert_eq!(status.spent, 6.0);
+        assert_eq!(status.thresholds_crossed, vec![50, 80, 100]);
+        assert_eq!(status.projected_overrun, 1.0);
+    }
+
+    #[test]
+    fn test_new_alerts_only_report_newly_crossed_thresholds() {
+        let evaluator = BudgetEvaluator::new();
+        let budget = create_test_budget(100.0, BudgetPeriod::Monthly, None);
+        let mut data = vec![create_test_record("2024-03-01T10:00:00Z", "auto", 60.0)];
+        let before = evaluator.evaluate_all(std::slice::from_ref(&budget), &data, day("2024-03-02"));
+
+        data.push(create_test_record("2024-03-02T10:00:00Z", "auto", 25.0));
+        let after = evaluator.evaluate_all(std::slice::from_ref(&budget), &data, day("2024-03-02"));
+
+        let alerts = new_alerts(&before, &after);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].threshold, 80);
+        assert_eq!(alerts[0].spent, 85.0);
+
+        // Without an earlier status every reached threshold is new
+        assert_eq!(new_alerts(&[], &after).len(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::models::{
+    cost_audit::{CostAudit, CostAuditGroup, CostAuditRow},
+    usage_data::UsageData,
+};
+use crate::services::pricing::PricingCatalog;
+use crate::utils::date_utils::local_day;
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Relative deviation tolerated before a row is flagged
+pub const DEFAULT_TOLERANCE: f64 = 0.05;
+
+/// Absolute deviation always tolerated; exports round costs to the cent
+const ROUNDING_TOLERANCE: f64 = 0.01;
+
+/// Recomputes what each record should cost at list price and compares it with the
+/// reported cost, grouping by model and local day in `timezone`
+pub struct CostAuditor {
+    timezone: Tz,
+    pricing: Arc<PricingCatalog>,
+}
+
+impl Default for CostAuditor {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            pricing: PricingCatalog::builtin(),
+        }
+    }
+}
+
+impl CostAuditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Auditor whose days are local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            ..Self::default()
+        }
+    }
+
+    /// Price records with `pricing` instead of the built-in catalog
+    pub fn with_pricing(mut self, pricing: Arc<PricingCatalog>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Audit `data`, flagging rows that deviate from list price by more than
+    /// `tolerance` (relative) and listing at most `limit` of them. Included and errored
+    /// rows are billed nothing whatever their list price, so they are only counted.
+    pub fn audit(&self, data: &[UsageData], tolerance: f64, limit: usize) -> CostAudit {
+        let mut groups: BTreeMap<(String, &str), CostAuditGroup> = BTreeMap::new();
+        let mut flagged = Vec::new();
+        let mut unpriced_records = 0;
+        let mut unpriced_models = BTreeSet::new();
+        let mut no_charge_records = 0;
+
+        for usage in data {
+            if usage.is_included() || usage.is_errored() {
+                no_charge_records += 1;
+                continue;
+            }
+            let Some(price) = self.pricing.price_for(&usage.model) else {
+                unpriced_records += 1;
+                unpriced_models.insert(usage.model.as_str());
+                continue;
+            };
+            let expected_cost = price.cost(usage);
+            let difference = usage.cost - expected_cost;
+
+            let date = local_day(&usage.date, self.timezone);
+            let group = groups
+                .entry((date.clone(), usage.model.as_str()))
+                .or_insert_with(|| CostAuditGroup {
+                    date,
+                    model: usage.model.clone(),
+                    record_count: 0,
+                    expected_cost: 0.0,
+                    charged_cost: 0.0,
+                    difference: 0.0,
+                });
+            group.record_count += 1;
+            group.expected_cost += expected_cost;
+            group.charged_cost += usage.cost;
+            group.difference += difference;
+
+            if Self::deviates(expected_cost, usage.cost, tolerance) {
+                flagged.push(CostAuditRow {
+                    date: usage.date.clone(),
+                    model: usage.model.clone(),
+                    kind: usage.kind.clone(),
+                    max_mode: usage.max_mode,
+                    total_tokens: usage.total_tokens,
+                    expected_cost,
+                    charged_cost: usage.cost,
+                    difference,
+                    relative_difference: (expected_cost > 0.0).then(|| difference / expected_cost),
+                });
+            }
+        }
+
+        let groups: Vec<CostAuditGroup> = groups.into_values().collect();
+        let expected_total: f64 = groups.iter().map(|group| group.expected_cost).sum();
+        let charged_total: f64 = groups.iter().map(|group| group.charged_cost).sum();
+
+        let flagged_count = flagged.len();
+        flagged.sort_by(|a, b| b.difference.abs().total_cmp(&a.difference.abs()));
+        flagged.truncate(limit);
+
+        CostAudit {
+            currency: self.pricing.currency().to_string(),
+            tolerance,
+            expected_total,
+            charged_total,
+            difference: charged_total - expected_total,
+            flagged_count,
+            groups,
+            top_rows: flagged,
+            unpriced_records,
+            unpriced_models: unpriced_models.into_iter().map(str::to_string).collect(),
+            no_charge_records,
+        }
+    }
+
+    /// Whether a charge is off by more than both the rounding slack and `tolerance`
+    fn deviates(expected: f64, charged: f64, tolerance: f64) -> bool {
+        let difference = (charged - expected).abs();
+        difference > ROUNDING_TOLERANCE && difference > tolerance * expected.max(charged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: 1_000_000,
+            cache_read: 0,
+            output_tokens: 100_000,
+            total_tokens: 1_100_000,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    fn create_test_auditor() -> CostAuditor {
+        let pricing = PricingCatalog::from_toml_str(
+            "[models.sonnet]\ninput = 3.0\ncache_write = 3.75\ncache_read = 0.3\noutput = 15.0",
+        )
+        .unwrap();
+        CostAuditor::new().with_pricing(Arc::new(pricing))
+    }
+
+    #[test]
+    fn test_audit_groups_by_day_and_model() {
+        // Expected cost of each row: 1M * 3.0 + 0.1M * 15.0 per million = 4.50
+        let data = vec![
+            create_test_record("2024-01-01T10:00:00Z", "sonnet", 4.50),
+            create_test_record("2024-01-01T12:00:00Z", "sonnet", 4.52),
+            create_test_record("2024-01-02T10:00:00Z", "sonnet", 9.00),
+            create_test_record("2024-01-02T11:00:00Z", "unknown", 1.00),
+        ];
+        let audit = create_test_auditor().audit(&data, DEFAULT_TOLERANCE, 10);
+
+        assert_eq!(audit.groups.len(), 2);
+        assert_eq!(audit.groups[0].date, "2024-01-01");
+        assert_eq!(audit.groups[0].record_count, 2);
+        assert!((audit.groups[0].expected_cost - 9.0).abs() < 1e-9);
+        assert!((audit.expected_total - 13.5).abs() < 1e-9);
+        assert!((audit.difference - 4.52).abs() < 1e-9);
+
+        // Only the doubled charge exceeds 5%; the 2-cent difference is within tolerance
+        assert_eq!(audit.flagged_count, 1);
+        assert_eq!(audit.top_rows[0].date, "2024-01-02T10:00:00Z");
+        assert!((audit.top_rows[0].relative_difference.unwrap() - 1.0).abs() < 1e-9);
+
+        assert_eq!(audit.unpriced_records, 1);
+        assert_eq!(audit.unpriced_models, vec!["unknown"]);
+    }
+
+    #[test]
+    fn test_top_rows_are_most_divergent_first() {
+        let data = vec![
+            create_test_record("2024-01-01T10:00:00Z", "sonnet", 5.50),
+            create_test_record("2024-01-01T11:00:00Z", "sonnet", 0.00),
+            create_test_record("2024-01-01T12:00:00Z", "sonnet", 6.00),
+        ];
+        let audit = create_test_auditor().audit(&data, DEFAULT_TOLERANCE, 2);
+
+        assert_eq!(audit.flagged_count, 3);
+        let differences: Vec<f64> = audit.top_rows.iter().map(|row| row.difference).collect();
+        assert_eq!(differences.len(), 2);
+        assert!((differences[0] + 4.5).abs() < 1e-9);
+        assert!((differences[1] - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_no_charge_kinds_are_not_flagged() {
+        let mut included = create_test_record("2024-01-01T10:00:00Z", "sonnet", 0.00);
+        included.kind = "Included".to_string();
+        let mut errored = create_test_record("2024-01-01T11:00:00Z", "sonnet", 0.00);
+        errored.kind = "Errored, No Charge".to_string();
+        let data = vec![
+            included,
+            errored,
+            create_test_record("2024-01-01T12:00:00Z", "sonnet", 4.50),
+        ];
+        let audit = create_test_auditor().audit(&data, DEFAULT_TOLERANCE, 10);
+
+        assert_eq!(audit.flagged_count, 0);
+        assert_eq!(audit.no_charge_records, 2);
+        assert_eq!(audit.groups[0].record_count, 1);
+        assert!((audit.expected_total - 4.5).abs() < 1e-9);
+        assert!(audit.difference.abs() < 1e-9);
+    }
+}
@@ -1,46 +1,123 @@
+use crate::models::parse_report::{ParseMode, ParseReport, RowError};
 use crate::models::usage_data::UsageData;
+use crate::services::csv_schema::{Column, ColumnMap};
 use csv::ReaderBuilder;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 
-pub struct CsvParser;
+/// Number of records handed to the caller at a time when streaming
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+#[derive(Default)]
+pub struct CsvParser {
+    mode: ParseMode,
+}
 
 impl CsvParser {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Parser that skips invalid rows instead of failing, recording them in the report
+    pub fn lenient() -> Self {
+        Self::with_mode(ParseMode::Lenient)
+    }
+
+    pub fn with_mode(mode: ParseMode) -> Self {
+        Self { mode }
+    }
+
+    pub fn mode(&self) -> ParseMode {
+        self.mode
     }
 
     pub fn parse_csv(&self, csv_content: &str) -> Result<Vec<UsageData>, String> {
-        // First validate the CSV format
-        self.validate_csv_format(csv_content)?;
+        let mut usage_data = Vec::new();
+        self.parse_reader(csv_content.as_bytes(), DEFAULT_BATCH_SIZE, |batch| {
+            usage_data.extend(batch);
+            Ok(())
+        })?;
 
+        Ok(usage_data)
+    }
+
+    /// Parse CSV data from any reader, handing records to `on_batch` in batches of at
+    /// most `batch_size` so memory use does not grow with the size of the input.
+    /// Returns the number of records parsed; an error from `on_batch` aborts parsing.
+    pub fn parse_reader<R, F>(&self, reader: R, batch_size: usize, on_batch: F) -> Result<usize, String>
+    where
+        R: Read,
+        F: FnMut(Vec<UsageData>) -> Result<(), String>,
+    {
+        self.parse_reader_with_report(reader, batch_size, on_batch)
+            .map(|report| report.accepted_rows)
+    }
+
+    /// Like `parse_reader`, but returns a report of accepted and rejected rows.
+    /// In strict mode the first invalid row aborts parsing; in lenient mode invalid
+    /// rows are skipped and listed in the report. Header problems are always fatal.
+    pub fn parse_reader_with_report<R, F>(
+        &self,
+        reader: R,
+        batch_size: usize,
+        mut on_batch: F,
+    ) -> Result<ParseReport, String>
+    where
+        R: Read,
+        F: FnMut(Vec<UsageData>) -> Result<(), String>,
+    {
+        let batch_size = batch_size.max(1);
         let mut reader = ReaderBuilder::new()
             .has_headers(true)
-            .from_reader(Cursor::new(csv_content));
+            .flexible(true)
+            .from_reader(reader);
 
-        let mut usage_data = Vec::new();
+        let headers = reader.headers().map_err(|e| {
+            format!("Error reading CSV headers: {}", e)
+        })?;
+        let columns = self.validate_headers(headers)?;
 
-        for (line_num, result) in reader.records().enumerate() {
-            let record = result.map_err(|e| {
-                format!("Error reading CSV line {}: {}", line_num + 2, e)
-            })?;
+        let mut report = ParseReport::new(self.mode, columns.schema_version, columns.ignored_columns.clone());
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut raw_record = csv::ByteRecord::new();
+        let mut line_num = 1;
 
-            if record.len() != 10 {
-                return Err(format!(
-                    "Invalid number of columns on line {}. Expected 10, found {}",
-                    line_num + 2,
-                    record.len()
-                ));
+        loop {
+            let has_record = reader.read_byte_record(&mut raw_record).map_err(|e| {
+                format!("Error reading CSV line {}: {}", line_num + 1, e)
+            })?;
+            if !has_record {
+                break;
+            }
+            line_num = raw_record
+                .position()
+                .map(|pos| pos.line() as usize)
+                .unwrap_or(line_num + 1);
+
+            match self.parse_raw_record(&raw_record, &columns, line_num) {
+                Ok(usage_entry) => {
+                    batch.push(usage_entry);
+                    report.accepted_rows += 1;
+                }
+                Err(row_error) => match self.mode {
+                    ParseMode::Strict => return Err(row_error.to_string()),
+                    ParseMode::Lenient => report.reject(row_error),
+                },
             }
 
-            let usage_entry = self.parse_record(&record, line_num + 2)?;
-            usage_data.push(usage_entry);
+            if batch.len() >= batch_size {
+                on_batch(std::mem::replace(&mut batch, Vec::with_capacity(batch_size)))?;
+            }
         }
 
-        if usage_data.is_empty() {
+        if report.accepted_rows + report.rejected_rows == 0 {
             return Err("CSV file contains no data rows".to_string());
         }
 
-        Ok(usage_data)
+        if !batch.is_empty() {
+            on_batch(batch)?;
+        }
+
+        Ok(report)
     }
 
     pub fn validate_csv_format(&self, csv_content: &str) -> Result<(), String> {
@@ -57,71 +134,91 @@ impl CsvParser {
             format!("Error reading CSV headers: {}", e)
         })?;
 
-        let expected_headers = [
-            "Date",
-            "Kind", 
-            "Model",
-            "Max Mode",
-            "Input (w/ Cache Write)",
-            "Input (w/o Cache Write)",
-            "Cache Read",
-            "Output Tokens",
-            "Total Tokens",
-            "Cost"
-        ];
-
-        if headers.len() != expected_headers.len() {
-            return Err(format!(
-                "Invalid number of columns in header. Expected {}, found {}",
-                expected_headers.len(),
-                headers.len()
-            ));
+        self.validate_headers(headers).map(|_| ())
+    }
+
+    /// Map the header row to known columns by name
+    fn validate_headers(&self, headers: &csv::StringRecord) -> Result<ColumnMap, String> {
+        if headers.iter().all(|header| header.trim().is_empty()) {
+            return Err("CSV file is empty".to_string());
         }
 
-        for (i, expected) in expected_headers.iter().enumerate() {
-            let actual = headers.get(i).unwrap_or("");
-            if actual != *expected {
-                return Err(format!(
-                    "Invalid header at column {}. Expected '{}', found '{}'",
-                    i + 1,
-                    expected,
-                    actual
-                ));
-            }
+        ColumnMap::from_headers(headers)
+    }
+
+    fn parse_raw_record(
+        &self,
+        raw_record: &csv::ByteRecord,
+        columns: &ColumnMap,
+        line_num: usize,
+    ) -> Result<UsageData, RowError> {
+        let record = csv::StringRecord::from_byte_record(raw_record.clone()).map_err(|e| {
+            RowError::invalid_row(line_num, None, format!("Invalid UTF-8 in field {}", e.utf8_error().field() + 1))
+        })?;
+
+        if record.len() != columns.width() {
+            return Err(RowError::invalid_row(
+                line_num,
+                None,
+                format!("Invalid number of columns. Expected {}, found {}", columns.width(), record.len()),
+            ));
         }
 
-        Ok(())
+        self.parse_record(&record, columns, line_num)
     }
 
-    fn parse_record(&self, record: &csv::StringRecord, line_num: usize) -> Result<UsageData, String> {
-        let date = record.get(0).unwrap_or("").trim_matches('"').to_string();
+    fn parse_record(
+        &self,
+        record: &csv::StringRecord,
+        columns: &ColumnMap,
+        line_num: usize,
+    ) -> Result<UsageData, RowError> {
+        let date = columns.get(record, Column::Date).to_string();
         if date.is_empty() {
-            return Err(format!("Empty date field on line {}", line_num));
+            return Err(RowError::invalid_row(line_num, Some(Column::Date.name()), "Empty date field".to_string()));
         }
 
-        let kind = record.get(1).unwrap_or("").trim_matches('"').to_string();
-        let model = record.get(2).unwrap_or("").trim_matches('"').to_string();
+        let kind = columns.get(record, Column::Kind).to_string();
+        let model = columns.get(record, Column::Model).to_string();
         
-        let max_mode_str = record.get(3).unwrap_or("").trim_matches('"');
+        let max_mode_str = columns.get(record, Column::MaxMode);
         let max_mode = match max_mode_str.to_lowercase().as_str() {
             "yes" | "true" | "1" => true,
             "no" | "false" | "0" => false,
-            _ => return Err(format!("Invalid Max Mode value '{}' on line {}. Expected 'Yes' or 'No'", max_mode_str, line_num)),
+            _ => return Err(RowError::invalid_value(line_num, Column::MaxMode.name(), max_mode_str, "Expected 'Yes' or 'No'")),
         };
 
-        let input_with_cache = self.parse_u32_field(record.get(4), "Input (w/ Cache Write)", line_num)?;
-        let input_without_cache = self.parse_u32_field(record.get(5), "Input (w/o Cache Write)", line_num)?;
-        let cache_read = self.parse_u32_field(record.get(6), "Cache Read", line_num)?;
-        let output_tokens = self.parse_u32_field(record.get(7), "Output Tokens", line_num)?;
-        let total_tokens = self.parse_u32_field(record.get(8), "Total Tokens", line_num)?;
-        let cost = self.parse_f64_field(record.get(9), "Cost", line_num)?;
+        // Errored requests are not charged; their token and cost cells are often blank
+        let blank_as_zero = is_no_charge_kind(&kind);
+        let input_with_cache = self.parse_u32_field(record, columns, Column::InputWithCache, blank_as_zero, line_num)?;
+        let input_without_cache = self.parse_u32_field(record, columns, Column::InputWithoutCache, blank_as_zero, line_num)?;
+        let cache_read = self.parse_u32_field(record, columns, Column::CacheRead, blank_as_zero, line_num)?;
+        let output_tokens = self.parse_u32_field(record, columns, Column::OutputTokens, blank_as_zero, line_num)?;
+        let total_tokens = self.parse_u32_field(record, columns, Column::TotalTokens, blank_as_zero, line_num)?;
+        let cost = self.parse_f64_field(record, columns, Column::Cost, blank_as_zero, line_num)?;
+
+        let user = Some(columns.get(record, Column::User))
+            .filter(|user| !user.trim().is_empty())
+            .map(|user| user.trim().to_string());
+        let requests = if columns.has(Column::Requests) {
+            self.parse_f64_field(record, columns, Column::Requests, blank_as_zero, line_num)?
+        } else {
+            1.0
+        };
 
         // Validate data consistency
-        let calculated_total = input_with_cache + input_without_cache + cache_read + output_tokens;
-        if calculated_total != total_tokens {
-            return Err(format!(
-                "Token calculation mismatch on line {}. Sum of individual tokens ({}) doesn't match Total Tokens ({})",
-                line_num, calculated_total, total_tokens
+        let calculated_total = input_with_cache as u64
+            + input_without_cache as u64
+            + cache_read as u64
+            + output_tokens as u64;
+        if calculated_total != total_tokens as u64 {
+            return Err(RowError::invalid_row(
+                line_num,
+                Some(Column::TotalTokens.name()),
+                format!(
+                    "Token calculation mismatch. Sum of individual tokens ({}) doesn't match Total Tokens ({})",
+                    calculated_total, total_tokens
+                ),
             ));
         }
 
@@ -136,27 +233,58 @@ impl CsvParser {
             output_tokens,
             total_tokens,
             cost,
+            user,
+            requests,
+            import_id: None,
         })
     }
 
-    fn parse_u32_field(&self, field: Option<&str>, field_name: &str, line_num: usize) -> Result<u32, String> {
-        let value_str = field.unwrap_or("").trim_matches('"');
+    fn parse_u32_field(
+        &self,
+        record: &csv::StringRecord,
+        columns: &ColumnMap,
+        column: Column,
+        blank_as_zero: bool,
+        line_num: usize,
+    ) -> Result<u32, RowError> {
+        let value_str = columns.get(record, column);
+        if blank_as_zero && value_str.trim().is_empty() {
+            return Ok(Default::default());
+        }
         value_str.parse::<u32>().map_err(|_| {
-            format!("Invalid {} value '{}' on line {}. Expected a positive integer", field_name, value_str, line_num)
+            RowError::invalid_value(line_num, column.name(), value_str, "Expected a positive integer")
         })
     }
 
-    fn parse_f64_field(&self, field: Option<&str>, field_name: &str, line_num: usize) -> Result<f64, String> {
-        let value_str = field.unwrap_or("").trim_matches('"');
-        value_str.parse::<f64>().map_err(|_| {
-            format!("Invalid {} value '{}' on line {}. Expected a number", field_name, value_str, line_num)
-        })
+    fn parse_f64_field(
+        &self,
+        record: &csv::StringRecord,
+        columns: &ColumnMap,
+        column: Column,
+        blank_as_zero: bool,
+        line_num: usize,
+    ) -> Result<f64, RowError> {
+        let value_str = columns.get(record, column);
+        if blank_as_zero && value_str.trim().is_empty() {
+            return Ok(Default::default());
+        }
+        match value_str.parse::<f64>() {
+            Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+            _ => Err(RowError::invalid_value(line_num, column.name(), value_str, "Expected a non-negative number")),
+        }
     }
 }
 
+/// Kinds whose rows carry no charge, such as "Errored, No Charge"
+fn is_no_charge_kind(kind: &str) -> bool {
+    let kind = kind.to_lowercase();
+    kind.starts_with("errored") || kind.contains("no charge")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::parse_report::{SchemaVersion, MAX_REPORTED_ROW_ERRORS};
 
     fn create_valid_csv() -> String {
         "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
@@ -184,7 +312,7 @@ mod tests {
         assert_eq!(data[0].date, "2024-01-01T10:00:00Z");
         assert_eq!(data[0].kind, "Included");
         assert_eq!(data[0].model, "auto");
-        assert_eq!(data[0].max_mode, false);
+        assert!(!data[0].max_mode);
         assert_eq!(data[0].input_with_cache, 100);
         assert_eq!(data[0].input_without_cache, 50);
         assert_eq!(data[0].cache_read, 25);
@@ -208,7 +336,7 @@ mod tests {
         
         let result = parser.parse_csv(&csv_content);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid header at column 4"));
+        assert!(result.unwrap_err().contains("Missing required columns in header: Max Mode"));
     }
 
     #[test]
@@ -217,7 +345,7 @@ mod tests {
         let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                           2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,300,0.05";
         
-        let result = parser.parse_csv(&csv_content);
+        let result = parser.parse_csv(csv_content);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Token calculation mismatch"));
     }
@@ -228,7 +356,7 @@ mod tests {
         let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                           2024-01-01T10:00:00Z,Included,auto,No,invalid,50,25,75,250,0.05";
         
-        let result = parser.parse_csv(&csv_content);
+        let result = parser.parse_csv(csv_content);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Invalid Input (w/ Cache Write) value"));
     }
@@ -239,7 +367,7 @@ mod tests {
         let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                           2024-01-01T10:00:00Z,Included,auto,Maybe,100,50,25,75,250,0.05";
         
-        let result = parser.parse_csv(&csv_content);
+        let result = parser.parse_csv(csv_content);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Invalid Max Mode value"));
     }
@@ -250,7 +378,7 @@ mod tests {
         let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                           2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75";
         
-        let result = parser.parse_csv(&csv_content);
+        let result = parser.parse_csv(csv_content);
         assert!(result.is_err());
         let error_msg = result.unwrap_err();
         // The CSV library returns different error messages, so we check for various possibilities
@@ -259,6 +387,65 @@ mod tests {
                 error_msg.contains("Expected 10, found"));
     }
 
+    #[test]
+    fn test_parse_reordered_columns_with_extras() {
+        let parser = CsvParser::new();
+        let csv_content = "Model,Cost,Date,Notes,Kind,Max Mode,Total Tokens,Output Tokens,Cache Read,Input (w/o Cache Write),Input (w/ Cache Write)\n\
+                          auto,0.05,2024-01-01T10:00:00Z,ignored,Included,No,250,75,25,50,100";
+
+        let mut data = Vec::new();
+        let report = parser
+            .parse_reader_with_report(csv_content.as_bytes(), 10, |batch| {
+                data.extend(batch);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(report.schema_version, SchemaVersion::Custom);
+        assert_eq!(report.ignored_columns, vec!["Notes"]);
+        assert_eq!(data[0].model, "auto");
+        assert_eq!(data[0].input_with_cache, 100);
+        assert_eq!(data[0].input_without_cache, 50);
+        assert_eq!(data[0].cost, 0.05);
+    }
+
+    #[test]
+    fn test_parse_team_export_columns() {
+        let parser = CsvParser::new();
+        let csv_content = "Date,User,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost,Requests\n\
+                          2024-01-01T10:00:00Z,alice@example.com,Usage-based,auto,No,100,50,25,75,250,0.05,2\n\
+                          2024-01-01T11:00:00Z,bob@example.com,\"Errored, No Charge\",auto,No,,,,,,,";
+
+        let mut data = Vec::new();
+        let report = parser
+            .parse_reader_with_report(csv_content.as_bytes(), 10, |batch| {
+                data.extend(batch);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(report.schema_version, SchemaVersion::V2);
+        assert_eq!(data[0].user.as_deref(), Some("alice@example.com"));
+        assert_eq!(data[0].requests, 2.0);
+        assert!(data[0].is_usage_based());
+
+        assert_eq!(data[1].kind, "Errored, No Charge");
+        assert!(data[1].is_errored());
+        assert_eq!(data[1].total_tokens, 0);
+        assert_eq!(data[1].cost, 0.0);
+        assert_eq!(data[1].requests, 0.0);
+    }
+
+    #[test]
+    fn test_blank_cells_rejected_for_charged_rows() {
+        let parser = CsvParser::new();
+        let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                          2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,";
+
+        let result = parser.parse_csv(csv_content);
+        assert!(result.unwrap_err().contains("Invalid Cost value ''"));
+    }
+
     #[test]
     fn test_validate_csv_format_success() {
         let parser = CsvParser::new();
@@ -281,8 +468,95 @@ mod tests {
         let parser = CsvParser::new();
         let csv_content = "Date,Kind,Model\n2024-01-01,Included,auto";
         
-        let result = parser.validate_csv_format(&csv_content);
+        let result = parser.validate_csv_format(csv_content);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid number of columns in header"));
+        assert!(result.unwrap_err().contains("Missing required columns in header"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_reader_emits_bounded_batches() {
+        let parser = CsvParser::new();
+        let mut csv_content = create_valid_csv();
+        csv_content.push_str("\n2024-01-02T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05");
+
+        let mut batch_sizes = Vec::new();
+        let total = parser
+            .parse_reader(csv_content.as_bytes(), 2, |batch| {
+                batch_sizes.push(batch.len());
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(total, 3);
+        assert_eq!(batch_sizes, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_parse_reader_stops_on_batch_error() {
+        let parser = CsvParser::new();
+        let csv_content = create_valid_csv();
+
+        let result = parser.parse_reader(csv_content.as_bytes(), 1, |_| Err("storage full".to_string()));
+        assert_eq!(result.unwrap_err(), "storage full");
+    }
+
+    #[test]
+    fn test_lenient_mode_reports_rejected_rows() {
+        let parser = CsvParser::lenient();
+        let csv_content = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                          2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+                          2024-01-02T10:00:00Z,Included,auto,Maybe,100,50,25,75,250,0.05\n\
+                          2024-01-03T10:00:00Z,Included,auto,No,100,50\n\
+                          2024-01-04T10:00:00Z,Included,auto,No,100,50,25,75,250,-1\n\
+                          2024-01-05T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05";
+
+        let mut accepted = Vec::new();
+        let report = parser
+            .parse_reader_with_report(csv_content.as_bytes(), 10, |batch| {
+                accepted.extend(batch);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(report.mode, ParseMode::Lenient);
+        assert_eq!(report.accepted_rows, 2);
+        assert_eq!(report.rejected_rows, 3);
+        assert!(!report.truncated);
+        assert_eq!(accepted.len(), 2);
+
+        let max_mode_error = &report.errors[0];
+        assert_eq!(max_mode_error.line, 3);
+        assert_eq!(max_mode_error.column.as_deref(), Some("Max Mode"));
+        assert_eq!(max_mode_error.value.as_deref(), Some("Maybe"));
+
+        assert_eq!(report.errors[1].line, 4);
+        assert!(report.errors[1].reason.contains("Expected 10, found 6"));
+
+        assert_eq!(report.errors[2].line, 5);
+        assert_eq!(report.errors[2].column.as_deref(), Some("Cost"));
+        assert_eq!(report.errors[2].value.as_deref(), Some("-1"));
+    }
+
+    #[test]
+    fn test_lenient_mode_still_rejects_bad_headers() {
+        let parser = CsvParser::lenient();
+        let result = parser.parse_reader_with_report(create_invalid_header_csv().as_bytes(), 10, |_| Ok(()));
+        assert!(result.unwrap_err().contains("Missing required columns in header: Max Mode"));
+    }
+
+    #[test]
+    fn test_lenient_report_caps_listed_errors() {
+        let parser = CsvParser::lenient();
+        let mut csv_content = create_valid_csv();
+        for _ in 0..MAX_REPORTED_ROW_ERRORS + 5 {
+            csv_content.push_str("\n2024-01-02T10:00:00Z,Included,auto,No,x,50,25,75,250,0.05");
+        }
+
+        let report = parser
+            .parse_reader_with_report(csv_content.as_bytes(), 100, |_| Ok(()))
+            .unwrap();
+        assert_eq!(report.rejected_rows, MAX_REPORTED_ROW_ERRORS + 5);
+        assert_eq!(report.errors.len(), MAX_REPORTED_ROW_ERRORS);
+        assert!(report.truncated);
+    }
+}
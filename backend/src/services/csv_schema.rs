@@ -0,0 +1,275 @@
+use crate::models::parse_report::SchemaVersion;
+use csv::StringRecord;
+
+/// A column the parser knows how to read, independent of its position in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Date,
+    Kind,
+    Model,
+    MaxMode,
+    InputWithCache,
+    InputWithoutCache,
+    CacheRead,
+    OutputTokens,
+    TotalTokens,
+    Cost,
+    User,
+    Requests,
+}
+
+impl Column {
+    /// Columns every export must contain, in the order of the original Cursor export
+    pub const REQUIRED: [Column; 10] = [
+        Column::Date,
+        Column::Kind,
+        Column::Model,
+        Column::MaxMode,
+        Column::InputWithCache,
+        Column::InputWithoutCache,
+        Column::CacheRead,
+        Column::OutputTokens,
+        Column::TotalTokens,
+        Column::Cost,
+    ];
+
+    /// Columns only present in some exports, such as team-admin exports
+    pub const OPTIONAL: [Column; 2] = [Column::User, Column::Requests];
+
+    const COUNT: usize = Self::REQUIRED.len() + Self::OPTIONAL.len();
+
+    /// Header used by the original Cursor export; also used in error messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Date => "Date",
+            Column::Kind => "Kind",
+            Column::Model => "Model",
+            Column::MaxMode => "Max Mode",
+            Column::InputWithCache => "Input (w/ Cache Write)",
+            Column::InputWithoutCache => "Input (w/o Cache Write)",
+            Column::CacheRead => "Cache Read",
+            Column::OutputTokens => "Output Tokens",
+            Column::TotalTokens => "Total Tokens",
+            Column::Cost => "Cost",
+            Column::User => "User",
+            Column::Requests => "Requests",
+        }
+    }
+
+    /// Normalized header names accepted for this column
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Column::Date => &["date", "timestamp", "time"],
+            Column::Kind => &["kind", "type"],
+            Column::Model => &["model"],
+            Column::MaxMode => &["max mode", "max_mode", "maxmode"],
+            Column::InputWithCache => &[
+                "input (w/ cache write)",
+                "input with cache write",
+                "input_with_cache",
+                "cache write",
+            ],
+            Column::InputWithoutCache => &[
+                "input (w/o cache write)",
+                "input without cache write",
+                "input_without_cache",
+                "input",
+            ],
+            Column::CacheRead => &["cache read", "cache_read"],
+            Column::OutputTokens => &["output tokens", "output_tokens", "output"],
+            Column::TotalTokens => &["total tokens", "total_tokens", "tokens"],
+            Column::Cost => &["cost", "cost ($)", "cost (usd)", "cost_usd"],
+            Column::User => &["user", "email", "user email", "user_email"],
+            Column::Requests => &["requests", "request count", "request_count", "requests_count"],
+        }
+    }
+
+    fn from_header(header: &str) -> Option<Column> {
+        let normalized = normalize_header(header);
+        Column::REQUIRED
+            .into_iter()
+            .chain(Column::OPTIONAL)
+            .find(|column| column.aliases().contains(&normalized.as_str()))
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Where each known column sits in a particular file, built from its header row
+#[derive(Debug, Clone)]
+pub struct ColumnMap {
+    indices: [Option<usize>; Column::COUNT],
+    width: usize,
+    pub schema_version: SchemaVersion,
+    /// Headers that did not match any known column, in file order
+    pub ignored_columns: Vec<String>,
+}
+
+impl ColumnMap {
+    /// Match headers to columns by name, accepting known aliases in any order.
+    /// Unknown headers are ignored; every required column must appear exactly once.
+    pub fn from_headers(headers: &StringRecord) -> Result<Self, String> {
+        let mut found: [Option<usize>; Column::COUNT] = [None; Column::COUNT];
+        let mut ignored_columns = Vec::new();
+
+        for (position, header) in headers.iter().enumerate() {
+            let Some(column) = Column::from_header(header) else {
+                ignored_columns.push(header.trim().to_string());
+                continue;
+            };
+
+            if let Some(previous) = found[column.index()] {
+                return Err(format!(
+                    "Duplicate '{}' column at header columns {} and {}",
+                    column.name(),
+                    previous + 1,
+                    position + 1
+                ));
+            }
+            found[column.index()] = Some(position);
+        }
+
+        let missing: Vec<&str> = Column::REQUIRED
+            .into_iter()
+            .filter(|column| found[column.index()].is_none())
+            .map(Column::name)
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("Missing required columns in header: {}", missing.join(", ")));
+        }
+
+        let schema_version = detect_schema_version(headers, &found, &ignored_columns);
+
+        Ok(Self {
+            indices: found,
+            width: headers.len(),
+            schema_version,
+            ignored_columns,
+        })
+    }
+
+    /// Number of fields every data row is expected to have
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Whether the file contains `column`
+    pub fn has(&self, column: Column) -> bool {
+        self.indices[column.index()].is_some()
+    }
+
+    /// Raw value of `column` in `record`, with surrounding quotes removed;
+    /// empty when the file lacks the column
+    pub fn get<'r>(&self, record: &'r StringRecord, column: Column) -> &'r str {
+        self.indices[column.index()]
+            .and_then(|index| record.get(index))
+            .unwrap_or("")
+            .trim_matches('"')
+    }
+}
+
+fn detect_schema_version(
+    headers: &StringRecord,
+    indices: &[Option<usize>],
+    ignored_columns: &[String],
+) -> SchemaVersion {
+    let header_at = |column: Column| indices[column.index()].and_then(|index| headers.get(index)).map(str::trim);
+    let exact_names = Column::REQUIRED
+        .into_iter()
+        .all(|column| header_at(column) == Some(column.name()));
+    if !exact_names || !ignored_columns.is_empty() {
+        return SchemaVersion::Custom;
+    }
+
+    let required: Vec<usize> = Column::REQUIRED.iter().filter_map(|column| indices[column.index()]).collect();
+    let in_order = required.windows(2).all(|pair| pair[0] < pair[1]);
+    if in_order && headers.len() == Column::REQUIRED.len() {
+        return SchemaVersion::V1;
+    }
+
+    let team_columns = Column::OPTIONAL
+        .into_iter()
+        .any(|column| header_at(column) == Some(column.name()));
+    if team_columns {
+        return SchemaVersion::V2;
+    }
+
+    SchemaVersion::Custom
+}
+
+/// Lowercase a header and collapse whitespace so spelling variants compare equal
+fn normalize_header(header: &str) -> String {
+    header
+        .trim_start_matches('\u{feff}')
+        .trim()
+        .trim_matches('"')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&str]) -> StringRecord {
+        StringRecord::from(names.to_vec())
+    }
+
+    #[test]
+    fn test_detects_original_layout() {
+        let map = ColumnMap::from_headers(&headers(&Column::REQUIRED.map(Column::name))).unwrap();
+        assert_eq!(map.schema_version, SchemaVersion::V1);
+        assert!(map.ignored_columns.is_empty());
+    }
+
+    #[test]
+    fn test_detects_team_export() {
+        let map = ColumnMap::from_headers(&headers(&[
+            "Date", "User", "Kind", "Model", "Max Mode", "Input (w/ Cache Write)",
+            "Input (w/o Cache Write)", "Cache Read", "Output Tokens", "Total Tokens", "Cost", "Requests",
+        ]))
+        .unwrap();
+        assert_eq!(map.schema_version, SchemaVersion::V2);
+        assert!(map.ignored_columns.is_empty());
+        assert!(map.has(Column::User) && map.has(Column::Requests));
+
+        let record = StringRecord::from(vec![
+            "2024-01-01", "a@example.com", "Included", "auto", "No", "1", "2", "3", "4", "10", "0.5", "1",
+        ]);
+        assert_eq!(map.get(&record, Column::Kind), "Included");
+        assert_eq!(map.get(&record, Column::Cost), "0.5");
+        assert_eq!(map.get(&record, Column::User), "a@example.com");
+    }
+
+    #[test]
+    fn test_maps_aliases_in_any_order() {
+        let map = ColumnMap::from_headers(&headers(&[
+            "\u{feff}cost ($)", "MODEL", "Notes", "Date", "Type", "max_mode", "Output",
+            "Total  Tokens", "Cache Read", "Input", "Cache Write",
+        ]))
+        .unwrap();
+        assert_eq!(map.schema_version, SchemaVersion::Custom);
+        assert_eq!(map.ignored_columns, vec!["Notes"]);
+        assert_eq!(map.width(), 11);
+
+        let record = StringRecord::from(vec!["0.05", "auto", "", "2024-01-01", "Included", "No", "75", "250", "25", "50", "100"]);
+        assert_eq!(map.get(&record, Column::Cost), "0.05");
+        assert_eq!(map.get(&record, Column::InputWithCache), "100");
+        assert_eq!(map.get(&record, Column::TotalTokens), "250");
+    }
+
+    #[test]
+    fn test_rejects_missing_and_duplicate_columns() {
+        let error = ColumnMap::from_headers(&headers(&["Date", "Kind", "Model"])).unwrap_err();
+        assert!(error.contains("Missing required columns in header: Max Mode"));
+
+        let mut names = Column::REQUIRED.map(Column::name).to_vec();
+        names.push("Cost (USD)");
+        let error = ColumnMap::from_headers(&headers(&names)).unwrap_err();
+        assert!(error.contains("Duplicate 'Cost' column at header columns 10 and 11"));
+    }
+}
@@ -1,12 +1,29 @@
-use crate::models::usage_data::{UsageData, UsageSummary, ModelStats, DateRange};
+use crate::models::usage_data::{UsageData, UsageSummary, ModelStats, GroupStats, DateRange};
+use crate::services::pricing::PricingCatalog;
+use crate::services::record_identity::ImportDeduplicator;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 // Data processing service for calculating summaries and merging data
-pub struct DataProcessor;
+pub struct DataProcessor {
+    pricing: Arc<PricingCatalog>,
+}
+
+impl Default for DataProcessor {
+    fn default() -> Self {
+        Self { pricing: PricingCatalog::builtin() }
+    }
+}
 
 impl DataProcessor {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Value cache savings with `pricing` instead of the built-in catalog
+    pub fn with_pricing(mut self, pricing: Arc<PricingCatalog>) -> Self {
+        self.pricing = pricing;
+        self
     }
 
     /// Calculate comprehensive usage summary from usage data
@@ -22,6 +39,8 @@ impl DataProcessor {
                     end: String::new(),
                 },
                 model_breakdown: Vec::new(),
+                kind_breakdown: Vec::new(),
+                user_breakdown: Vec::new(),
             };
         }
 
@@ -56,7 +75,59 @@ impl DataProcessor {
                 end: end_date,
             },
             model_breakdown,
+            kind_breakdown: self.calculate_kind_stats(data),
+            user_breakdown: self.calculate_user_stats(data),
+        }
+    }
+
+    /// Totals per usage kind ("Included", "Usage-based", "Errored, No Charge", ...)
+    pub fn calculate_kind_stats(&self, data: &[UsageData]) -> Vec<GroupStats> {
+        self.calculate_group_stats(data, |usage| Some(usage.kind.as_str()))
+    }
+
+    /// Totals per user email; rows without a user are left out
+    pub fn calculate_user_stats(&self, data: &[UsageData]) -> Vec<GroupStats> {
+        self.calculate_group_stats(data, |usage| usage.user.as_deref())
+    }
+
+    /// Group records by `key` and total them, most expensive group first
+    pub fn calculate_group_stats<'a>(
+        &self,
+        data: &'a [UsageData],
+        key: impl Fn(&'a UsageData) -> Option<&'a str>,
+    ) -> Vec<GroupStats> {
+        let mut groups: HashMap<&str, GroupStats> = HashMap::new();
+
+        for usage in data {
+            let Some(group_key) = key(usage) else {
+                continue;
+            };
+
+            let stats = groups.entry(group_key).or_insert_with(|| GroupStats {
+                key: group_key.to_string(),
+                record_count: 0,
+                requests: 0.0,
+                total_tokens: 0,
+                total_cost: 0.0,
+                errored_count: 0,
+            });
+            stats.record_count += 1;
+            stats.requests += usage.requests;
+            stats.total_tokens += usage.total_tokens as u64;
+            stats.total_cost += usage.cost;
+            if usage.is_errored() {
+                stats.errored_count += 1;
+            }
         }
+
+        let mut stats: Vec<GroupStats> = groups.into_values().collect();
+        stats.sort_by(|a, b| {
+            b.total_cost
+                .partial_cmp(&a.total_cost)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        stats
     }
 
     /// Calculate detailed statistics for each model
@@ -96,7 +167,7 @@ impl DataProcessor {
         }
 
         // Sort by total requests (most used first)
-        stats.sort_by(|a, b| b.total_requests.cmp(&a.total_requests));
+        stats.sort_by_key(|s| std::cmp::Reverse(s.total_requests));
         stats
     }
 
@@ -119,53 +190,40 @@ impl DataProcessor {
             0.0
         };
 
-        // Calculate cache savings in monetary terms
-        // Estimate: if cache_read tokens were not cached, they would have cost proportionally
-        let total_cost: f64 = model_usage.iter().map(|u| u.cost).sum();
-        let total_tokens: u32 = model_usage.iter().map(|u| u.total_tokens).sum();
-        
-        let cache_savings = if total_tokens > 0 && total_cache_read > 0 {
-            // Estimate cost per token and calculate savings
-            let cost_per_token = total_cost / total_tokens as f64;
-            // Assume cached tokens would have cost the same as input tokens
-            cost_per_token * total_cache_read as f64
-        } else {
-            0.0
-        };
+        // Uncached minus cached list price; 0 for models missing from the catalog
+        let cache_savings = self.pricing.cache_savings(model_usage.iter().copied()).amount;
 
         (cache_hit_rate, cache_savings)
     }
 
-    /// Merge existing data with new data, removing duplicates and sorting by date
+    /// Merge a new import into existing data and sort by date. Rows of `new` whose
+    /// identity (see `ImportDeduplicator`) is already present in `existing` are dropped
+    /// as re-imports; identical rows within either side are kept.
     pub fn merge_data(&self, existing: Vec<UsageData>, new: Vec<UsageData>) -> Vec<UsageData> {
+        let mut deduplicator = ImportDeduplicator::new(&existing);
         let mut combined = existing;
-        combined.extend(new);
-
-        // Remove duplicates based on date, model, and cost (assuming these uniquely identify a record)
-        combined.sort_by(|a, b| {
-            a.date.cmp(&b.date)
-                .then_with(|| a.model.cmp(&b.model))
-                .then_with(|| a.cost.partial_cmp(&b.cost).unwrap_or(std::cmp::Ordering::Equal))
-        });
-
-        combined.dedup_by(|a, b| {
-            a.date == b.date && 
-            a.model == b.model && 
-            a.cost == b.cost &&
-            a.total_tokens == b.total_tokens
-        });
+        combined.extend(new.into_iter().filter(|usage| deduplicator.is_new(usage)));
 
+        // Stable, so records with equal dates keep their import order
+        combined.sort_by(|a, b| a.date.cmp(&b.date));
         combined
     }
 
     /// Validate usage data for consistency
     pub fn validate_usage_data(&self, data: &[UsageData]) -> Result<(), String> {
+        self.validate_usage_batch(data, 0)
+    }
+
+    /// Validate one batch of a larger stream; `offset` is the index of the batch's
+    /// first record so errors point at the record's position in the whole stream
+    pub fn validate_usage_batch(&self, data: &[UsageData], offset: usize) -> Result<(), String> {
         for (index, usage) in data.iter().enumerate() {
+            let index = offset + index;
             // Validate token calculation
-            let calculated_total = usage.input_with_cache + usage.input_without_cache + 
-                                 usage.cache_read + usage.output_tokens;
+            let calculated_total = usage.input_with_cache as u64 + usage.input_without_cache as u64 +
+                                 usage.cache_read as u64 + usage.output_tokens as u64;
             
-            if calculated_total != usage.total_tokens {
+            if calculated_total != usage.total_tokens as u64 {
                 return Err(format!(
                     "Token calculation mismatch at index {}. Sum of individual tokens ({}) doesn't match Total Tokens ({})",
                     index, calculated_total, usage.total_tokens
@@ -0,0 +1,345 @@
+use crate::models::{
+    forecast::{Forecast, ForecastMethod, ForecastPoint, MethodForecast, ProjectedTotal},
+    usage_data::UsageData,
+};
+use crate::services::billing_cycles::BillingCycles;
+use crate::utils::date_utils::to_local;
+use chrono::{Days, NaiveDate};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// Days of history the methods are fitted on unless the request says otherwise
+pub const DEFAULT_HISTORY_DAYS: u32 = 28;
+
+/// Bands are two-sided 95% intervals, assuming independent daily errors
+const CONFIDENCE: f64 = 0.95;
+const Z_SCORE: f64 = 1.959964;
+
+/// Smoothing factors tried when fitting exponential smoothing
+const ALPHAS: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Daily spend or token values fitted by one method
+struct Fit {
+    /// Forecast for each of the next days
+    daily: Vec<f64>,
+    /// Half-width of the band around the sum of `daily`
+    band: f64,
+}
+
+/// Least-squares line through `values` (one per day), extended `days` ahead
+fn fit_linear(values: &[f64], days: usize) -> Fit {
+    let n = values.len() as f64;
+    let mean_t = (n - 1.0) / 2.0;
+    let mean_v = values.iter().sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (t, value) in values.iter().enumerate() {
+        covariance += (t as f64 - mean_t) * (value - mean_v);
+        variance += (t as f64 - mean_t).powi(2);
+    }
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    let intercept = mean_v - slope * mean_t;
+
+    let sse: f64 = values
+        .iter()
+        .enumerate()
+        .map(|(t, value)| (value - (intercept + slope * t as f64)).powi(2))
+        .sum();
+    let sigma = if values.len() > 2 { (sse / (n - 2.0)).sqrt() } else { 0.0 };
+
+    Fit {
+        daily: (1..=days)
+            .map(|h| (intercept + slope * (n - 1.0 + h as f64)).max(0.0))
+            .collect(),
+        band: Z_SCORE * sigma * (days as f64).sqrt(),
+    }
+}
+
+/// Simple exponential smoothing of `values` with the best one-step-ahead factor,
+/// extended `days` ahead at the final level
+fn fit_smoothing(values: &[f64], days: usize) -> Fit {
+    let smooth = |alpha: f64| {
+        let mut level = values[0];
+        let mut sse = 0.0;
+        for value in &values[1..] {
+            let error = value - level;
+            sse += error * error;
+            level += alpha * error;
+        }
+        (level, sse)
+    };
+    let (alpha, (level, sse)) = ALPHAS
+        .iter()
+        .map(|&alpha| (alpha, smooth(alpha)))
+        .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+        .expect("ALPHAS is not empty");
+
+    let sigma = if values.len() > 1 {
+        (sse / (values.len() - 1) as f64).sqrt()
+    } else {
+        0.0
+    };
+    // The h-step-ahead variance of exponential smoothing is sigma² (1 + (h - 1) alpha²)
+    let variance: f64 = (1..=days)
+        .map(|h| 1.0 + (h - 1) as f64 * alpha * alpha)
+        .sum();
+
+    Fit {
+        daily: vec![level.max(0.0); days],
+        band: Z_SCORE * sigma * variance.sqrt(),
+    }
+}
+
+fn fit(method: ForecastMethod, values: &[f64], days: usize) -> Fit {
+    match method {
+        ForecastMethod::Linear => fit_linear(values, days),
+        ForecastMethod::ExponentialSmoothing => fit_smoothing(values, days),
+    }
+}
+
+fn project(actual: f64, fit: &Fit) -> ProjectedTotal {
+    let projected = actual + fit.daily.iter().sum::<f64>();
+    ProjectedTotal {
+        projected,
+        lower: (projected - fit.band).max(actual),
+        upper: projected + fit.band,
+    }
+}
+
+/// Projects spend and tokens through the end of the billing cycle containing the
+/// latest record, from daily totals in the cycles' timezone
+#[derive(Default)]
+pub struct Forecaster {
+    cycles: BillingCycles,
+}
+
+impl Forecaster {
+    /// Forecaster over calendar months in UTC
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forecaster over calendar months local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self::with_billing_cycles(BillingCycles::new(1, timezone))
+    }
+
+    /// Forecaster over a dataset's billing cycles
+    pub fn with_billing_cycles(cycles: BillingCycles) -> Self {
+        Self { cycles }
+    }
+
+    /// Forecast the billing cycle of the latest record, fitting on up to
+    /// `history_days` days ending with it. `None` when there is no dated data.
+    pub fn forecast(&self, data: &[UsageData], history_days: u32) -> Option<Forecast> {
+        let as_of = self.cycles.latest_day(data)?;
+        self.forecast_as_of(data, as_of, history_days)
+    }
+
+    /// Forecast the billing cycle containing `as_of` from the records up to it, fitting
+    /// on up to `history_days` days ending with it. `None` when no dated record falls on
+    /// or before `as_of`.
+    pub fn forecast_as_of(&self, data: &[UsageData], as_of: NaiveDate, history_days: u32) -> Option<Forecast> {
+        let mut daily = self.daily_totals(data);
+        daily.retain(|day, _| *day <= as_of);
+        if daily.is_empty() {
+            return None;
+        }
+        let period = self.cycles.cycle_containing(as_of);
+        Some(self.forecast_period(&daily, as_of, period.start, period.end, history_days))
+    }
+
+    /// Cost and tokens per local day
+    fn daily_totals(&self, data: &[UsageData]) -> BTreeMap<NaiveDate, (f64, u64)> {
+        let mut daily = BTreeMap::new();
+        for usage in data {
+            if let Some(local) = to_local(&usage.date, self.cycles.timezone()) {
+                let totals: &mut (f64, u64) = daily.entry(local.date_naive()).or_default();
+                totals.0 += usage.cost;
+                totals.1 += usage.total_tokens as u64;
+            }
+        }
+        daily
+    }
+
+    fn forecast_period(
+        &self,
+        daily: &BTreeMap<NaiveDate, (f64, u64)>,
+        as_of: NaiveDate,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        history_days: u32,
+    ) -> Forecast {
+        let history_days = history_days.max(1);
+        let first_day = *daily.keys().next().unwrap_or(&as_of);
+        let history_start = as_of
+            .checked_sub_days(Days::new(history_days as u64 - 1))
+            .map_or(first_day, |start| start.max(first_day));
+
+        // Zero-filled history, oldest first
+        let history: Vec<(f64, f64)> = history_start
+            .iter_days()
+            .take_while(|day| *day <= as_of)
+            .map(|day| daily.get(&day).map_or((0.0, 0.0), |&(cost, tokens)| (cost, tokens as f64)))
+            .collect();
+        let costs: Vec<f64> = history.iter().map(|(cost, _)| *cost).collect();
+        let tokens: Vec<f64> = history.iter().map(|(_, tokens)| *tokens).collect();
+
+        let (actual_cost, actual_tokens) = daily
+            .range(period_start..=as_of)
+            .fold((0.0, 0), |(cost, tokens), (_, day)| (cost + day.0, tokens + day.1));
+
+        let days_remaining = (period_end - as_of).num_days().max(0) as usize;
+        let remaining_days: Vec<String> = as_of
+            .iter_days()
+            .skip(1)
+            .take(days_remaining)
+            .map(|day| day.to_string())
+            .collect();
+
+        let forecasts = [ForecastMethod::Linear, ForecastMethod::ExponentialSmoothing]
+            .into_iter()
+            .map(|method| {
+                let cost_fit = fit(method, &costs, days_remaining);
+                let token_fit = fit(method, &tokens, days_remaining);
+                MethodForecast {
+                    method,
+                    cost: project(actual_cost, &cost_fit),
+                    tokens: project(actual_tokens as f64, &token_fit),
+                    daily: remaining_days
+                        .iter()
+                        .zip(cost_fit.daily.iter().zip(&token_fit.daily))
+                        .map(|(date, (&cost, &tokens))| ForecastPoint {
+                            date: date.clone(),
+                            cost,
+                            tokens,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Forecast {
+            period_start: period_start.to_string(),
+            period_end: period_end.to_string(),
+            as_of: as_of.to_string(),
+            days_elapsed: ((as_of - period_start).num_days() + 1).max(0) as u32,
+            days_remaining: days_remaining as u32,
+            history_days: history.len() as u32,
+            confidence: CONFIDENCE,
+            actual_cost,
+            actual_tokens,
+            forecasts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, cost: f64, total_tokens: u32) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: "auto".to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: total_tokens,
+            cache_read: 0,
+            output_tokens: 0,
+            total_tokens,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_linear_trend_is_extended_to_month_end() {
+        // 1.0, 2.0, ..., 10.0 on June 1st-10th: a perfect line with no residuals
+        let data: Vec<UsageData> = (1..=10)
+            .map(|day| create_test_record(&format!("2024-06-{:02}T12:00:00Z", day), day as f64, 100))
+            .collect();
+        let forecast = Forecaster::new().forecast(&data, DEFAULT_HISTORY_DAYS).unwrap();
+
+        assert_eq!(forecast.period_start, "2024-06-01");
+        assert_eq!(forecast.period_end, "2024-06-30");
+        assert_eq!((forecast.days_elapsed, forecast.days_remaining), (10, 20));
+        assert_eq!(forecast.actual_cost, 55.0);
+
+        let linear = &forecast.forecasts[0];
+        assert_eq!(linear.method, ForecastMethod::Linear);
+        assert_eq!(linear.daily.len(), 20);
+        assert_eq!(linear.daily[0].date, "2024-06-11");
+        // 55 so far plus 11 + 12 + ... + 30
+        assert!((linear.cost.projected - 465.0).abs() < 1e-6);
+        assert!((linear.cost.upper - linear.cost.lower).abs() < 1e-6);
+        assert!((linear.tokens.projected - 3000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_smoothing_band_widens_with_noise() {
+        let costs = [4.0, 6.0, 5.0, 3.0, 7.0, 5.0];
+        let data: Vec<UsageData> = costs
+            .iter()
+            .enumerate()
+            .map(|(i, cost)| create_test_record(&format!("2024-02-{:02}T12:00:00Z", i + 20), *cost, 100))
+            .collect();
+        let forecast = Forecaster::new().forecast(&data, DEFAULT_HISTORY_DAYS).unwrap();
+
+        // Leap year: Feb 25th leaves 4 days
+        assert_eq!(forecast.days_remaining, 4);
+        assert_eq!(forecast.days_elapsed, 25);
+        let smoothing = &forecast.forecasts[1];
+        assert_eq!(smoothing.method, ForecastMethod::ExponentialSmoothing);
+        assert!(smoothing.cost.lower < smoothing.cost.projected);
+        assert!(smoothing.cost.upper > smoothing.cost.projected);
+        assert!(smoothing.cost.lower >= forecast.actual_cost);
+    }
+
+    #[test]
+    fn test_forecast_runs_to_billing_cycle_end() {
+        let data = vec![
+            create_test_record("2024-03-20T12:00:00Z", 10.0, 100),
+            create_test_record("2024-03-21T12:00:00Z", 10.0, 100),
+        ];
+        let forecast = Forecaster::with_billing_cycles(BillingCycles::new(15, Tz::UTC))
+            .forecast(&data, DEFAULT_HISTORY_DAYS)
+            .unwrap();
+        assert_eq!(forecast.period_start, "2024-03-15");
+        assert_eq!(forecast.period_end, "2024-04-14");
+        assert_eq!(forecast.days_remaining, 24);
+    }
+
+    #[test]
+    fn test_history_is_zero_filled_and_bounded() {
+        let data = vec![
+            create_test_record("2024-03-01T12:00:00Z", 10.0, 100),
+            create_test_record("2024-03-05T12:00:00Z", 10.0, 100),
+        ];
+        let forecast = Forecaster::new().forecast(&data, 3).unwrap();
+        assert_eq!(forecast.history_days, 3);
+        assert_eq!(forecast.actual_cost, 20.0);
+
+        assert!(Forecaster::new().forecast(&[], DEFAULT_HISTORY_DAYS).is_none());
+    }
+
+    #[test]
+    fn test_forecast_as_of_ignores_later_records() {
+        let data = vec![
+            create_test_record("2024-03-01T12:00:00Z", 10.0, 100),
+            create_test_record("2024-04-02T12:00:00Z", 10.0, 100),
+        ];
+        let forecaster = Forecaster::new();
+        let as_of = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let forecast = forecaster.forecast_as_of(&data, as_of, 3).unwrap();
+        assert_eq!(forecast.as_of, "2024-03-10");
+        assert_eq!(forecast.period_end, "2024-03-31");
+        assert_eq!(forecast.actual_cost, 10.0);
+
+        // Nothing on or before the day to fit on
+        let before = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        assert!(forecaster.forecast_as_of(&data, before, 3).is_none());
+    }
+}
@@ -0,0 +1,361 @@
+use crate::models::parse_report::{ParseMode, ParseReport, RowError, SchemaVersion};
+use crate::models::usage_data::UsageData;
+use crate::services::data_processor::DataProcessor;
+use chrono::DateTime;
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+/// How JSON usage events are laid out in an upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// A single JSON array of events
+    Array,
+    /// One JSON event per line (NDJSON / JSON Lines)
+    Ndjson,
+}
+
+impl JsonFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        match mime.as_str() {
+            "application/json" | "text/json" => Some(JsonFormat::Array),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" | "application/x-jsonlines" => {
+                Some(JsonFormat::Ndjson)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".json") {
+            Some(JsonFormat::Array)
+        } else if file_name.ends_with(".ndjson") || file_name.ends_with(".jsonl") {
+            Some(JsonFormat::Ndjson)
+        } else {
+            None
+        }
+    }
+}
+
+/// A usage event as captured by external tooling. Field names follow `UsageData`;
+/// camelCase spellings are accepted too.
+#[derive(Debug, Deserialize)]
+struct JsonUsageEvent {
+    #[serde(alias = "timestamp", alias = "time")]
+    date: Value,
+    #[serde(default)]
+    kind: String,
+    model: String,
+    #[serde(default, alias = "maxMode")]
+    max_mode: bool,
+    #[serde(default, alias = "inputWithCache", alias = "inputWithCacheWrite", alias = "input_with_cache_write")]
+    input_with_cache: u32,
+    #[serde(default, alias = "inputWithoutCache", alias = "inputWithoutCacheWrite", alias = "input_without_cache_write")]
+    input_without_cache: u32,
+    #[serde(default, alias = "cacheRead")]
+    cache_read: u32,
+    #[serde(default, alias = "outputTokens")]
+    output_tokens: u32,
+    #[serde(default, alias = "totalTokens")]
+    total_tokens: Option<u32>,
+    #[serde(alias = "costUsd", alias = "cost_usd")]
+    cost: f64,
+    #[serde(default, alias = "email", alias = "userEmail")]
+    user: Option<String>,
+    #[serde(default, alias = "requestCount")]
+    requests: Option<f64>,
+}
+
+impl JsonUsageEvent {
+    fn into_usage_data(self) -> Result<UsageData, String> {
+        let date = match self.date {
+            Value::String(date) => date,
+            // Unix timestamps, in milliseconds when too large to be seconds
+            Value::Number(number) => {
+                let timestamp = number.as_i64().ok_or("Invalid date timestamp")?;
+                let parsed = if timestamp.abs() >= 100_000_000_000 {
+                    DateTime::from_timestamp_millis(timestamp)
+                } else {
+                    DateTime::from_timestamp(timestamp, 0)
+                };
+                parsed.ok_or("Invalid date timestamp")?.to_rfc3339()
+            }
+            _ => return Err("Date must be a string or a Unix timestamp".to_string()),
+        };
+
+        let total_tokens = match self.total_tokens {
+            Some(total) => total,
+            None => {
+                let sum = self.input_with_cache as u64
+                    + self.input_without_cache as u64
+                    + self.cache_read as u64
+                    + self.output_tokens as u64;
+                u32::try_from(sum).map_err(|_| "Total tokens out of range".to_string())?
+            }
+        };
+
+        Ok(UsageData {
+            date,
+            kind: self.kind,
+            model: self.model,
+            max_mode: self.max_mode,
+            input_with_cache: self.input_with_cache,
+            input_without_cache: self.input_without_cache,
+            cache_read: self.cache_read,
+            output_tokens: self.output_tokens,
+            total_tokens,
+            cost: self.cost,
+            user: self.user.filter(|user| !user.trim().is_empty()),
+            requests: self.requests.unwrap_or(1.0),
+            import_id: None,
+        })
+    }
+}
+
+/// Importer turning JSON usage events into `UsageData`, validated like CSV rows
+#[derive(Default)]
+pub struct JsonImporter {
+    mode: ParseMode,
+}
+
+impl JsonImporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mode(mode: ParseMode) -> Self {
+        Self { mode }
+    }
+
+    /// Parse events from `reader`, handing records to `on_batch` in batches of at most
+    /// `batch_size`. Every record goes through `DataProcessor::validate_usage_data`;
+    /// invalid records abort parsing in strict mode and are reported in lenient mode.
+    pub fn parse_reader_with_report<R, F>(
+        &self,
+        reader: R,
+        format: JsonFormat,
+        batch_size: usize,
+        on_batch: F,
+    ) -> Result<ParseReport, String>
+    where
+        R: Read,
+        F: FnMut(Vec<UsageData>) -> Result<(), String>,
+    {
+        let mut sink = RecordSink {
+            mode: self.mode,
+            processor: DataProcessor::new(),
+            report: ParseReport::new(self.mode, SchemaVersion::Json, Vec::new()),
+            batch_size: batch_size.max(1),
+            batch: Vec::new(),
+            on_batch,
+            aborted: None,
+        };
+
+        match format {
+            JsonFormat::Array => {
+                // serde_json reads byte by byte, so the reader must be buffered
+                let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+                let result = ArrayVisitor { sink: &mut sink }
+                    .deserialize(&mut deserializer)
+                    .and_then(|_| deserializer.end());
+                if let Err(e) = result {
+                    return Err(sink.aborted.take().unwrap_or_else(|| format!("Invalid JSON upload: {}", e)));
+                }
+            }
+            JsonFormat::Ndjson => {
+                for (index, line) in BufReader::new(reader).lines().enumerate() {
+                    let line = line.map_err(|e| format!("Error reading JSON line {}: {}", index + 1, e))?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let value = serde_json::from_str::<Value>(&line).map_err(|e| RowError::invalid_row(
+                        index + 1,
+                        None,
+                        format!("Invalid JSON: {}", e),
+                    ));
+                    sink.accept(index + 1, value)?;
+                }
+            }
+        }
+
+        sink.finish()
+    }
+}
+
+/// Converts, validates and batches records, tracking the report as it goes
+struct RecordSink<F> {
+    mode: ParseMode,
+    processor: DataProcessor,
+    report: ParseReport,
+    batch_size: usize,
+    batch: Vec<UsageData>,
+    on_batch: F,
+    /// Error that stopped a JSON array midway, kept so it is not reported as a syntax error
+    aborted: Option<String>,
+}
+
+impl<F> RecordSink<F>
+where
+    F: FnMut(Vec<UsageData>) -> Result<(), String>,
+{
+    fn accept(&mut self, line: usize, value: Result<Value, RowError>) -> Result<(), String> {
+        match value.and_then(|value| self.convert(line, value)) {
+            Ok(usage) => {
+                self.batch.push(usage);
+                self.report.accepted_rows += 1;
+            }
+            Err(row_error) => match self.mode {
+                ParseMode::Strict => return Err(row_error.to_string()),
+                ParseMode::Lenient => self.report.reject(row_error),
+            },
+        }
+
+        if self.batch.len() >= self.batch_size {
+            let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+            (self.on_batch)(batch)?;
+        }
+        Ok(())
+    }
+
+    fn convert(&self, line: usize, value: Value) -> Result<UsageData, RowError> {
+        let usage = serde_json::from_value::<JsonUsageEvent>(value)
+            .map_err(|e| e.to_string())
+            .and_then(JsonUsageEvent::into_usage_data)
+            .map_err(|e| RowError::invalid_row(line, None, format!("Invalid usage event: {}", e)))?;
+
+        self.processor
+            .validate_usage_data(std::slice::from_ref(&usage))
+            .map_err(|e| RowError::invalid_row(line, None, e))?;
+        Ok(usage)
+    }
+
+    fn finish(mut self) -> Result<ParseReport, String> {
+        if self.report.accepted_rows + self.report.rejected_rows == 0 {
+            return Err("JSON upload contains no usage events".to_string());
+        }
+        if !self.batch.is_empty() {
+            (self.on_batch)(std::mem::take(&mut self.batch))?;
+        }
+        Ok(self.report)
+    }
+}
+
+/// Streams the elements of a top-level JSON array into a `RecordSink` one at a time
+struct ArrayVisitor<'a, F> {
+    sink: &'a mut RecordSink<F>,
+}
+
+impl<'de, F> DeserializeSeed<'de> for ArrayVisitor<'_, F>
+where
+    F: FnMut(Vec<UsageData>) -> Result<(), String>,
+{
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for ArrayVisitor<'_, F>
+where
+    F: FnMut(Vec<UsageData>) -> Result<(), String>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON array of usage events")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut position = 0;
+        while let Some(value) = seq.next_element::<Value>()? {
+            position += 1;
+            if let Err(e) = self.sink.accept(position, Ok(value)) {
+                self.sink.aborted = Some(e.clone());
+                return Err(de::Error::custom(e));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(importer: &JsonImporter, input: &str, format: JsonFormat) -> Result<(ParseReport, Vec<UsageData>), String> {
+        let mut records = Vec::new();
+        let report = importer.parse_reader_with_report(input.as_bytes(), format, 2, |batch| {
+            records.extend(batch);
+            Ok(())
+        })?;
+        Ok((report, records))
+    }
+
+    #[test]
+    fn test_detects_format() {
+        assert_eq!(JsonFormat::from_content_type("application/json; charset=utf-8"), Some(JsonFormat::Array));
+        assert_eq!(JsonFormat::from_content_type("application/x-ndjson"), Some(JsonFormat::Ndjson));
+        assert_eq!(JsonFormat::from_content_type("text/csv"), None);
+        assert_eq!(JsonFormat::from_file_name("events.jsonl"), Some(JsonFormat::Ndjson));
+    }
+
+    #[test]
+    fn test_parse_json_array() {
+        let input = r#"[
+            {"date": "2024-01-01T10:00:00Z", "kind": "Included", "model": "auto", "max_mode": false,
+             "input_with_cache": 100, "input_without_cache": 50, "cache_read": 25, "output_tokens": 75,
+             "total_tokens": 250, "cost": 0.05},
+            {"timestamp": 1704103200000, "kind": "Usage-based", "model": "gpt-4", "maxMode": true,
+             "inputWithCacheWrite": 10, "outputTokens": 5, "costUsd": 0.01, "email": "alice@example.com", "requestCount": 2},
+            {"date": "2024-01-02T10:00:00Z", "model": "auto", "cost": 0.02}
+        ]"#;
+
+        let (report, records) = parse(&JsonImporter::new(), input, JsonFormat::Array).unwrap();
+        assert_eq!(report.schema_version, SchemaVersion::Json);
+        assert_eq!(report.accepted_rows, 3);
+        assert_eq!(records[0].total_tokens, 250);
+        assert_eq!(records[1].date, "2024-01-01T10:00:00+00:00");
+        assert_eq!(records[1].total_tokens, 15);
+        assert_eq!(records[1].user.as_deref(), Some("alice@example.com"));
+        assert_eq!(records[1].requests, 2.0);
+        assert!(records[1].max_mode);
+        assert_eq!(records[2].requests, 1.0);
+    }
+
+    #[test]
+    fn test_parse_ndjson_lenient() {
+        let input = "{\"date\": \"2024-01-01T10:00:00Z\", \"model\": \"auto\", \"cost\": 0.05}\n\
+                     \n\
+                     not json\n\
+                     {\"date\": \"2024-01-01T11:00:00Z\", \"model\": \"auto\", \"cost\": -1}\n\
+                     {\"date\": \"2024-01-01T12:00:00Z\", \"model\": \"auto\", \"output_tokens\": 5, \"total_tokens\": 9, \"cost\": 0}\n\
+                     {\"date\": \"2024-01-01T13:00:00Z\", \"model\": \"auto\", \"cost\": 0.01}";
+
+        let (report, records) = parse(&JsonImporter::with_mode(ParseMode::Lenient), input, JsonFormat::Ndjson).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(report.rejected_rows, 3);
+        let lines: Vec<usize> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+        assert!(report.errors[1].reason.contains("Negative cost"));
+        assert!(report.errors[2].reason.contains("Token calculation mismatch"));
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_invalid_events() {
+        let input = r#"[{"date": "2024-01-01T10:00:00Z", "model": "auto", "cost": 0.05}, {"model": "auto"}]"#;
+        let error = parse(&JsonImporter::new(), input, JsonFormat::Array).unwrap_err();
+        assert!(error.starts_with("Invalid usage event: missing field `date`"));
+        assert!(error.ends_with("on line 2"));
+
+        let error = parse(&JsonImporter::new(), "{}", JsonFormat::Array).unwrap_err();
+        assert!(error.contains("Invalid JSON upload"));
+
+        let error = parse(&JsonImporter::new(), "[]", JsonFormat::Array).unwrap_err();
+        assert!(error.contains("no usage events"));
+    }
+}
@@ -1,3 +1,18 @@
 pub mod csv_parser;
+pub mod csv_schema;
+pub mod anomaly_detector;
+pub mod billing_cycles;
+pub mod budget_evaluator;
+pub mod cost_audit;
 pub mod data_processor;
-pub mod stats_calculator;
\ No newline at end of file
+pub mod forecaster;
+pub mod json_importer;
+pub mod model_simulator;
+pub mod pricing;
+pub mod ranker;
+pub mod record_identity;
+pub mod sessionizer;
+pub mod stats_calculator;
+pub mod time_series;
+pub mod upload_preview;
+pub mod webhook_dispatcher;
\ No newline at end of file
@@ -0,0 +1,214 @@
+use crate::models::{
+    simulation::{Simulation, SimulationGroup},
+    usage_data::UsageData,
+};
+use crate::services::pricing::PricingCatalog;
+use crate::utils::date_utils::local_day;
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// Projects what usage would have cost had some models been swapped for others,
+/// grouping by model and local day in `timezone`.
+///
+/// A substituted record keeps its tokens and is re-priced by the ratio of the two
+/// models' list prices for those tokens, so discounts and included (free) usage in the
+/// actual charge carry over to the projection.
+pub struct ModelSimulator {
+    timezone: Tz,
+    pricing: Arc<PricingCatalog>,
+}
+
+impl Default for ModelSimulator {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            pricing: PricingCatalog::builtin(),
+        }
+    }
+}
+
+impl ModelSimulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulator whose days are local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            ..Self::default()
+        }
+    }
+
+    /// Price models with `pricing` instead of the built-in catalog
+    pub fn with_pricing(mut self, pricing: Arc<PricingCatalog>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Simulate `data` with every model named in `substitutions` replaced by its value.
+    /// Model names match case-insensitively; every replacement must have a price.
+    pub fn simulate(
+        &self,
+        data: &[UsageData],
+        substitutions: &HashMap<String, String>,
+    ) -> Result<Simulation, String> {
+        let mut replacements = HashMap::new();
+        for (model, replacement) in substitutions {
+            let replacement = replacement.trim();
+            if self.pricing.price_for(replacement).is_none() {
+                return Err(format!("Model '{}' has no price in the pricing catalog", replacement));
+            }
+            replacements.insert(model.trim().to_lowercase(), replacement);
+        }
+
+        let mut groups: BTreeMap<(String, &str), SimulationGroup> = BTreeMap::new();
+        let mut substituted_records = 0;
+        let mut unpriced_records = 0;
+        let mut unpriced_models = BTreeSet::new();
+
+        for usage in data {
+            // Whether a record is substituted depends on its model alone, so every record
+            // of a group shares the group's projected model
+            let target = match replacements.get(&usage.model.trim().to_lowercase()).copied() {
+                Some(_) if self.pricing.price_for(&usage.model).is_none() => {
+                    unpriced_records += 1;
+                    unpriced_models.insert(usage.model.as_str());
+                    None
+                }
+                target => target,
+            };
+            let projected_cost = match target {
+                Some(target) => {
+                    substituted_records += 1;
+                    self.project(usage, target)
+                }
+                None => usage.cost,
+            };
+
+            let date = local_day(&usage.date, self.timezone);
+            let group = groups
+                .entry((date.clone(), usage.model.as_str()))
+                .or_insert_with(|| SimulationGroup {
+                    date,
+                    model: usage.model.clone(),
+                    projected_model: target.map(str::to_string),
+                    record_count: 0,
+                    actual_cost: 0.0,
+                    projected_cost: 0.0,
+                    difference: 0.0,
+                });
+            group.record_count += 1;
+            group.actual_cost += usage.cost;
+            group.projected_cost += projected_cost;
+            group.difference += projected_cost - usage.cost;
+        }
+
+        let groups: Vec<SimulationGroup> = groups.into_values().collect();
+        let actual_cost: f64 = groups.iter().map(|group| group.actual_cost).sum();
+        let projected_cost: f64 = groups.iter().map(|group| group.projected_cost).sum();
+
+        Ok(Simulation {
+            currency: self.pricing.currency().to_string(),
+            actual_cost,
+            projected_cost,
+            difference: projected_cost - actual_cost,
+            substituted_records,
+            unpriced_records,
+            unpriced_models: unpriced_models.into_iter().map(str::to_string).collect(),
+            groups,
+        })
+    }
+
+    /// Charge of `usage` had it run on `replacement`; unchanged unless both models are priced
+    fn project(&self, usage: &UsageData, replacement: &str) -> f64 {
+        let (Some(own), Some(other)) = (self.pricing.price_for(&usage.model), self.pricing.price_for(replacement)) else {
+            return usage.cost;
+        };
+        let list_cost = own.cost(usage);
+        if list_cost > 0.0 {
+            usage.cost * other.cost(usage) / list_cost
+        } else {
+            usage.cost
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: 1_000_000,
+            cache_read: 0,
+            output_tokens: 100_000,
+            total_tokens: 1_100_000,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    fn create_test_simulator() -> ModelSimulator {
+        let pricing = PricingCatalog::from_toml_str(
+            "[models.opus]\ninput = 15.0\ncache_write = 18.75\ncache_read = 1.5\noutput = 75.0\n\
+             [models.sonnet]\ninput = 3.0\ncache_write = 3.75\ncache_read = 0.3\noutput = 15.0",
+        )
+        .unwrap();
+        ModelSimulator::new().with_pricing(Arc::new(pricing))
+    }
+
+    fn substitute(model: &str, replacement: &str) -> HashMap<String, String> {
+        HashMap::from([(model.to_string(), replacement.to_string())])
+    }
+
+    #[test]
+    fn test_substitution_reprices_by_list_price_ratio() {
+        // List prices: opus 22.50, sonnet 4.50 for these tokens (a 1/5 ratio)
+        let data = vec![
+            create_test_record("2024-01-01T10:00:00Z", "claude-opus", 20.0),
+            create_test_record("2024-01-01T11:00:00Z", "Opus", 0.0),
+            create_test_record("2024-01-02T10:00:00Z", "sonnet", 4.5),
+            create_test_record("2024-01-02T11:00:00Z", "opus", 22.5),
+        ];
+        let simulation = create_test_simulator()
+            .simulate(&data, &substitute("opus", "sonnet"))
+            .unwrap();
+
+        assert_eq!(simulation.substituted_records, 2);
+        assert_eq!(simulation.unpriced_records, 0);
+        assert!((simulation.actual_cost - 47.0).abs() < 1e-9);
+        assert!((simulation.projected_cost - (20.0 + 4.5 + 4.5)).abs() < 1e-9);
+
+        let opus_day_one = simulation.groups.iter().find(|g| g.model == "Opus").unwrap();
+        // A free record still belongs to the substituted group
+        assert_eq!(opus_day_one.projected_model.as_deref(), Some("sonnet"));
+        assert_eq!(opus_day_one.projected_cost, 0.0);
+
+        let opus_day_two = simulation.groups.iter().find(|g| g.model == "opus").unwrap();
+        assert!((opus_day_two.difference + 18.0).abs() < 1e-9);
+        assert!(simulation.groups.iter().find(|g| g.model == "claude-opus").unwrap().projected_model.is_none());
+    }
+
+    #[test]
+    fn test_unpriced_models() {
+        let data = vec![create_test_record("2024-01-01T10:00:00Z", "mystery", 1.0)];
+        let simulator = create_test_simulator();
+
+        assert!(simulator.simulate(&data, &substitute("opus", "mystery")).is_err());
+
+        let simulation = simulator.simulate(&data, &substitute("mystery", "sonnet")).unwrap();
+        assert_eq!(simulation.unpriced_records, 1);
+        assert_eq!(simulation.unpriced_models, vec!["mystery"]);
+        assert_eq!(simulation.projected_cost, 1.0);
+        assert!(simulation.groups[0].projected_model.is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::models::usage_data::UsageData;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// Catalog compiled into the binary; see the file for the format
+const BUILTIN_CATALOG: &str = include_str!("../../pricing.toml");
+
+const TOKENS_PER_UNIT: f64 = 1_000_000.0;
+
+/// List prices of one model, in the catalog's currency per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// Price of a record as billed, with cache writes and reads at their own rates
+    pub fn cost(&self, usage: &UsageData) -> f64 {
+        (usage.input_without_cache as f64 * self.input
+            + usage.input_with_cache as f64 * self.cache_write
+            + usage.cache_read as f64 * self.cache_read
+            + usage.output_tokens as f64 * self.output)
+            / TOKENS_PER_UNIT
+    }
+
+    /// Price of the same tokens had every input token been sent uncached
+    pub fn uncached_cost(&self, usage: &UsageData) -> f64 {
+        let input_tokens = usage.input_without_cache as f64
+            + usage.input_with_cache as f64
+            + usage.cache_read as f64;
+        (input_tokens * self.input + usage.output_tokens as f64 * self.output) / TOKENS_PER_UNIT
+    }
+
+    /// Uncached minus cached price; negative when cache-write premiums outweigh reads
+    pub fn cache_savings(&self, usage: &UsageData) -> f64 {
+        self.uncached_cost(usage) - self.cost(usage)
+    }
+}
+
+/// Cache savings over a set of records
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CacheSavings {
+    /// In the catalog's currency, over priced records only
+    pub amount: f64,
+    /// Models of records that had no price and were left out, sorted
+    pub unpriced_models: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CatalogEntry {
+    input: f64,
+    cache_write: f64,
+    cache_read: f64,
+    output: f64,
+    #[serde(default)]
+    aliases: Vec<String>,
+}
+
+impl CatalogEntry {
+    fn price(&self) -> ModelPrice {
+        ModelPrice {
+            input: self.input,
+            cache_write: self.cache_write,
+            cache_read: self.cache_read,
+            output: self.output,
+        }
+    }
+}
+
+/// On-disk shape of a catalog, shared by TOML and JSON
+#[derive(Debug, Deserialize)]
+struct CatalogFile {
+    #[serde(default = "default_currency")]
+    currency: String,
+    #[serde(default)]
+    default: Option<ModelPrice>,
+    #[serde(default)]
+    models: HashMap<String, CatalogEntry>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+/// Per-model list prices used to value token usage
+#[derive(Debug, Clone)]
+pub struct PricingCatalog {
+    currency: String,
+    /// Keyed by lower-cased model name and alias
+    prices: HashMap<String, ModelPrice>,
+    default: Option<ModelPrice>,
+}
+
+impl PricingCatalog {
+    fn from_file(file: CatalogFile) -> Result<Self, String> {
+        let mut prices = HashMap::new();
+        for (model, entry) in &file.models {
+            let price = entry.price();
+            for name in std::iter::once(model).chain(&entry.aliases) {
+                let key = name.trim().to_lowercase();
+                if prices.insert(key, price).is_some() {
+                    return Err(format!("Model '{}' is priced more than once", name));
+                }
+            }
+        }
+
+        let negative = |price: &ModelPrice| {
+            [price.input, price.cache_write, price.cache_read, price.output]
+                .iter()
+                .any(|rate| !rate.is_finite() || *rate < 0.0)
+        };
+        if let Some(model) = prices.iter().find(|(_, price)| negative(price)).map(|(model, _)| model) {
+            return Err(format!("Prices of '{}' must be non-negative numbers", model));
+        }
+        if file.default.as_ref().is_some_and(negative) {
+            return Err("Default prices must be non-negative numbers".to_string());
+        }
+
+        Ok(Self {
+            currency: file.currency,
+            prices,
+            default: file.default,
+        })
+    }
+
+    /// Parse a catalog in TOML
+    pub fn from_toml_str(content: &str) -> Result<Self, String> {
+        let file = toml::from_str(content).map_err(|e| format!("Invalid pricing catalog: {}", e))?;
+        Self::from_file(file)
+    }
+
+    /// Parse a catalog in JSON
+    pub fn from_json_str(content: &str) -> Result<Self, String> {
+        let file = serde_json::from_str(content).map_err(|e| format!("Invalid pricing catalog: {}", e))?;
+        Self::from_file(file)
+    }
+
+    /// Load a catalog file; `.json` files are read as JSON, anything else as TOML
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read pricing catalog '{}': {}", path.display(), e))?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            Self::from_json_str(&content)
+        } else {
+            Self::from_toml_str(&content)
+        }
+    }
+
+    /// The catalog shipped with the binary (`pricing.toml`)
+    pub fn builtin() -> Arc<Self> {
+        static BUILTIN: OnceLock<Arc<PricingCatalog>> = OnceLock::new();
+        BUILTIN
+            .get_or_init(|| {
+                Arc::new(Self::from_toml_str(BUILTIN_CATALOG).expect("built-in pricing catalog is valid"))
+            })
+            .clone()
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Prices of `model`, falling back to the catalog default
+    pub fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices
+            .get(&model.trim().to_lowercase())
+            .or(self.default.as_ref())
+    }
+
+    /// Total cache savings of `data`, leaving out records of unpriced models
+    pub fn cache_savings<'a>(&self, data: impl IntoIterator<Item = &'a UsageData>) -> CacheSavings {
+        let mut amount = 0.0;
+        let mut unpriced = BTreeSet::new();
+        for usage in data {
+            match self.price_for(&usage.model) {
+                Some(price) => amount += price.cache_savings(usage),
+                None => {
+                    unpriced.insert(usage.model.as_str());
+                }
+            }
+        }
+
+        CacheSavings {
+            amount,
+            unpriced_models: unpriced.into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// Catalog named by `PRICING_CATALOG_PATH`, or the built-in one when unset
+pub fn catalog_from_env() -> Result<Arc<PricingCatalog>, String> {
+    match std::env::var("PRICING_CATALOG_PATH") {
+        Ok(path) => PricingCatalog::load(Path::new(&path)).map(Arc::new),
+        Err(_) => Ok(PricingCatalog::builtin()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(model: &str) -> UsageData {
+        UsageData {
+            date: "2024-01-01T10:00:00Z".to_string(),
+            kind: "Included".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 100_000,
+            input_without_cache: 50_000,
+            cache_read: 1_000_000,
+            output_tokens: 20_000,
+            total_tokens: 1_170_000,
+            cost: 0.5,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_savings_is_price_difference() {
+        let price = ModelPrice {
+            input: 3.0,
+            cache_write: 3.75,
+            cache_read: 0.3,
+            output: 15.0,
+        };
+        let usage = create_test_record("claude-4-sonnet");
+
+        // Reads save (3.0 - 0.3) * 1.0M, writes cost (3.75 - 3.0) * 0.1M extra
+        assert!((price.cache_savings(&usage) - (2.7 - 0.075)).abs() < 1e-9);
+        assert!((price.uncached_cost(&usage) - (1.15 * 3.0 + 0.02 * 15.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_catalog_formats_aliases_and_default() {
+        let toml = r#"
+            currency = "EUR"
+
+            [models.claude-4-sonnet]
+            input = 3
+            cache_write = 3.75
+            cache_read = 0.3
+            output = 15
+            aliases = ["Claude-4-Sonnet-Thinking"]
+        "#;
+        let catalog = PricingCatalog::from_toml_str(toml).unwrap();
+        assert_eq!(catalog.currency(), "EUR");
+        assert!(catalog.price_for("claude-4-sonnet-thinking").is_some());
+        assert!(catalog.price_for("gpt-5").is_none());
+
+        let savings = catalog.cache_savings(&[create_test_record("claude-4-sonnet"), create_test_record("gpt-5")]);
+        assert!((savings.amount - 2.625).abs() < 1e-9);
+        assert_eq!(savings.unpriced_models, vec!["gpt-5"]);
+
+        let json = r#"{
+            "default": { "input": 1, "cache_write": 1, "cache_read": 0.1, "output": 5 },
+            "models": {}
+        }"#;
+        let catalog = PricingCatalog::from_json_str(json).unwrap();
+        assert_eq!(catalog.currency(), "USD");
+        assert_eq!(catalog.price_for("anything").unwrap().cache_read, 0.1);
+
+        assert!(PricingCatalog::from_json_str(r#"{ "default": { "input": -1, "cache_write": 1, "cache_read": 1, "output": 1 } }"#).is_err());
+        assert!(PricingCatalog::builtin().price_for("auto").is_some());
+    }
+}
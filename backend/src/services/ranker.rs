@@ -0,0 +1,242 @@
+use crate::models::{
+    ranking::{RankedPeriod, RankedRequest, RankingMetric, Rankings},
+    stats::Granularity,
+    usage_data::UsageData,
+};
+use crate::services::time_series::TimeSeriesAggregator;
+use crate::utils::date_utils::to_local;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+
+/// Item kept by `top_n`, ordered by its value; on ties the earlier item ranks higher
+struct HeapEntry<T> {
+    value: f64,
+    order: usize,
+    item: T,
+}
+
+impl<T> PartialEq for HeapEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for HeapEntry<T> {}
+
+impl<T> PartialOrd for HeapEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for HeapEntry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value
+            .total_cmp(&other.value)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+/// The `n` items of highest value, highest first. A min-heap of at most `n` entries
+/// keeps this O(len log n) instead of sorting everything.
+pub fn top_n<T>(items: impl IntoIterator<Item = (f64, T)>, n: usize) -> Vec<T> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut heap: BinaryHeap<Reverse<HeapEntry<T>>> = BinaryHeap::with_capacity(n);
+    for (order, (value, item)) in items.into_iter().enumerate() {
+        let entry = HeapEntry { value, order, item };
+        if heap.len() < n {
+            heap.push(Reverse(entry));
+        } else if let Some(mut lowest) = heap.peek_mut() {
+            if entry > lowest.0 {
+                *lowest = Reverse(entry);
+            }
+        }
+    }
+    // Ascending in `Reverse` is descending in value
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(entry)| entry.item)
+        .collect()
+}
+
+fn metric_value(metric: RankingMetric, cost: f64, total_tokens: f64) -> f64 {
+    match metric {
+        RankingMetric::Cost => cost,
+        RankingMetric::Tokens => total_tokens,
+    }
+}
+
+/// Running totals of one hour or day
+#[derive(Default)]
+struct PeriodTotals {
+    record_count: usize,
+    requests: f64,
+    total_tokens: u64,
+    cost: f64,
+}
+
+/// Ranks requests, and hours and days of wall-clock time in `timezone`
+#[derive(Default)]
+pub struct Ranker {
+    timezone: Tz,
+    buckets: TimeSeriesAggregator,
+}
+
+impl Ranker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ranker whose hours and days follow the wall clock of `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            buckets: TimeSeriesAggregator::with_timezone(timezone),
+        }
+    }
+
+    /// The `limit` highest requests, hours and days of `data` by `metric`. Records with
+    /// unparseable dates still rank as requests but belong to no hour or day.
+    /// Requirements: 3.5
+    pub fn rank(&self, data: &[UsageData], metric: RankingMetric, limit: usize) -> Rankings {
+        let requests = top_n(
+            data.iter()
+                .map(|usage| (metric_value(metric, usage.cost, usage.total_tokens as f64), usage)),
+            limit,
+        )
+        .into_iter()
+        .map(|usage| RankedRequest {
+            date: usage.date.clone(),
+            model: usage.model.clone(),
+            kind: usage.kind.clone(),
+            max_mode: usage.max_mode,
+            user: usage.user.clone(),
+            total_tokens: usage.total_tokens,
+            cost: usage.cost,
+            import_id: usage.import_id.clone(),
+        })
+        .collect();
+
+        Rankings {
+            metric,
+            limit,
+            requests,
+            hours: self.rank_periods(data, metric, limit, Granularity::Hourly),
+            days: self.rank_periods(data, metric, limit, Granularity::Daily),
+        }
+    }
+
+    fn rank_periods(&self, data: &[UsageData], metric: RankingMetric, limit: usize, granularity: Granularity) -> Vec<RankedPeriod> {
+        // Ordered by start so ties keep the earlier period first
+        let mut totals: BTreeMap<i64, PeriodTotals> = BTreeMap::new();
+        for usage in data {
+            let Some(local) = to_local(&usage.date, self.timezone) else {
+                continue;
+            };
+            let period = totals
+                .entry(self.buckets.bucket_start(&local, granularity).timestamp())
+                .or_default();
+            period.record_count += 1;
+            period.requests += usage.requests;
+            period.total_tokens += usage.total_tokens as u64;
+            period.cost += usage.cost;
+        }
+
+        top_n(
+            totals
+                .into_iter()
+                .map(|(start, totals)| (metric_value(metric, totals.cost, totals.total_tokens as f64), (start, totals))),
+            limit,
+        )
+        .into_iter()
+        .map(|(start, totals)| RankedPeriod {
+            start: DateTime::from_timestamp(start, 0)
+                .map(|start| start.with_timezone(&self.timezone).to_rfc3339())
+                .unwrap_or_default(),
+            record_count: totals.record_count,
+            requests: totals.requests,
+            total_tokens: totals.total_tokens,
+            cost: totals.cost,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, total_tokens: u32, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: total_tokens,
+            cache_read: 0,
+            output_tokens: 0,
+            total_tokens,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_top_n_keeps_highest_in_order() {
+        let values = [3.0, 9.0, 1.0, 9.0, 5.0, 7.0];
+        let top = top_n(values.iter().enumerate().map(|(i, value)| (*value, i)), 3);
+        // The earlier of the two 9.0s ranks first
+        assert_eq!(top, vec![1, 3, 5]);
+
+        assert_eq!(top_n(values.iter().map(|value| (*value, *value)), 10).len(), 6);
+        assert!(top_n(values.iter().map(|value| (*value, *value)), 0).is_empty());
+    }
+
+    #[test]
+    fn test_rank_requests_hours_and_days() {
+        let data = vec![
+            create_test_record("2024-01-01T10:05:00Z", "auto", 1000, 0.5),
+            create_test_record("2024-01-01T10:40:00Z", "auto", 1000, 0.6),
+            create_test_record("2024-01-01T11:00:00Z", "gpt-4", 200, 2.0),
+            create_test_record("2024-01-02T09:00:00Z", "auto", 5000, 0.1),
+        ];
+        let ranker = Ranker::new();
+
+        let by_cost = ranker.rank(&data, RankingMetric::Cost, 2);
+        assert_eq!(by_cost.requests.len(), 2);
+        assert_eq!(by_cost.requests[0].model, "gpt-4");
+        assert_eq!(by_cost.requests[1].cost, 0.6);
+        assert_eq!(by_cost.hours[0].start, "2024-01-01T11:00:00+00:00");
+        assert_eq!(by_cost.hours[1].start, "2024-01-01T10:00:00+00:00");
+        assert_eq!(by_cost.hours[1].record_count, 2);
+        assert!((by_cost.hours[1].cost - 1.1).abs() < 1e-9);
+        assert_eq!(by_cost.days[0].start, "2024-01-01T00:00:00+00:00");
+
+        let by_tokens = ranker.rank(&data, RankingMetric::Tokens, 1);
+        assert_eq!(by_tokens.requests[0].total_tokens, 5000);
+        assert_eq!(by_tokens.days.len(), 1);
+        assert_eq!(by_tokens.days[0].start, "2024-01-02T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_periods_follow_timezone() {
+        // 20:00 and 23:30 UTC on Jan 1 fall on Jan 2 in Tokyo
+        let data = vec![
+            create_test_record("2024-01-01T20:00:00Z", "auto", 100, 1.0),
+            create_test_record("2024-01-01T23:30:00Z", "auto", 100, 1.0),
+            create_test_record("2024-01-01T10:00:00Z", "auto", 100, 1.5),
+        ];
+        let rankings = Ranker::with_timezone(chrono_tz::Asia::Tokyo).rank(&data, RankingMetric::Cost, 5);
+        assert_eq!(rankings.days[0].start, "2024-01-02T00:00:00+09:00");
+        assert_eq!(rankings.days[0].cost, 2.0);
+        assert_eq!(rankings.hours[0].start, "2024-01-01T19:00:00+09:00");
+        assert!(RankingMetric::parse("requests").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::models::usage_data::UsageData;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// SHA-256 over every data field of a record (not its import id); records with equal
+/// fingerprints are indistinguishable
+pub type RecordFingerprint = [u8; 32];
+
+/// Number of stored records per fingerprint
+pub type StoredCounts = HashMap<RecordFingerprint, u32>;
+
+/// Fingerprint a record. Strings are length-prefixed so adjacent fields cannot run
+/// into each other, and `-0.0` is folded into `0.0`.
+pub fn fingerprint(usage: &UsageData) -> RecordFingerprint {
+    let mut hasher = Sha256::new();
+    for text in [&usage.date, &usage.kind, &usage.model] {
+        hasher.update((text.len() as u64).to_le_bytes());
+        hasher.update(text.as_bytes());
+    }
+    hasher.update([usage.max_mode as u8]);
+    for tokens in [
+        usage.input_with_cache,
+        usage.input_without_cache,
+        usage.cache_read,
+        usage.output_tokens,
+        usage.total_tokens,
+    ] {
+        hasher.update(tokens.to_le_bytes());
+    }
+    hasher.update((usage.cost + 0.0).to_bits().to_le_bytes());
+    match usage.user {
+        Some(ref user) => {
+            hasher.update([1]);
+            hasher.update((user.len() as u64).to_le_bytes());
+            hasher.update(user.as_bytes());
+        }
+        None => hasher.update([0]),
+    }
+    hasher.update((usage.requests + 0.0).to_bits().to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// A record's fingerprint plus its occurrence number among identical records of the
+/// same import, starting at 1. Identical rows within one export are distinct requests;
+/// the same identity arriving in a later import means those rows were imported before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordIdentity {
+    pub fingerprint: RecordFingerprint,
+    pub occurrence: u32,
+}
+
+/// Decides, record by record, which rows of a new import are already stored
+#[derive(Debug, Default)]
+pub struct ImportDeduplicator {
+    /// Identical stored records per fingerprint; since stored data is deduplicated on
+    /// import, this is the highest occurrence any earlier import contributed
+    stored: StoredCounts,
+    seen: HashMap<RecordFingerprint, u32>,
+}
+
+impl ImportDeduplicator {
+    /// Start an import on top of the records currently stored
+    pub fn new(stored: &[UsageData]) -> Self {
+        let mut counts = StoredCounts::new();
+        for usage in stored {
+            *counts.entry(fingerprint(usage)).or_insert(0) += 1;
+        }
+        Self {
+            stored: counts,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Identity of the import's next record
+    pub fn identify(&mut self, usage: &UsageData) -> RecordIdentity {
+        let fingerprint = fingerprint(usage);
+        let occurrence = self.seen.entry(fingerprint).or_insert(0);
+        *occurrence += 1;
+        RecordIdentity {
+            fingerprint,
+            occurrence: *occurrence,
+        }
+    }
+
+    /// Whether a record with this identity is already stored
+    pub fn is_stored(&self, identity: &RecordIdentity) -> bool {
+        identity.occurrence <= self.stored.get(&identity.fingerprint).copied().unwrap_or(0)
+    }
+
+    /// Identify the import's next record and report whether it still needs storing
+    pub fn is_new(&mut self, usage: &UsageData) -> bool {
+        let identity = self.identify(usage);
+        !self.is_stored(&identity)
+    }
+
+    /// Keep the records of the import's next batch that still need storing, for an
+    /// import started from `default()` without the stored records at hand. Stored counts
+    /// of fingerprints the import has not met yet are fetched with one `lookup` call; as
+    /// the import has written none of those records, it sees only earlier imports.
+    pub fn retain_new<F>(&mut self, batch: Vec<UsageData>, lookup: F) -> Result<Vec<UsageData>, String>
+    where
+        F: FnOnce(&[RecordFingerprint]) -> Result<StoredCounts, String>,
+    {
+        let fingerprints: Vec<RecordFingerprint> = batch.iter().map(fingerprint).collect();
+        let mut unseen: Vec<RecordFingerprint> = fingerprints
+            .iter()
+            .filter(|fingerprint| !self.seen.contains_key(*fingerprint))
+            .copied()
+            .collect();
+        unseen.sort_unstable();
+        unseen.dedup();
+        if !unseen.is_empty() {
+            self.stored.extend(lookup(&unseen)?);
+        }
+
+        Ok(batch
+            .into_iter()
+            .zip(fingerprints)
+            .filter(|(_, fingerprint)| {
+                let occurrence = self.seen.entry(*fingerprint).or_insert(0);
+                *occurrence += 1;
+                *occurrence > self.stored.get(fingerprint).copied().unwrap_or(0)
+            })
+            .map(|(usage, _)| usage)
+            .collect())
+    }
+
+    /// Records of the import so far that were left out as already stored, per fingerprint
+    pub fn skipped(&self) -> StoredCounts {
+        self.seen
+            .iter()
+            .filter_map(|(fingerprint, seen)| {
+                let skipped = (*seen).min(self.stored.get(fingerprint).copied().unwrap_or(0));
+                (skipped > 0).then_some((*fingerprint, skipped))
+            })
+            .collect()
+    }
+}
+
+/// What one import holds of the records sharing a fingerprint: those it stored and
+/// those it skipped because an earlier import had stored them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportClaim {
+    pub import_id: String,
+    pub stored: u32,
+    pub skipped: u32,
+}
+
+/// When an import is deleted, how many of its `deleted` records sharing a fingerprint
+/// have to stay, and which of the remaining imports (`claims`, oldest first) takes them
+/// over. An import's file held `stored + skipped` copies, so the dataset keeps as many
+/// as the largest of those, counting the `remaining` copies other imports stored.
+pub fn hand_over(deleted: u32, remaining: u32, claims: &[ImportClaim]) -> Option<(&ImportClaim, u32)> {
+    let claim = claims
+        .iter()
+        .rev()
+        .max_by_key(|claim| claim.stored + claim.skipped)?;
+    let kept = (claim.stored + claim.skipped).saturating_sub(remaining).min(deleted);
+    (kept > 0).then_some((claim, kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, kind: &str) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: kind.to_string(),
+            model: "auto".to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens: 250,
+            cost: 0.05,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_covers_every_field() {
+        let record = create_test_record("2024-01-01T10:00:00Z", "Included");
+        assert_eq!(fingerprint(&record), fingerprint(&record.clone()));
+
+        let mut other_kind = record.clone();
+        other_kind.kind = "Usage-based".to_string();
+        let mut other_user = record.clone();
+        other_user.user = Some("alice@example.com".to_string());
+        let mut other_requests = record.clone();
+        other_requests.requests = 2.0;
+        for other in [other_kind, other_user, other_requests] {
+            assert_ne!(fingerprint(&record), fingerprint(&other));
+        }
+
+        let mut free = record.clone();
+        free.cost = 0.0;
+        let mut negative_zero = record;
+        negative_zero.cost = -0.0;
+        assert_eq!(fingerprint(&free), fingerprint(&negative_zero));
+    }
+
+    #[test]
+    fn test_repeats_within_an_import_are_kept() {
+        let record = create_test_record("2024-01-01T10:00:00Z", "Included");
+        let mut dedup = ImportDeduplicator::new(&[]);
+
+        let first = dedup.identify(&record);
+        let second = dedup.identify(&record);
+        assert_eq!((first.occurrence, second.occurrence), (1, 2));
+        assert!(!dedup.is_stored(&first) && !dedup.is_stored(&second));
+    }
+
+    #[test]
+    fn test_reimported_rows_are_rejected() {
+        let repeated = create_test_record("2024-01-01T10:00:00Z", "Included");
+        let stored = vec![repeated.clone(), repeated.clone()];
+
+        // An overlapping export with one more identical request adds only that one
+        let mut dedup = ImportDeduplicator::new(&stored);
+        let kept: Vec<bool> = (0..3).map(|_| dedup.is_new(&repeated)).collect();
+        assert_eq!(kept, vec![false, false, true]);
+
+        // A near-duplicate differing only in kind is a different record
+        let mut dedup = ImportDeduplicator::new(&stored);
+        assert!(dedup.is_new(&create_test_record("2024-01-01T10:00:00Z", "Errored, No Charge")));
+    }
+
+    #[test]
+    fn test_retain_new_looks_up_each_fingerprint_once() {
+        let repeated = create_test_record("2024-01-01T10:00:00Z", "Included");
+        let other = create_test_record("2024-01-02T10:00:00Z", "Included");
+        let stored = StoredCounts::from([(fingerprint(&repeated), 2)]);
+        let mut lookups = Vec::new();
+        let mut dedup = ImportDeduplicator::default();
+
+        let mut lookup = |fingerprints: &[RecordFingerprint]| -> Result<StoredCounts, String> {
+            lookups.push(fingerprints.len());
+            Ok(fingerprints
+                .iter()
+                .filter_map(|fingerprint| stored.get(fingerprint).map(|count| (*fingerprint, *count)))
+                .collect())
+        };
+        let kept = dedup
+            .retain_new(vec![repeated.clone(), other, repeated.clone()], &mut lookup)
+            .unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].date, "2024-01-02T10:00:00Z");
+
+        // Later batches only look up fingerprints not met before; this is the third
+        // identical record, one more than was stored
+        let kept = dedup.retain_new(vec![repeated], &mut lookup).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(lookups, vec![2]);
+    }
+
+    #[test]
+    fn test_skipped_counts_rows_left_out() {
+        let repeated = create_test_record("2024-01-01T10:00:00Z", "Included");
+        let other = create_test_record("2024-01-02T10:00:00Z", "Included");
+        let mut dedup = ImportDeduplicator::new(&[repeated.clone(), repeated.clone()]);
+
+        for usage in [&repeated, &other, &repeated, &repeated] {
+            dedup.is_new(usage);
+        }
+        assert_eq!(dedup.skipped(), StoredCounts::from([(fingerprint(&repeated), 2)]));
+    }
+
+    #[test]
+    fn test_hand_over_keeps_what_the_largest_claim_needs() {
+        let claim = |import_id: &str, stored, skipped| ImportClaim {
+            import_id: import_id.to_string(),
+            stored,
+            skipped,
+        };
+
+        // Nobody else relies on the records
+        assert_eq!(hand_over(2, 0, &[]), None);
+        // Another import's file held two copies, one of them stored by itself
+        let claims = [claim("b", 1, 1)];
+        assert_eq!(hand_over(2, 1, &claims), Some((&claims[0], 1)));
+        // The oldest of the imports needing the most copies takes them over
+        let claims = [claim("b", 0, 1), claim("c", 0, 3), claim("d", 1, 2)];
+        assert_eq!(hand_over(2, 1, &claims), Some((&claims[1], 2)));
+        // Copies stored by other imports already cover every claim
+        assert_eq!(hand_over(2, 3, &claims), None);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::models::{
+    session::{SessionReport, UsageSession},
+    usage_data::UsageData,
+};
+use crate::utils::date_utils::to_local;
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap};
+
+/// Idle minutes that end a session unless the request says otherwise
+pub const DEFAULT_IDLE_GAP_MINUTES: u32 = 30;
+
+/// Groups each user's requests into coding sessions: a session ends when the next
+/// request comes the idle gap or more after the previous one
+#[derive(Default)]
+pub struct Sessionizer {
+    timezone: Tz,
+}
+
+impl Sessionizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sessionizer reporting timestamps local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self { timezone }
+    }
+
+    /// Sessions of `data` split at gaps of at least `idle_gap_minutes`, with the
+    /// `limit` most expensive ones. Records with unparseable dates are left out.
+    /// Requirements: 3.5
+    pub fn sessionize(&self, data: &[UsageData], idle_gap_minutes: u32, limit: usize) -> SessionReport {
+        let idle_gap = Duration::minutes(idle_gap_minutes as i64);
+
+        // Sessions never span users, so team exports are split per user first
+        let mut by_user: BTreeMap<Option<&str>, Vec<(DateTime<Tz>, &UsageData)>> = BTreeMap::new();
+        for usage in data {
+            if let Some(timestamp) = to_local(&usage.date, self.timezone) {
+                by_user.entry(usage.user.as_deref()).or_default().push((timestamp, usage));
+            }
+        }
+
+        let mut sessions = Vec::new();
+        for records in by_user.values_mut() {
+            records.sort_by_key(|(timestamp, _)| *timestamp);
+            let mut start = 0;
+            for index in 1..=records.len() {
+                let split = index == records.len() || records[index].0 - records[index - 1].0 >= idle_gap;
+                if split {
+                    sessions.push(Self::summarize(&records[start..index]));
+                    start = index;
+                }
+            }
+        }
+        sessions.sort_by(|a, b| a.start.cmp(&b.start));
+
+        let session_count = sessions.len();
+        let (average_duration_minutes, average_cost) = if session_count > 0 {
+            (
+                sessions.iter().map(|session| session.duration_minutes).sum::<f64>() / session_count as f64,
+                sessions.iter().map(|session| session.total_cost).sum::<f64>() / session_count as f64,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        let mut most_expensive = sessions.clone();
+        most_expensive.sort_by(|a, b| b.total_cost.total_cmp(&a.total_cost));
+        most_expensive.truncate(limit);
+
+        SessionReport {
+            idle_gap_minutes,
+            session_count,
+            average_duration_minutes,
+            average_cost,
+            sessions,
+            most_expensive,
+        }
+    }
+
+    /// Totals of one session's records, sorted by time
+    fn summarize(records: &[(DateTime<Tz>, &UsageData)]) -> UsageSession {
+        let (first, last) = (records[0].0, records[records.len() - 1].0);
+
+        let mut tokens_by_model: HashMap<&str, u64> = HashMap::new();
+        for (_, usage) in records {
+            *tokens_by_model.entry(usage.model.as_str()).or_default() += usage.total_tokens as u64;
+        }
+        // Ties go to the alphabetically first model so the result is stable
+        let dominant_model = tokens_by_model
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(model, _)| model.to_string())
+            .unwrap_or_default();
+
+        UsageSession {
+            start: first.to_rfc3339(),
+            end: last.to_rfc3339(),
+            duration_minutes: (last - first).num_seconds() as f64 / 60.0,
+            user: records[0].1.user.clone(),
+            record_count: records.len(),
+            requests: records.iter().map(|(_, usage)| usage.requests).sum(),
+            total_tokens: records.iter().map(|(_, usage)| usage.total_tokens as u64).sum(),
+            total_cost: records.iter().map(|(_, usage)| usage.cost).sum(),
+            dominant_model,
+            model_count: tokens_by_model.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, total_tokens: u32, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Usage-based".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 0,
+            input_without_cache: total_tokens,
+            cache_read: 0,
+            output_tokens: 0,
+            total_tokens,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_sessions_split_at_idle_gap() {
+        // Exports list the newest request first
+        let data = vec![
+            create_test_record("2024-01-01T15:00:00Z", "auto", 100, 0.5),
+            create_test_record("2024-01-01T10:40:00Z", "gpt-4", 500, 2.0),
+            create_test_record("2024-01-01T10:20:00Z", "auto", 100, 0.1),
+            create_test_record("2024-01-01T10:00:00Z", "auto", 200, 0.2),
+        ];
+        let report = Sessionizer::new().sessionize(&data, DEFAULT_IDLE_GAP_MINUTES, 10);
+
+        assert_eq!(report.session_count, 2);
+        let first = &report.sessions[0];
+        assert_eq!(first.start, "2024-01-01T10:00:00+00:00");
+        assert_eq!(first.end, "2024-01-01T10:40:00+00:00");
+        assert_eq!(first.duration_minutes, 40.0);
+        assert_eq!(first.record_count, 3);
+        assert_eq!(first.total_tokens, 800);
+        assert!((first.total_cost - 2.3).abs() < 1e-9);
+        assert_eq!(first.dominant_model, "gpt-4");
+        assert_eq!(first.model_count, 2);
+        assert_eq!(report.sessions[1].duration_minutes, 0.0);
+
+        // A shorter gap splits the morning too
+        assert_eq!(Sessionizer::new().sessionize(&data, 15, 10).session_count, 4);
+    }
+
+    #[test]
+    fn test_most_expensive_sessions_and_users() {
+        let mut alice = create_test_record("2024-01-01T10:00:00Z", "auto", 100, 5.0);
+        alice.user = Some("alice@example.com".to_string());
+        let mut bob = create_test_record("2024-01-01T10:05:00Z", "auto", 100, 1.0);
+        bob.user = Some("bob@example.com".to_string());
+        let data = vec![alice, bob, create_test_record("2024-01-02T10:00:00Z", "auto", 100, 3.0)];
+
+        let report = Sessionizer::with_timezone(chrono_tz::Asia::Tokyo).sessionize(&data, 30, 2);
+        assert_eq!(report.session_count, 3);
+        assert_eq!(report.sessions[0].start, "2024-01-01T19:00:00+09:00");
+        assert_eq!(report.most_expensive.len(), 2);
+        assert_eq!(report.most_expensive[0].user.as_deref(), Some("alice@example.com"));
+        assert_eq!(report.most_expensive[1].total_cost, 3.0);
+        assert!((report.average_cost - 3.0).abs() < 1e-9);
+
+        let empty = Sessionizer::new().sessionize(&[], 30, 2);
+        assert_eq!(empty.session_count, 0);
+        assert!(empty.most_expensive.is_empty());
+    }
+}
@@ -2,14 +2,49 @@ use crate::models::{
     usage_data::UsageData,
     stats::{PeakUsageStats, CostEfficiencyStats, UsageTrendStats, UsagePercentiles, ComprehensiveStats}
 };
-use chrono::{DateTime, Timelike};
+use crate::services::pricing::PricingCatalog;
+use crate::utils::date_utils::to_local;
+use chrono::Timelike;
+use chrono_tz::Tz;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-pub struct StatsCalculator;
+/// Calculates statistics, bucketing records by hour and day in `timezone`
+pub struct StatsCalculator {
+    timezone: Tz,
+    pricing: Arc<PricingCatalog>,
+}
+
+impl Default for StatsCalculator {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            pricing: PricingCatalog::builtin(),
+        }
+    }
+}
 
 impl StatsCalculator {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Calculator whose hours and days are local to `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            ..Self::default()
+        }
+    }
+
+    /// Value cache savings with `pricing` instead of the built-in catalog
+    pub fn with_pricing(mut self, pricing: Arc<PricingCatalog>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
     }
 
     /// Calculate peak usage statistics (hours, days)
@@ -28,8 +63,8 @@ impl StatsCalculator {
         let mut daily_costs: HashMap<String, f64> = HashMap::new();
 
         for usage in data {
-            // Parse date and extract hour and day
-            if let Ok(datetime) = DateTime::parse_from_rfc3339(&usage.date) {
+            // Parse date and extract the local hour and day
+            if let Some(datetime) = to_local(&usage.date, self.timezone) {
                 let hour = datetime.hour() as u8;
                 let day = datetime.format("%Y-%m-%d").to_string();
 
@@ -71,6 +106,8 @@ impl StatsCalculator {
                 cost_per_token: 0.0,
                 cost_per_request: 0.0,
                 cache_savings: 0.0,
+                currency: self.pricing.currency().to_string(),
+                unpriced_models: Vec::new(),
             };
         }
 
@@ -90,20 +127,14 @@ impl StatsCalculator {
             0.0
         };
 
-        // Calculate cache savings based on cache read tokens
-        let total_cache_read: u32 = data.iter().map(|d| d.cache_read).sum();
-        let total_input_without_cache: u32 = data.iter().map(|d| d.input_without_cache).sum();
-        
-        let cache_savings = if total_input_without_cache + total_cache_read > 0 {
-            (total_cache_read as f64 / (total_input_without_cache + total_cache_read) as f64) * 100.0
-        } else {
-            0.0
-        };
+        let savings = self.pricing.cache_savings(data);
 
         CostEfficiencyStats {
             cost_per_token,
             cost_per_request,
-            cache_savings,
+            cache_savings: savings.amount,
+            currency: self.pricing.currency().to_string(),
+            unpriced_models: savings.unpriced_models,
         }
     }
 
@@ -125,7 +156,7 @@ impl StatsCalculator {
         // Group data by day and calculate daily totals
         let mut daily_usage: HashMap<String, u32> = HashMap::new();
         for usage in data {
-            if let Ok(datetime) = DateTime::parse_from_rfc3339(&usage.date) {
+            if let Some(datetime) = to_local(&usage.date, self.timezone) {
                 let day = datetime.format("%Y-%m-%d").to_string();
                 *daily_usage.entry(day).or_insert(0) += usage.total_tokens;
             }
@@ -184,7 +215,7 @@ impl StatsCalculator {
 
         let len = token_counts.len();
         
-        let median = if len % 2 == 0 {
+        let median = if len.is_multiple_of(2) {
             (token_counts[len / 2 - 1] + token_counts[len / 2]) / 2
         } else {
             token_counts[len / 2]
@@ -231,6 +262,9 @@ mod tests {
                 output_tokens: 75,
                 total_tokens: 250,
                 cost: 0.05,
+                user: None,
+                requests: 1.0,
+                import_id: None,
             },
             UsageData {
                 date: "2024-01-01T14:00:00Z".to_string(),
@@ -243,6 +277,9 @@ mod tests {
                 output_tokens: 150,
                 total_tokens: 500,
                 cost: 0.15,
+                user: None,
+                requests: 1.0,
+                import_id: None,
             },
             UsageData {
                 date: "2024-01-02T10:00:00Z".to_string(),
@@ -255,6 +292,9 @@ mod tests {
                 output_tokens: 95,
                 total_tokens: 350,
                 cost: 0.08,
+                user: None,
+                requests: 1.0,
+                import_id: None,
             },
         ]
     }
@@ -275,6 +315,24 @@ mod tests {
         assert_eq!(peak_stats.peak_cost_per_day, 0.20); // 0.05 + 0.15
     }
 
+    #[test]
+    fn test_peak_usage_in_local_timezone() {
+        let calculator = StatsCalculator::with_timezone(chrono_tz::Asia::Tokyo);
+        let mut data = create_test_data();
+        data[2].date = "2024-01-01T20:00:00Z".to_string();
+
+        let peak_stats = calculator.calculate_peak_usage(&data);
+
+        // 10:00Z is 19:00 in Tokyo; 20:00Z on Jan 1 is 05:00 on Jan 2
+        assert_eq!(peak_stats.peak_hour, 23);
+        assert_eq!(peak_stats.peak_tokens_per_hour, 500);
+        assert_eq!(peak_stats.peak_day, "2024-01-01");
+        assert_eq!(peak_stats.peak_cost_per_day, 0.20);
+
+        let usage_trends = calculator.calculate_usage_trends(&data);
+        assert_eq!(usage_trends.usage_pattern, "decreasing");
+    }
+
     #[test]
     fn test_calculate_peak_usage_empty_data() {
         let calculator = StatsCalculator::new();
@@ -301,9 +359,11 @@ mod tests {
         assert!((cost_efficiency.cost_per_token - 0.28 / 1100.0).abs() < 0.0001);
         assert!((cost_efficiency.cost_per_request - 0.28 / 3.0).abs() < 0.0001);
         
-        // Cache savings: total_cache_read / (total_input_without_cache + total_cache_read) * 100
-        // (25 + 50 + 30) / (50 + 100 + 75 + 25 + 50 + 30) * 100 = 105 / 330 * 100 ≈ 31.82%
-        assert!((cost_efficiency.cache_savings - 31.818181818181817).abs() < 0.0001);
+        // Built-in "auto" prices (per 1M): input 1.25, cache write 1.25, cache read 0.25;
+        // reads save 1.00 per 1M over (25 + 30) tokens, and gpt-4 has no catalog entry
+        assert!((cost_efficiency.cache_savings - 55.0 / 1_000_000.0).abs() < 1e-12);
+        assert_eq!(cost_efficiency.currency, "USD");
+        assert_eq!(cost_efficiency.unpriced_models, vec!["gpt-4"]);
     }
 
     #[test]
@@ -330,7 +390,7 @@ mod tests {
         assert_eq!(usage_trends.usage_pattern, "decreasing");
         
         // Check percentiles
-        let mut sorted_tokens = vec![250, 350, 500];
+        let mut sorted_tokens = [250, 350, 500];
         sorted_tokens.sort();
         assert_eq!(usage_trends.usage_percentiles.median, 350);
         assert_eq!(usage_trends.usage_percentiles.p95, 500);
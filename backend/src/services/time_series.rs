@@ -0,0 +1,310 @@
+use crate::models::{
+    stats::{Granularity, ModelTimeSeries, TimeSeries, TimeSeriesBucket},
+    usage_data::UsageData,
+};
+use crate::utils::date_utils::to_local;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap};
+
+/// Most buckets a single series may span; a 10-minute series over a leap year is 52,704
+pub const MAX_BUCKETS: usize = 60_000;
+
+/// Running totals of one bucket
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_read_tokens: u64,
+    total_tokens: u64,
+    cost: f64,
+    requests: f64,
+}
+
+impl Totals {
+    fn add(&mut self, usage: &UsageData) {
+        self.input_tokens += usage.input_with_cache as u64 + usage.input_without_cache as u64;
+        self.output_tokens += usage.output_tokens as u64;
+        self.cache_read_tokens += usage.cache_read as u64;
+        self.total_tokens += usage.total_tokens as u64;
+        self.cost += usage.cost;
+        self.requests += usage.requests;
+    }
+
+    fn to_bucket(self, start: &DateTime<Tz>) -> TimeSeriesBucket {
+        TimeSeriesBucket {
+            start: start.to_rfc3339(),
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            cache_read_tokens: self.cache_read_tokens,
+            total_tokens: self.total_tokens,
+            cost: self.cost,
+            requests: self.requests,
+        }
+    }
+}
+
+/// Buckets usage into days, hours or 10-minute slots of wall-clock time in `timezone`
+pub struct TimeSeriesAggregator {
+    timezone: Tz,
+}
+
+impl Default for TimeSeriesAggregator {
+    fn default() -> Self {
+        Self { timezone: Tz::UTC }
+    }
+}
+
+impl TimeSeriesAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aggregator whose buckets follow the wall clock of `timezone`
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self { timezone }
+    }
+
+    /// Bucket `data`, zero-filling every empty bucket between the first and last record.
+    /// With `by_model`, each model additionally gets a series aligned to the same buckets.
+    /// Records with unparseable dates are skipped.
+    pub fn aggregate(
+        &self,
+        data: &[UsageData],
+        granularity: Granularity,
+        by_model: bool,
+    ) -> Result<TimeSeries, String> {
+        let mut totals: BTreeMap<i64, Totals> = BTreeMap::new();
+        let mut model_totals: BTreeMap<&str, HashMap<i64, Totals>> = BTreeMap::new();
+
+        for usage in data {
+            let Some(local) = to_local(&usage.date, self.timezone) else {
+                continue;
+            };
+            let key = self.bucket_start(&local, granularity).timestamp();
+            totals.entry(key).or_default().add(usage);
+            if by_model {
+                model_totals
+                    .entry(usage.model.as_str())
+                    .or_default()
+                    .entry(key)
+                    .or_default()
+                    .add(usage);
+            }
+        }
+
+        let starts = match (totals.keys().next(), totals.keys().next_back()) {
+            (Some(&first), Some(&last)) => self.bucket_starts(first, last, granularity)?,
+            _ => Vec::new(),
+        };
+
+        let buckets = starts
+            .iter()
+            .map(|start| {
+                totals
+                    .get(&start.timestamp())
+                    .copied()
+                    .unwrap_or_default()
+                    .to_bucket(start)
+            })
+            .collect();
+
+        let by_model = by_model.then(|| {
+            model_totals
+                .into_iter()
+                .map(|(model, totals)| ModelTimeSeries {
+                    model: model.to_string(),
+                    buckets: starts
+                        .iter()
+                        .map(|start| {
+                            totals
+                                .get(&start.timestamp())
+                                .copied()
+                                .unwrap_or_default()
+                                .to_bucket(start)
+                        })
+                        .collect(),
+                })
+                .collect()
+        });
+
+        Ok(TimeSeries {
+            granularity,
+            timezone: self.timezone.name().to_string(),
+            buckets,
+            by_model,
+        })
+    }
+
+    /// Every bucket start from `first` to `last` (Unix timestamps of bucket starts)
+    fn bucket_starts(
+        &self,
+        first: i64,
+        last: i64,
+        granularity: Granularity,
+    ) -> Result<Vec<DateTime<Tz>>, String> {
+        let mut starts = Vec::new();
+        let mut current = self.timezone.timestamp_opt(first, 0).single();
+
+        while let Some(start) = current {
+            if start.timestamp() > last {
+                break;
+            }
+            if starts.len() == MAX_BUCKETS {
+                return Err(format!(
+                    "The selected range spans more than {} buckets; choose a coarser granularity or a shorter range",
+                    MAX_BUCKETS
+                ));
+            }
+            current = self.next_bucket(&start, granularity);
+            starts.push(start);
+        }
+
+        Ok(starts)
+    }
+
+    /// Start of the bucket containing `local`
+    pub(crate) fn bucket_start(&self, local: &DateTime<Tz>, granularity: Granularity) -> DateTime<Tz> {
+        let within = match granularity {
+            Granularity::Daily => return self.day_start(local.date_naive()),
+            Granularity::Hourly => local.minute() * 60 + local.second(),
+            Granularity::TenMinutes => (local.minute() % 10) * 60 + local.second(),
+        };
+        // Subtracting from the instant keeps the two 01:00 hours of a DST fall-back apart
+        local.with_nanosecond(0).unwrap_or(*local) - Duration::seconds(within as i64)
+    }
+
+    /// Start of the bucket after the one starting at `start`
+    fn next_bucket(&self, start: &DateTime<Tz>, granularity: Granularity) -> Option<DateTime<Tz>> {
+        let step = match granularity {
+            Granularity::Daily => return start.date_naive().succ_opt().map(|day| self.day_start(day)),
+            Granularity::Hourly => Duration::hours(1),
+            Granularity::TenMinutes => Duration::minutes(10),
+        };
+        let stepped = *start + step;
+        // Re-align after offset changes that are not whole steps (e.g. 30-minute DST shifts)
+        let next = self.bucket_start(&stepped, granularity);
+        Some(if next > *start { next } else { stepped })
+    }
+
+    /// First instant of a local day; midnight itself can be skipped by a DST change
+    fn day_start(&self, day: NaiveDate) -> DateTime<Tz> {
+        (0..24)
+            .filter_map(|hour| day.and_hms_opt(hour, 0, 0))
+            .find_map(|time| self.timezone.from_local_datetime(&time).earliest())
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&day.and_time(NaiveTime::MIN)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::date_utils::parse_timezone;
+
+    fn create_test_record(date: &str, model: &str, total_tokens: u32) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Included".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens,
+            cost: 0.05,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_daily_series_is_zero_filled() {
+        let data = vec![
+            create_test_record("2024-01-01T10:00:00Z", "auto", 250),
+            create_test_record("2024-01-01T12:00:00Z", "auto", 250),
+            create_test_record("2024-01-03T10:00:00Z", "auto", 100),
+        ];
+        let series = TimeSeriesAggregator::new()
+            .aggregate(&data, Granularity::Daily, false)
+            .unwrap();
+
+        let starts: Vec<&str> = series.buckets.iter().map(|b| b.start.as_str()).collect();
+        assert_eq!(starts, vec![
+            "2024-01-01T00:00:00+00:00",
+            "2024-01-02T00:00:00+00:00",
+            "2024-01-03T00:00:00+00:00",
+        ]);
+        assert_eq!(series.buckets[0].total_tokens, 500);
+        assert_eq!(series.buckets[0].input_tokens, 300);
+        assert_eq!(series.buckets[0].cache_read_tokens, 50);
+        assert_eq!(series.buckets[0].requests, 2.0);
+        assert_eq!(series.buckets[1].total_tokens, 0);
+        assert_eq!(series.buckets[1].cost, 0.0);
+        assert!(series.by_model.is_none());
+    }
+
+    #[test]
+    fn test_ten_minute_series_split_by_model() {
+        let data = vec![
+            create_test_record("2024-01-01T10:03:00Z", "auto", 250),
+            create_test_record("2024-01-01T10:29:59Z", "gpt-4", 100),
+        ];
+        let series = TimeSeriesAggregator::new()
+            .aggregate(&data, Granularity::TenMinutes, true)
+            .unwrap();
+
+        assert_eq!(series.buckets.len(), 3);
+        assert_eq!(series.buckets[2].start, "2024-01-01T10:20:00+00:00");
+
+        let by_model = series.by_model.unwrap();
+        assert_eq!(by_model.len(), 2);
+        assert_eq!(by_model[0].model, "auto");
+        assert_eq!(by_model[1].buckets.len(), 3);
+        let totals: Vec<u64> = by_model[1].buckets.iter().map(|b| b.total_tokens).collect();
+        assert_eq!(totals, vec![0, 0, 100]);
+    }
+
+    #[test]
+    fn test_hourly_series_across_dst_fall_back() {
+        // 2024-11-03 01:00-02:00 happens twice in New York
+        let new_york = parse_timezone("America/New_York").unwrap();
+        let data = vec![
+            create_test_record("2024-11-03T05:30:00Z", "auto", 250),
+            create_test_record("2024-11-03T06:30:00Z", "auto", 100),
+        ];
+        let series = TimeSeriesAggregator::with_timezone(new_york)
+            .aggregate(&data, Granularity::Hourly, false)
+            .unwrap();
+
+        let starts: Vec<&str> = series.buckets.iter().map(|b| b.start.as_str()).collect();
+        assert_eq!(starts, vec!["2024-11-03T01:00:00-04:00", "2024-11-03T01:00:00-05:00"]);
+
+        // The fall-back day is 25 hours long but still a single daily bucket
+        let daily = TimeSeriesAggregator::with_timezone(new_york)
+            .aggregate(&data, Granularity::Daily, false)
+            .unwrap();
+        assert_eq!(daily.buckets.len(), 1);
+        assert_eq!(daily.buckets[0].start, "2024-11-03T00:00:00-04:00");
+    }
+
+    #[test]
+    fn test_too_many_buckets_is_an_error() {
+        let data = vec![
+            create_test_record("2023-01-01T00:00:00Z", "auto", 250),
+            create_test_record("2024-12-31T00:00:00Z", "auto", 250),
+        ];
+        let aggregator = TimeSeriesAggregator::new();
+        assert!(aggregator.aggregate(&data, Granularity::TenMinutes, false).is_err());
+        assert_eq!(aggregator.aggregate(&data, Granularity::Daily, false).unwrap().buckets.len(), 731);
+
+        // A whole leap year still fits at the finest granularity
+        let year = vec![
+            create_test_record("2024-01-01T00:00:00Z", "auto", 250),
+            create_test_record("2024-12-31T23:50:00Z", "auto", 250),
+        ];
+        let series = aggregator.aggregate(&year, Granularity::TenMinutes, false).unwrap();
+        assert_eq!(series.buckets.len(), 366 * 24 * 6);
+    }
+}
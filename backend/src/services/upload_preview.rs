@@ -0,0 +1,153 @@
+use crate::models::parse_report::MAX_REPORTED_ROW_ERRORS;
+use crate::models::usage_data::{DateRange, UsageData};
+use crate::services::data_processor::DataProcessor;
+use crate::services::record_identity::ImportDeduplicator;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What an upload would add to a dataset, collected batch by batch without storing anything
+#[derive(Debug, Serialize)]
+pub struct UploadPreview {
+    pub valid_rows: usize,
+    /// Parsed rows failing the checks an import runs on every batch; any of them makes
+    /// the import fail
+    pub invalid_rows: usize,
+    /// Why rows were invalid, at most `MAX_REPORTED_ROW_ERRORS` of them
+    pub validation_errors: Vec<String>,
+    pub date_range: Option<DateRange>,
+    /// Row count per model
+    pub models: BTreeMap<String, usize>,
+    /// Row count per kind
+    pub kinds: BTreeMap<String, usize>,
+    pub total_tokens: u64,
+    pub total_cost: f64,
+    pub existing_records: usize,
+    /// Rows an earlier import already stored
+    pub duplicates_of_existing: usize,
+    /// Rows identical to an earlier row of the same file; they are kept as separate requests
+    pub duplicates_within_file: usize,
+    /// Rows an append would actually add after re-imported rows are skipped
+    pub new_rows: usize,
+    #[serde(skip)]
+    deduplicator: ImportDeduplicator,
+    /// Rows seen so far, valid or not
+    #[serde(skip)]
+    offset: usize,
+}
+
+impl UploadPreview {
+    /// Start a preview against the records currently stored in the dataset
+    pub fn new(existing: &[UsageData]) -> Self {
+        Self {
+            valid_rows: 0,
+            invalid_rows: 0,
+            validation_errors: Vec::new(),
+            date_range: None,
+            models: BTreeMap::new(),
+            kinds: BTreeMap::new(),
+            total_tokens: 0,
+            total_cost: 0.0,
+            existing_records: existing.len(),
+            duplicates_of_existing: 0,
+            duplicates_within_file: 0,
+            new_rows: 0,
+            deduplicator: ImportDeduplicator::new(existing),
+            offset: 0,
+        }
+    }
+
+    pub fn add_batch(&mut self, batch: &[UsageData]) {
+        let processor = DataProcessor::new();
+        for usage in batch {
+            let index = self.offset;
+            self.offset += 1;
+            if let Err(e) = processor.validate_usage_batch(std::slice::from_ref(usage), index) {
+                self.invalid_rows += 1;
+                if self.validation_errors.len() < MAX_REPORTED_ROW_ERRORS {
+                    self.validation_errors.push(e);
+                }
+                continue;
+            }
+
+            self.valid_rows += 1;
+            self.total_tokens += usage.total_tokens as u64;
+            self.total_cost += usage.cost;
+            *self.models.entry(usage.model.clone()).or_default() += 1;
+            *self.kinds.entry(usage.kind.clone()).or_default() += 1;
+
+            DateRange::include(&mut self.date_range, &usage.date);
+
+            let identity = self.deduplicator.identify(usage);
+            if identity.occurrence > 1 {
+                self.duplicates_within_file += 1;
+            }
+            if self.deduplicator.is_stored(&identity) {
+                self.duplicates_of_existing += 1;
+            } else {
+                self.new_rows += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, model: &str, cost: f64) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Included".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens: 250,
+            cost,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_preview_counts_duplicates_and_range() {
+        let existing = vec![create_test_record("2024-01-01T10:00:00Z", "auto", 0.05)];
+        let mut preview = UploadPreview::new(&existing);
+
+        preview.add_batch(&[
+            create_test_record("2024-01-03T10:00:00Z", "gpt-4", 0.10),
+            create_test_record("2024-01-01T10:00:00Z", "auto", 0.05),
+        ]);
+        preview.add_batch(&[create_test_record("2024-01-03T10:00:00Z", "gpt-4", 0.10)]);
+
+        assert_eq!(preview.valid_rows, 3);
+        assert_eq!(preview.existing_records, 1);
+        assert_eq!(preview.duplicates_of_existing, 1);
+        assert_eq!(preview.duplicates_within_file, 1);
+        assert_eq!(preview.new_rows, 2);
+        assert_eq!(preview.models["gpt-4"], 2);
+        let range = preview.date_range.unwrap();
+        assert_eq!(range.start, "2024-01-01T10:00:00Z");
+        assert_eq!(range.end, "2024-01-03T10:00:00Z");
+    }
+
+    #[test]
+    fn test_preview_runs_import_validation() {
+        let mut preview = UploadPreview::new(&[]);
+        let negative_cost = create_test_record("2024-01-01T10:00:00Z", "auto", -1.0);
+        let mut mismatch = create_test_record("2024-01-01T11:00:00Z", "auto", 0.05);
+        mismatch.total_tokens = 999;
+
+        preview.add_batch(&[create_test_record("2024-01-01T09:00:00Z", "auto", 0.05), negative_cost]);
+        preview.add_batch(&[mismatch]);
+
+        assert_eq!(preview.valid_rows, 1);
+        assert_eq!(preview.invalid_rows, 2);
+        assert_eq!(preview.new_rows, 1);
+        assert_eq!(preview.validation_errors[0], "Negative cost at index 1: -1");
+        assert!(preview.validation_errors[1].starts_with("Token calculation mismatch at index 2"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::models::webhook::{Webhook, WebhookDelivery, WebhookEvent, WebhookFormat};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of "<timestamp>.<body>">` for signed webhooks
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Unix time the delivery was signed at; part of the signed message to prevent replays
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// Event id, identical across retries so receivers can drop duplicates
+pub const EVENT_ID_HEADER: &str = "X-Webhook-Id";
+
+/// How often and how patiently a failed delivery is retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled after each further failure
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Limit on each attempt, connection included
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after failed attempt number `attempt` (1-based)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Result of a single POST
+enum Attempt {
+    Delivered(u16),
+    /// Worth retrying: no response, a server error or rate limiting
+    Retryable(Option<u16>, String),
+    /// The receiver rejected the request; retrying would not help
+    Rejected(u16, String),
+}
+
+/// Posts events to webhooks, retrying transient failures with exponential backoff
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self::with_retry_policy(RetryPolicy::default())
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retry_policy(retry: RetryPolicy) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(retry.timeout)
+                .build()
+                .unwrap_or_default(),
+            retry,
+        }
+    }
+
+    /// Body posted to `webhook` for `event`
+    pub fn payload(webhook: &Webhook, event: &WebhookEvent) -> Value {
+        match webhook.format {
+            WebhookFormat::Json => serde_json::to_value(event).unwrap_or(Value::Null),
+            WebhookFormat::Slack => json!({ "text": event.summary }),
+        }
+    }
+
+    /// Signature header value for `body` signed at `timestamp`
+    pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Post `event` to `webhook` until it is accepted, rejected, or the attempts run out
+    pub async fn deliver(&self, webhook: &Webhook, event: &WebhookEvent) -> WebhookDelivery {
+        let body = serde_json::to_vec(&Self::payload(webhook, event)).unwrap_or_default();
+        let mut attempts = 0;
+        let (success, status_code, error) = loop {
+            attempts += 1;
+            match self.attempt(webhook, event, &body).await {
+                Attempt::Delivered(status) => break (true, Some(status), None),
+                Attempt::Rejected(status, error) => break (false, Some(status), Some(error)),
+                Attempt::Retryable(status, error) if attempts >= self.retry.max_attempts => {
+                    break (false, status, Some(error))
+                }
+                Attempt::Retryable(_, error) => {
+                    tracing::warn!(
+                        "Webhook delivery to {} failed (attempt {}): {}",
+                        webhook.url, attempts, error
+                    );
+                    tokio::time::sleep(self.retry.backoff(attempts)).await;
+                }
+            }
+        };
+
+        WebhookDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            webhook_id: webhook.id.clone(),
+            event_id: event.id.clone(),
+            event: event.event,
+            url: webhook.url.clone(),
+            success,
+            attempts,
+            status_code,
+            error,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    async fn attempt(&self, webhook: &Webhook, event: &WebhookEvent, body: &[u8]) -> Attempt {
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event.event.as_str())
+            .header(EVENT_ID_HEADER, &event.id);
+        if let Some(ref secret) = webhook.secret {
+            let timestamp = chrono::Utc::now().timestamp();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, Self::signature(secret, timestamp, body));
+        }
+
+        match request.body(body.to_vec()).send().await {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_success() {
+                    Attempt::Delivered(status.as_u16())
+                } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    Attempt::Retryable(Some(status.as_u16()), format!("Receiver responded with {}", status))
+                } else {
+                    Attempt::Rejected(status.as_u16(), format!("Receiver responded with {}", status))
+                }
+            }
+            Err(e) => Attempt::Retryable(None, format!("Request failed: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::webhook::{WebhookEventKind, WebhookRequest};
+
+    fn create_test_webhook(format: WebhookFormat) -> Webhook {
+        Webhook::new(WebhookRequest {
+            url: "http://127.0.0.1:9/hook".to_string(),
+            format: Some(format),
+            secret: Some("secret".to_string()),
+            events: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_limit() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(1),
+        };
+        let waits: Vec<u128> = (1..=5).map(|attempt| policy.backoff(attempt).as_millis()).collect();
+        assert_eq!(waits, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let signature = WebhookDispatcher::signature("secret", 1_700_000_000, b"{}");
+        assert_eq!(
+            signature,
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_ne!(signature, WebhookDispatcher::signature("secret", 1_700_000_001, b"{}"));
+        assert_ne!(signature, WebhookDispatcher::signature("other", 1_700_000_000, b"{}"));
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let event = WebhookEvent::new(
+            WebhookEventKind::BudgetThreshold,
+            "default",
+            "Budget reached 80%".to_string(),
+            json!({ "threshold": 80 }),
+        );
+
+        let payload = WebhookDispatcher::payload(&create_test_webhook(WebhookFormat::Json), &event);
+        assert_eq!(payload["event"], "budget_threshold");
+        assert_eq!(payload["data"]["threshold"], 80);
+
+        let slack = WebhookDispatcher::payload(&create_test_webhook(WebhookFormat::Slack), &event);
+        assert_eq!(slack, json!({ "text": "Budget reached 80%" }));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_receiver_is_retried() {
+        let dispatcher = WebhookDispatcher::with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            timeout: Duration::from_secs(1),
+        });
+        let webhook = create_test_webhook(WebhookFormat::Json);
+        let event = WebhookEvent::new(WebhookEventKind::Anomaly, "default", String::new(), Value::Null);
+
+        let delivery = dispatcher.deliver(&webhook, &event).await;
+        assert!(!delivery.success);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.status_code, None);
+        assert!(delivery.error.is_some());
+    }
+}
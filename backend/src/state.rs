@@ -0,0 +1,50 @@
+use crate::services::{pricing::PricingCatalog, webhook_dispatcher::WebhookDispatcher};
+use crate::storage::{memory::MemoryStore, UsageStore};
+use chrono_tz::Tz;
+use std::sync::Arc;
+
+/// Shared application state handed to every handler through axum `State`
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<dyn UsageStore>,
+    /// Timezone used for bucketing and date filters when a request does not name one
+    pub timezone: Tz,
+    /// Prices used to value cache savings
+    pub pricing: Arc<PricingCatalog>,
+    /// Posts budget and anomaly events to the datasets' webhooks
+    pub webhooks: Arc<WebhookDispatcher>,
+}
+
+impl AppState {
+    pub fn new(store: Arc<dyn UsageStore>) -> Self {
+        Self {
+            store,
+            timezone: Tz::UTC,
+            pricing: PricingCatalog::builtin(),
+            webhooks: Arc::new(WebhookDispatcher::new()),
+        }
+    }
+
+    /// Use `timezone` as the server default
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Use `pricing` instead of the built-in catalog
+    pub fn with_pricing(mut self, pricing: Arc<PricingCatalog>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Deliver webhooks through `webhooks`, e.g. one with a different retry policy
+    pub fn with_webhook_dispatcher(mut self, webhooks: Arc<WebhookDispatcher>) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// State backed by a fresh, isolated in-memory store
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(MemoryStore::new()))
+    }
+}
@@ -0,0 +1,514 @@
+use crate::models::billing::BillingCycleConfig;
+use crate::models::budget::Budget;
+use crate::models::dataset::{Dataset, DEFAULT_DATASET_ID};
+use crate::models::import::ImportRecord;
+use crate::models::usage_data::{DateRange, UsageData};
+use crate::models::webhook::{Webhook, WebhookDelivery};
+use crate::storage::{
+    generate_dataset_id, validate_dataset_name, BatchWriter, UsageQuery, UsageStore, WriteMode,
+};
+use crate::services::record_identity::{fingerprint, hand_over, ImportClaim, RecordFingerprint, StoredCounts};
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockWriteGuard};
+
+struct DatasetEntry {
+    id: String,
+    name: String,
+    created_at: String,
+    records: Vec<UsageData>,
+    imports: Vec<ImportRecord>,
+    /// Rows each import skipped as already stored, by import id
+    duplicates: HashMap<String, StoredCounts>,
+    billing_cycle: BillingCycleConfig,
+    budgets: Vec<Budget>,
+    webhooks: Vec<Webhook>,
+    deliveries: Vec<WebhookDelivery>,
+}
+
+impl DatasetEntry {
+    fn new(id: String, name: String) -> Self {
+        Self {
+            id,
+            name,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            records: Vec::new(),
+            imports: Vec::new(),
+            duplicates: HashMap::new(),
+            billing_cycle: BillingCycleConfig::default(),
+            budgets: Vec::new(),
+            webhooks: Vec::new(),
+            deliveries: Vec::new(),
+        }
+    }
+
+    fn to_dataset(&self) -> Dataset {
+        Dataset {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            created_at: self.created_at.clone(),
+            record_count: self.records.len(),
+            billing_cycle: self.billing_cycle.clone(),
+        }
+    }
+}
+
+/// Volatile store keeping records in vectors; data is lost on restart
+pub struct MemoryStore {
+    // Kept in creation order
+    datasets: RwLock<Vec<DatasetEntry>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            datasets: RwLock::new(vec![DatasetEntry::new(
+                DEFAULT_DATASET_ID.to_string(),
+                "Default".to_string(),
+            )]),
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_entry<T>(&self, dataset_id: &str, f: impl FnOnce(&DatasetEntry) -> T) -> Result<T, String> {
+        let datasets = self.datasets.read().unwrap();
+        datasets
+            .iter()
+            .find(|entry| entry.id == dataset_id)
+            .map(f)
+            .ok_or_else(|| format!("Dataset '{}' not found", dataset_id))
+    }
+
+    fn with_entry_mut<T>(&self, dataset_id: &str, f: impl FnOnce(&mut DatasetEntry) -> T) -> Result<T, String> {
+        let mut datasets = self.datasets.write().unwrap();
+        datasets
+            .iter_mut()
+            .find(|entry| entry.id == dataset_id)
+            .map(f)
+            .ok_or_else(|| format!("Dataset '{}' not found", dataset_id))
+    }
+
+    fn with_records<T>(&self, dataset_id: &str, f: impl FnOnce(&[UsageData]) -> T) -> Result<T, String> {
+        self.with_entry(dataset_id, |entry| f(&entry.records))
+    }
+
+    fn with_records_mut<T>(
+        &self,
+        dataset_id: &str,
+        f: impl FnOnce(&mut Vec<UsageData>) -> T,
+    ) -> Result<T, String> {
+        self.with_entry_mut(dataset_id, |entry| f(&mut entry.records))
+    }
+}
+
+/// Stages written records until commit, then swaps them in. Holds the store's write
+/// lock throughout so concurrent writes cannot both miss each other's records.
+struct MemoryBatchWriter<'a> {
+    datasets: RwLockWriteGuard<'a, Vec<DatasetEntry>>,
+    index: usize,
+    mode: WriteMode,
+    staged: Vec<UsageData>,
+    prior_imports: Vec<ImportRecord>,
+    import: Option<(ImportRecord, StoredCounts)>,
+    /// Counts over the records stored before the write, built on first use
+    stored: Option<StoredCounts>,
+}
+
+impl BatchWriter for MemoryBatchWriter<'_> {
+    fn write(&mut self, records: &[UsageData]) -> Result<(), String> {
+        if let Some(stored) = self.stored.as_mut() {
+            for usage in records {
+                *stored.entry(fingerprint(usage)).or_insert(0) += 1;
+            }
+        }
+        self.staged.extend_from_slice(records);
+        Ok(())
+    }
+
+    fn stored_counts(&mut self, fingerprints: &[RecordFingerprint]) -> Result<StoredCounts, String> {
+        let stored = match self.stored {
+            Some(ref stored) => stored,
+            None => {
+                let mut counts = StoredCounts::new();
+                let kept = match self.mode {
+                    WriteMode::Append => &self.datasets[self.index].records[..],
+                    WriteMode::Replace => &[],
+                };
+                for usage in kept.iter().chain(&self.staged) {
+                    *counts.entry(fingerprint(usage)).or_insert(0) += 1;
+                }
+                self.stored.insert(counts)
+            }
+        };
+
+        Ok(fingerprints
+            .iter()
+            .filter_map(|fingerprint| stored.get(fingerprint).map(|count| (*fingerprint, *count)))
+            .collect())
+    }
+
+    fn prior_imports(&self) -> &[ImportRecord] {
+        &self.prior_imports
+    }
+
+    fn prior_records(&mut self, query: &UsageQuery) -> Result<Vec<UsageData>, String> {
+        Ok(self.datasets[self.index]
+            .records
+            .iter()
+            .filter(|usage| query.matches(usage))
+            .cloned()
+            .collect())
+    }
+
+    fn record_import(&mut self, import: &ImportRecord, skipped: &StoredCounts) -> Result<(), String> {
+        self.import = Some((import.clone(), skipped.clone()));
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), String> {
+        let MemoryBatchWriter { mut datasets, index, mode, staged, import, .. } = *self;
+        let entry = &mut datasets[index];
+        if mode == WriteMode::Replace {
+            entry.records.clear();
+            entry.imports.clear();
+            entry.duplicates.clear();
+        }
+        entry.records.extend(staged);
+        if let Some((import, skipped)) = import {
+            entry.duplicates.insert(import.id.clone(), skipped);
+            entry.imports.push(import);
+        }
+        Ok(())
+    }
+}
+
+impl UsageStore for MemoryStore {
+    fn list_datasets(&self) -> Result<Vec<Dataset>, String> {
+        Ok(self.datasets.read().unwrap().iter().map(DatasetEntry::to_dataset).collect())
+    }
+
+    fn get_dataset(&self, dataset_id: &str) -> Result<Option<Dataset>, String> {
+        let datasets = self.datasets.read().unwrap();
+        Ok(datasets
+            .iter()
+            .find(|entry| entry.id == dataset_id)
+            .map(DatasetEntry::to_dataset))
+    }
+
+    fn create_dataset(&self, name: &str) -> Result<Dataset, String> {
+        let name = validate_dataset_name(name)?;
+        let mut datasets = self.datasets.write().unwrap();
+        let id = generate_dataset_id(&name, |candidate| {
+            datasets.iter().any(|entry| entry.id == candidate)
+        });
+
+        let entry = DatasetEntry::new(id, name);
+        let dataset = entry.to_dataset();
+        datasets.push(entry);
+        Ok(dataset)
+    }
+
+    fn rename_dataset(&self, dataset_id: &str, name: &str) -> Result<Option<Dataset>, String> {
+        let name = validate_dataset_name(name)?;
+        let mut datasets = self.datasets.write().unwrap();
+        Ok(datasets.iter_mut().find(|entry| entry.id == dataset_id).map(|entry| {
+            entry.name = name;
+            entry.to_dataset()
+        }))
+    }
+
+    fn set_billing_cycle(&self, dataset_id: &str, config: &BillingCycleConfig) -> Result<Option<Dataset>, String> {
+        config.validate()?;
+        let mut datasets = self.datasets.write().unwrap();
+        Ok(datasets.iter_mut().find(|entry| entry.id == dataset_id).map(|entry| {
+            entry.billing_cycle = config.clone();
+            entry.to_dataset()
+        }))
+    }
+
+    fn delete_dataset(&self, dataset_id: &str) -> Result<bool, String> {
+        let mut datasets = self.datasets.write().unwrap();
+        let before = datasets.len();
+        datasets.retain(|entry| entry.id != dataset_id);
+        Ok(datasets.len() != before)
+    }
+
+    fn insert_batch(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String> {
+        self.with_records_mut(dataset_id, |stored| stored.extend_from_slice(records))
+    }
+
+    fn replace_all(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String> {
+        self.with_records_mut(dataset_id, |stored| *stored = records.to_vec())
+    }
+
+    fn query(&self, dataset_id: &str, query: &UsageQuery) -> Result<Vec<UsageData>, String> {
+        self.with_records(dataset_id, |records| {
+            if query.is_unfiltered() {
+                return records.to_vec();
+            }
+
+            records.iter().filter(|usage| query.matches(usage)).cloned().collect()
+        })
+    }
+
+    fn begin_write(&self, dataset_id: &str, mode: WriteMode) -> Result<Box<dyn BatchWriter + '_>, String> {
+        let datasets = self.datasets.write().unwrap();
+        let index = datasets
+            .iter()
+            .position(|entry| entry.id == dataset_id)
+            .ok_or_else(|| format!("Dataset '{}' not found", dataset_id))?;
+        let prior_imports = datasets[index].imports.clone();
+        Ok(Box::new(MemoryBatchWriter {
+            datasets,
+            index,
+            mode,
+            staged: Vec::new(),
+            prior_imports,
+            import: None,
+            stored: None,
+        }))
+    }
+
+    fn count(&self, dataset_id: &str) -> Result<usize, String> {
+        self.with_records(dataset_id, |records| records.len())
+    }
+
+    fn clear(&self, dataset_id: &str) -> Result<(), String> {
+        self.with_records_mut(dataset_id, |stored| stored.clear())
+    }
+
+    fn list_imports(&self, dataset_id: &str) -> Result<Vec<ImportRecord>, String> {
+        self.with_entry(dataset_id, |entry| entry.imports.clone())
+    }
+
+    fn delete_import(&self, dataset_id: &str, import_id: &str) -> Result<Option<usize>, String> {
+        self.with_entry_mut(dataset_id, |entry| {
+            let position = entry.imports.iter().position(|import| import.id == import_id)?;
+            entry.imports.remove(position);
+            entry.duplicates.remove(import_id);
+
+            // Records a remaining import skipped as duplicates of these stay, now owned by it
+            let mut owned: HashMap<RecordFingerprint, Vec<usize>> = HashMap::new();
+            let mut remaining = StoredCounts::new();
+            let mut stored: HashMap<(String, RecordFingerprint), u32> = HashMap::new();
+            for (index, usage) in entry.records.iter().enumerate() {
+                let fingerprint = fingerprint(usage);
+                if usage.import_id.as_deref() == Some(import_id) {
+                    owned.entry(fingerprint).or_default().push(index);
+                    continue;
+                }
+                *remaining.entry(fingerprint).or_insert(0) += 1;
+                if let Some(ref id) = usage.import_id {
+                    *stored.entry((id.clone(), fingerprint)).or_insert(0) += 1;
+                }
+            }
+            for (fingerprint, indices) in owned {
+                let claims: Vec<ImportClaim> = entry
+                    .imports
+                    .iter()
+                    .filter_map(|import| {
+                        let skipped = entry.duplicates.get(&import.id)?.get(&fingerprint).copied()?;
+                        Some(ImportClaim {
+                            import_id: import.id.clone(),
+                            stored: stored.get(&(import.id.clone(), fingerprint)).copied().unwrap_or(0),
+                            skipped,
+                        })
+                    })
+                    .collect();
+                let remaining = remaining.get(&fingerprint).copied().unwrap_or(0);
+                let Some((claim, kept)) = hand_over(indices.len() as u32, remaining, &claims) else {
+                    continue;
+                };
+                let Some(import) = entry.imports.iter_mut().find(|import| import.id == claim.import_id) else {
+                    continue;
+                };
+                for &index in &indices[..kept as usize] {
+                    let usage = &mut entry.records[index];
+                    usage.import_id = Some(import.id.clone());
+                    DateRange::include(&mut import.date_range, &usage.date);
+                }
+                import.record_count += kept as usize;
+                if let Some(skipped) = entry.duplicates.get_mut(&import.id) {
+                    match skipped.get(&fingerprint).copied() {
+                        Some(count) if count > kept => {
+                            skipped.insert(fingerprint, count - kept);
+                        }
+                        _ => {
+                            skipped.remove(&fingerprint);
+                        }
+                    }
+                }
+            }
+
+            let before = entry.records.len();
+            entry.records.retain(|usage| usage.import_id.as_deref() != Some(import_id));
+            Some(before - entry.records.len())
+        })
+    }
+
+    fn list_budgets(&self, dataset_id: &str) -> Result<Vec<Budget>, String> {
+        self.with_entry(dataset_id, |entry| entry.budgets.clone())
+    }
+
+    fn save_budget(&self, dataset_id: &str, budget: &Budget) -> Result<(), String> {
+        self.with_entry_mut(dataset_id, |entry| {
+            match entry.budgets.iter_mut().find(|existing| existing.id == budget.id) {
+                Some(existing) => *existing = budget.clone(),
+                None => entry.budgets.push(budget.clone()),
+            }
+        })
+    }
+
+    fn delete_budget(&self, dataset_id: &str, budget_id: &str) -> Result<bool, String> {
+        self.with_entry_mut(dataset_id, |entry| {
+            let before = entry.budgets.len();
+            entry.budgets.retain(|budget| budget.id != budget_id);
+            entry.budgets.len() != before
+        })
+    }
+
+    fn list_webhooks(&self, dataset_id: &str) -> Result<Vec<Webhook>, String> {
+        self.with_entry(dataset_id, |entry| entry.webhooks.clone())
+    }
+
+    fn save_webhook(&self, dataset_id: &str, webhook: &Webhook) -> Result<(), String> {
+        self.with_entry_mut(dataset_id, |entry| {
+            match entry.webhooks.iter_mut().find(|existing| existing.id == webhook.id) {
+                Some(existing) => *existing = webhook.clone(),
+                None => entry.webhooks.push(webhook.clone()),
+            }
+        })
+    }
+
+    fn delete_webhook(&self, dataset_id: &str, webhook_id: &str) -> Result<bool, String> {
+        self.with_entry_mut(dataset_id, |entry| {
+            let before = entry.webhooks.len();
+            entry.webhooks.retain(|webhook| webhook.id != webhook_id);
+            entry.webhooks.len() != before
+        })
+    }
+
+    fn record_delivery(&self, dataset_id: &str, delivery: &WebhookDelivery) -> Result<(), String> {
+        self.with_entry_mut(dataset_id, |entry| entry.deliveries.push(delivery.clone()))
+    }
+
+    fn list_deliveries(&self, dataset_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, String> {
+        self.with_entry(dataset_id, |entry| entry.deliveries.iter().rev().take(limit).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(date: &str, kind: &str) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: kind.to_string(),
+            model: "auto".to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens: 250,
+            cost: 0.05,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_query_and_replace() {
+        let store = MemoryStore::new();
+        store
+            .insert_batch(DEFAULT_DATASET_ID, &[
+                create_test_record("2024-01-01T10:00:00Z", "Included"),
+                create_test_record("2024-01-02T10:00:00Z", "Usage-based"),
+            ])
+            .unwrap();
+
+        let by_kind = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                kind: Some("Usage-based".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_kind.len(), 1);
+        assert_eq!(by_kind[0].date, "2024-01-02T10:00:00Z");
+
+        store
+            .replace_all(DEFAULT_DATASET_ID, &[create_test_record("2024-01-03T10:00:00Z", "Included")])
+            .unwrap();
+        assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), 1);
+
+        store.clear(DEFAULT_DATASET_ID).unwrap();
+        assert!(store.load_all(DEFAULT_DATASET_ID).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_datasets_are_isolated() {
+        let store = MemoryStore::new();
+        let alice = store.create_dataset("Alice's export").unwrap();
+        let bob = store.create_dataset("Alice's export").unwrap();
+        assert_eq!(alice.id, "alice-s-export");
+        assert_eq!(bob.id, "alice-s-export-2");
+
+        store
+            .insert_batch(&alice.id, &[create_test_record("2024-01-01T10:00:00Z", "Included")])
+            .unwrap();
+        store
+            .replace_all(&bob.id, &[])
+            .unwrap();
+        assert_eq!(store.count(&alice.id).unwrap(), 1);
+        assert_eq!(store.count(&bob.id).unwrap(), 0);
+
+        let renamed = store.rename_dataset(&bob.id, "Bob").unwrap().unwrap();
+        assert_eq!(renamed.id, bob.id);
+        assert_eq!(renamed.name, "Bob");
+
+        assert!(store.delete_dataset(&alice.id).unwrap());
+        assert!(store.count(&alice.id).is_err());
+        assert_eq!(store.list_datasets().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_writer_counts_stored_and_written_fingerprints() {
+        let store = MemoryStore::new();
+        let record = create_test_record("2024-01-01T10:00:00Z", "Included");
+        store.insert_batch(DEFAULT_DATASET_ID, std::slice::from_ref(&record)).unwrap();
+        let fingerprints = [fingerprint(&record)];
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        assert_eq!(writer.stored_counts(&fingerprints).unwrap()[&fingerprints[0]], 1);
+        writer.write(&[record]).unwrap();
+        assert_eq!(writer.stored_counts(&fingerprints).unwrap()[&fingerprints[0]], 2);
+        drop(writer);
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+        assert!(writer.stored_counts(&fingerprints).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_writer_reads_prior_records_only() {
+        let store = MemoryStore::new();
+        let included = create_test_record("2024-01-01T10:00:00Z", "Included");
+        let usage = create_test_record("2024-01-02T10:00:00Z", "Usage-based");
+        store.insert_batch(DEFAULT_DATASET_ID, &[included, usage.clone()]).unwrap();
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+        writer.write(&[usage]).unwrap();
+        let query = UsageQuery {
+            kind: Some("Usage-based".to_string()),
+            ..Default::default()
+        };
+        let prior = writer.prior_records(&query).unwrap();
+        assert_eq!(prior.len(), 1);
+        assert_eq!(prior[0].date, "2024-01-02T10:00:00Z");
+    }
+}
@@ -1,7 +1,270 @@
-use std::sync::{Arc, Mutex};
-use crate::models::usage_data::UsageData;
+pub mod memory;
+pub mod sqlite;
 
-// Shared in-memory storage for uploaded data
-lazy_static::lazy_static! {
-    pub static ref UPLOADED_DATA: Arc<Mutex<Vec<UsageData>>> = Arc::new(Mutex::new(Vec::new()));
-}
\ No newline at end of file
+use crate::models::{
+    billing::BillingCycleConfig,
+    budget::Budget,
+    dataset::Dataset,
+    import::ImportRecord,
+    usage_data::UsageData,
+    webhook::{Webhook, WebhookDelivery},
+};
+use crate::services::record_identity::{RecordFingerprint, StoredCounts};
+use crate::utils::date_utils::to_local;
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+use memory::MemoryStore;
+use sqlite::SqliteStore;
+use std::sync::Arc;
+
+const DEFAULT_DATABASE_PATH: &str = "data/usage.db";
+
+/// Filter applied when reading records back from a store
+#[derive(Debug, Clone, Default)]
+pub struct UsageQuery {
+    /// Inclusive first day, compared against the record's calendar date
+    pub start_date: Option<NaiveDate>,
+    /// Inclusive last day, compared against the record's calendar date
+    pub end_date: Option<NaiveDate>,
+    pub model: Option<String>,
+    pub kind: Option<String>,
+    pub user: Option<String>,
+    /// Timezone whose calendar dates `start_date` and `end_date` refer to; when unset,
+    /// each record's date is taken in its own UTC offset
+    pub timezone: Option<Tz>,
+}
+
+impl UsageQuery {
+    /// Whether the query places no restriction on the records returned
+    pub fn is_unfiltered(&self) -> bool {
+        self.start_date.is_none()
+            && self.end_date.is_none()
+            && self.model.is_none()
+            && self.kind.is_none()
+            && self.user.is_none()
+    }
+
+    /// Check a single record against every filter in the query
+    /// Requirements: 10.1, 10.2, 10.5
+    pub fn matches(&self, usage: &UsageData) -> bool {
+        if let Some(ref model) = self.model {
+            if &usage.model != model {
+                return false;
+            }
+        }
+
+        if let Some(ref kind) = self.kind {
+            if &usage.kind != kind {
+                return false;
+            }
+        }
+
+        if let Some(ref user) = self.user {
+            if usage.user.as_ref() != Some(user) {
+                return false;
+            }
+        }
+
+        if self.start_date.is_none() && self.end_date.is_none() {
+            return true;
+        }
+
+        // Records with unparseable dates never fall inside a date range
+        let usage_date = match self.timezone {
+            Some(timezone) => match to_local(&usage.date, timezone) {
+                Some(dt) => dt.date_naive(),
+                None => return false,
+            },
+            None => match DateTime::parse_from_rfc3339(&usage.date) {
+                Ok(dt) => dt.date_naive(),
+                Err(_) => return false,
+            },
+        };
+
+        if let Some(start) = self.start_date {
+            if usage_date < start {
+                return false;
+            }
+        }
+
+        if let Some(end) = self.end_date {
+            if usage_date > end {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// How a streamed write combines with the records already in a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    Replace,
+    Append,
+}
+
+/// Transactional writer returned by `UsageStore::begin_write`. Records only become
+/// visible once `commit` succeeds; dropping the writer discards everything written.
+/// A writer may hold the store until it is dropped, so it should only be fed from
+/// local data, never while waiting on a client.
+pub trait BatchWriter {
+    fn write(&mut self, records: &[UsageData]) -> Result<(), String>;
+
+    /// How many records identical to each of `fingerprints` the dataset holds, as seen by
+    /// this write, including what was written through it so far; fingerprints with none
+    /// are left out. Writes are serialized, so two appends cannot both miss each other's
+    /// records.
+    fn stored_counts(&mut self, fingerprints: &[RecordFingerprint]) -> Result<StoredCounts, String>;
+
+    /// Imports the dataset held when the write began
+    fn prior_imports(&self) -> &[ImportRecord];
+
+    /// Records matching `query` that the dataset held when the write began, leaving out
+    /// what was written through it; in replace mode these are the records being replaced
+    fn prior_records(&mut self, query: &UsageQuery) -> Result<Vec<UsageData>, String>;
+
+    /// Record the upload being written; it is stored together with the records on commit.
+    /// `skipped` counts the rows it left out as already stored, so that deleting the
+    /// import that stored them can hand them over instead.
+    fn record_import(&mut self, import: &ImportRecord, skipped: &StoredCounts) -> Result<(), String>;
+
+    fn commit(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Persistence backend for uploaded usage data, partitioned into named datasets
+pub trait UsageStore: Send + Sync {
+    /// All datasets ordered by creation time
+    fn list_datasets(&self) -> Result<Vec<Dataset>, String>;
+
+    /// Look up a single dataset
+    fn get_dataset(&self, dataset_id: &str) -> Result<Option<Dataset>, String>;
+
+    /// Create an empty dataset with an identifier derived from its name
+    fn create_dataset(&self, name: &str) -> Result<Dataset, String>;
+
+    /// Change a dataset's display name; its identifier is kept
+    fn rename_dataset(&self, dataset_id: &str, name: &str) -> Result<Option<Dataset>, String>;
+
+    /// Change how a dataset's usage is split into billing cycles
+    fn set_billing_cycle(&self, dataset_id: &str, config: &BillingCycleConfig) -> Result<Option<Dataset>, String>;
+
+    /// Delete a dataset together with its records, returning whether it existed
+    fn delete_dataset(&self, dataset_id: &str) -> Result<bool, String>;
+
+    /// Append records after the ones already stored in the dataset
+    fn insert_batch(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String>;
+
+    /// Replace all records in the dataset with the given records
+    fn replace_all(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String>;
+
+    /// Load the dataset's records matching `query` in insertion order
+    fn query(&self, dataset_id: &str, query: &UsageQuery) -> Result<Vec<UsageData>, String>;
+
+    /// Start a transactional write so large uploads can be stored batch by batch.
+    /// In replace mode the dataset's earlier imports are discarded with its records.
+    fn begin_write(&self, dataset_id: &str, mode: WriteMode) -> Result<Box<dyn BatchWriter + '_>, String>;
+
+    /// Number of records stored in the dataset
+    fn count(&self, dataset_id: &str) -> Result<usize, String>;
+
+    /// Remove every record from the dataset
+    fn clear(&self, dataset_id: &str) -> Result<(), String>;
+
+    /// Imports recorded for the dataset, oldest first
+    fn list_imports(&self, dataset_id: &str) -> Result<Vec<ImportRecord>, String>;
+
+    /// Delete an import together with the records it stored, returning how many
+    /// records were removed, or `None` if the dataset has no such import. Records a later
+    /// import skipped as duplicates of these are kept and handed over to it.
+    fn delete_import(&self, dataset_id: &str, import_id: &str) -> Result<Option<usize>, String>;
+
+    /// Budgets defined on the dataset, oldest first
+    fn list_budgets(&self, dataset_id: &str) -> Result<Vec<Budget>, String>;
+
+    /// Store a budget, replacing the dataset's budget with the same id
+    fn save_budget(&self, dataset_id: &str, budget: &Budget) -> Result<(), String>;
+
+    /// Delete a budget, returning whether the dataset had it
+    fn delete_budget(&self, dataset_id: &str, budget_id: &str) -> Result<bool, String>;
+
+    /// Webhooks notified of the dataset's events, oldest first
+    fn list_webhooks(&self, dataset_id: &str) -> Result<Vec<Webhook>, String>;
+
+    /// Store a webhook, replacing the dataset's webhook with the same id
+    fn save_webhook(&self, dataset_id: &str, webhook: &Webhook) -> Result<(), String>;
+
+    /// Delete a webhook, returning whether the dataset had it; its deliveries stay logged
+    fn delete_webhook(&self, dataset_id: &str, webhook_id: &str) -> Result<bool, String>;
+
+    /// Add a finished delivery to the dataset's delivery log
+    fn record_delivery(&self, dataset_id: &str, delivery: &WebhookDelivery) -> Result<(), String>;
+
+    /// The dataset's `limit` most recent deliveries, newest first
+    fn list_deliveries(&self, dataset_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, String>;
+
+    /// Load all of the dataset's records in insertion order
+    fn load_all(&self, dataset_id: &str) -> Result<Vec<UsageData>, String> {
+        self.query(dataset_id, &UsageQuery::default())
+    }
+}
+
+/// Derive a URL-safe dataset identifier from a display name, adding a numeric
+/// suffix until `is_taken` no longer reports a collision
+pub fn generate_dataset_id(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let base = if slug.is_empty() { "dataset" } else { slug };
+
+    if !is_taken(base) {
+        return base.to_string();
+    }
+
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| !is_taken(candidate))
+        .unwrap()
+}
+
+/// Validate and normalize a dataset display name
+pub fn validate_dataset_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Dataset name must not be empty".to_string());
+    }
+    if name.chars().count() > 100 {
+        return Err("Dataset name must be at most 100 characters".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Path of the SQLite database file, configurable through `DATABASE_PATH`
+pub fn database_path() -> String {
+    std::env::var("DATABASE_PATH").unwrap_or_else(|_| DEFAULT_DATABASE_PATH.to_string())
+}
+
+/// Open the store selected by `STORAGE_BACKEND` (`sqlite` by default, or `memory`)
+pub fn open_from_env() -> Result<Arc<dyn UsageStore>, String> {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "sqlite".to_string());
+
+    match backend.to_lowercase().as_str() {
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        "sqlite" => {
+            let path = database_path();
+            let store = SqliteStore::open(&path)
+                .map_err(|e| format!("Failed to open database at {}: {}", path, e))?;
+            Ok(Arc::new(store))
+        }
+        other => Err(format!(
+            "Unknown STORAGE_BACKEND '{}'. Expected 'sqlite' or 'memory'",
+            other
+        )),
+    }
+}
@@ -0,0 +1,1574 @@
+use crate::models::billing::BillingCycleConfig;
+use crate::models::budget::{Budget, BudgetPeriod, BudgetScope};
+use crate::models::webhook::{Webhook, WebhookDelivery, WebhookEventKind, WebhookFormat};
+use crate::models::dataset::Dataset;
+use crate::models::import::ImportRecord;
+use crate::models::usage_data::{DateRange, UsageData};
+use crate::storage::{
+    generate_dataset_id, validate_dataset_name, BatchWriter, UsageQuery, UsageStore, WriteMode,
+};
+use crate::services::record_identity::{fingerprint, hand_over, ImportClaim, RecordFingerprint, StoredCounts};
+use chrono::Days;
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Schema migrations, applied in order and tracked through `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    // 1: initial usage record table
+    "CREATE TABLE usage_records (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        date TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        model TEXT NOT NULL,
+        max_mode INTEGER NOT NULL,
+        input_with_cache INTEGER NOT NULL,
+        input_without_cache INTEGER NOT NULL,
+        cache_read INTEGER NOT NULL,
+        output_tokens INTEGER NOT NULL,
+        total_tokens INTEGER NOT NULL,
+        cost REAL NOT NULL
+    );
+    CREATE INDEX idx_usage_records_date ON usage_records(date);",
+    // 2: named datasets; existing records move into the default dataset
+    "CREATE TABLE datasets (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    INSERT INTO datasets (id, name, created_at)
+        VALUES ('default', 'Default', strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));
+    ALTER TABLE usage_records ADD COLUMN dataset_id TEXT NOT NULL DEFAULT 'default';
+    CREATE INDEX idx_usage_records_dataset ON usage_records(dataset_id, id);",
+    // 3: user email and request count columns from team-admin exports
+    "ALTER TABLE usage_records ADD COLUMN user TEXT;
+    ALTER TABLE usage_records ADD COLUMN requests REAL NOT NULL DEFAULT 1;",
+    // 4: one row per stored upload, keyed by content hash to detect re-uploads
+    "CREATE TABLE imports (
+        id TEXT PRIMARY KEY,
+        dataset_id TEXT NOT NULL,
+        file_name TEXT NOT NULL,
+        content_hash TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        record_count INTEGER NOT NULL
+    );
+    CREATE INDEX idx_imports_dataset ON imports(dataset_id, content_hash);",
+    // 5: tag records with the import that stored them, and record each import's date span
+    "ALTER TABLE usage_records ADD COLUMN import_id TEXT;
+    CREATE INDEX idx_usage_records_import ON usage_records(import_id);
+    ALTER TABLE imports ADD COLUMN start_date TEXT;
+    ALTER TABLE imports ADD COLUMN end_date TEXT;",
+    // 6: per-dataset billing cycle anchor day and timezone
+    "ALTER TABLE datasets ADD COLUMN billing_anchor_day INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE datasets ADD COLUMN billing_timezone TEXT;",
+    // 7: spend budgets; thresholds are stored as comma-separated percentages
+    "CREATE TABLE budgets (
+        id TEXT PRIMARY KEY,
+        dataset_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        amount REAL NOT NULL,
+        period TEXT NOT NULL,
+        model TEXT,
+        kind TEXT,
+        user TEXT,
+        thresholds TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_budgets_dataset ON budgets(dataset_id);",
+    // 8: outbound webhooks and the log of their deliveries
+    "CREATE TABLE webhooks (
+        id TEXT PRIMARY KEY,
+        dataset_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        format TEXT NOT NULL,
+        secret TEXT,
+        events TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_webhooks_dataset ON webhooks(dataset_id);
+    CREATE TABLE webhook_deliveries (
+        id TEXT PRIMARY KEY,
+        dataset_id TEXT NOT NULL,
+        webhook_id TEXT NOT NULL,
+        event_id TEXT NOT NULL,
+        event TEXT NOT NULL,
+        url TEXT NOT NULL,
+        success INTEGER NOT NULL,
+        attempts INTEGER NOT NULL,
+        status_code INTEGER,
+        error TEXT,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_webhook_deliveries_dataset ON webhook_deliveries(dataset_id, created_at);",
+    // 9: record fingerprints so appends can be deduplicated without loading the dataset;
+    // existing rows are filled in by `backfill_fingerprints`
+    "ALTER TABLE usage_records ADD COLUMN fingerprint BLOB;
+    CREATE INDEX idx_usage_records_fingerprint ON usage_records(dataset_id, fingerprint);",
+    // 10: rows each import skipped as already stored, per fingerprint
+    "CREATE TABLE import_duplicates (
+        import_id TEXT NOT NULL,
+        dataset_id TEXT NOT NULL,
+        fingerprint BLOB NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (import_id, fingerprint)
+    );
+    CREATE INDEX idx_import_duplicates_fingerprint ON import_duplicates(dataset_id, fingerprint);",
+];
+
+/// Migration adding `usage_records.fingerprint`, whose values are computed in Rust
+const FINGERPRINT_MIGRATION: usize = 9;
+
+/// SQLite-backed store so uploaded data survives restarts
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database file at `path` and apply pending migrations
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    format!("Error creating database directory {}: {}", parent.display(), e)
+                })?;
+            }
+        }
+
+        let conn = Connection::open(path).map_err(|e| format!("Error opening database: {}", e))?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| format!("Error configuring database: {}", e))?;
+        Self::from_connection(conn)
+    }
+
+    /// Open a private in-memory database, mainly useful for tests
+    pub fn open_in_memory() -> Result<Self, String> {
+        let conn = Connection::open_in_memory().map_err(|e| format!("Error opening database: {}", e))?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, String> {
+        Self::migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Apply every migration newer than the database's recorded schema version
+    fn migrate(conn: &mut Connection) -> Result<(), String> {
+        let current: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|e| format!("Error reading schema version: {}", e))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+            let tx = conn
+                .transaction()
+                .map_err(|e| format!("Error starting migration {}: {}", index + 1, e))?;
+            tx.execute_batch(migration)
+                .map_err(|e| format!("Error applying migration {}: {}", index + 1, e))?;
+            if index + 1 == FINGERPRINT_MIGRATION {
+                backfill_fingerprints(&tx)?;
+            }
+            tx.pragma_update(None, "user_version", index + 1)
+                .map_err(|e| format!("Error recording migration {}: {}", index + 1, e))?;
+            tx.commit()
+                .map_err(|e| format!("Error committing migration {}: {}", index + 1, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Current schema version of the database
+    pub fn schema_version(&self) -> Result<usize, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|e| format!("Error reading schema version: {}", e))
+    }
+}
+
+const INSERT_SQL: &str = "INSERT INTO usage_records (
+        dataset_id, date, kind, model, max_mode, input_with_cache, input_without_cache,
+        cache_read, output_tokens, total_tokens, cost, user, requests, import_id, fingerprint
+    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)";
+
+const SELECT_SQL: &str = "SELECT date, kind, model, max_mode, input_with_cache, input_without_cache,
+        cache_read, output_tokens, total_tokens, cost, user, requests, import_id
+    FROM usage_records";
+
+const SELECT_DATASET_SQL: &str = "SELECT d.id, d.name, d.created_at,
+        (SELECT COUNT(*) FROM usage_records r WHERE r.dataset_id = d.id),
+        d.billing_anchor_day, d.billing_timezone
+    FROM datasets d";
+
+const SELECT_IMPORT_SQL: &str = "SELECT id, file_name, content_hash, created_at, record_count, start_date, end_date
+    FROM imports";
+
+fn insert_records(conn: &Connection, dataset_id: &str, records: &[UsageData]) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare_cached(INSERT_SQL)
+        .map_err(|e| format!("Error preparing insert: {}", e))?;
+
+    for record in records {
+        stmt.execute(params![
+            dataset_id,
+            record.date,
+            record.kind,
+            record.model,
+            record.max_mode,
+            record.input_with_cache,
+            record.input_without_cache,
+            record.cache_read,
+            record.output_tokens,
+            record.total_tokens,
+            record.cost,
+            record.user,
+            record.requests,
+            record.import_id,
+            fingerprint(record),
+        ])
+        .map_err(|e| format!("Error inserting record: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Fill in the fingerprint of every record stored before fingerprints were recorded
+fn backfill_fingerprints(conn: &Connection) -> Result<(), String> {
+    let mut select = conn
+        .prepare(
+            "SELECT date, kind, model, max_mode, input_with_cache, input_without_cache,
+                cache_read, output_tokens, total_tokens, cost, user, requests, import_id, id
+            FROM usage_records WHERE fingerprint IS NULL",
+        )
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+    let mut update = conn
+        .prepare("UPDATE usage_records SET fingerprint = ?1 WHERE id = ?2")
+        .map_err(|e| format!("Error preparing update: {}", e))?;
+
+    let rows = select
+        .query_map([], |row| Ok((row_to_usage(row)?, row.get::<_, i64>(13)?)))
+        .map_err(|e| format!("Error querying data: {}", e))?;
+    for row in rows {
+        let (usage, id) = row.map_err(|e| format!("Error reading stored data: {}", e))?;
+        update
+            .execute(params![fingerprint(&usage), id])
+            .map_err(|e| format!("Error fingerprinting stored data: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// How many of the dataset's records match each of `fingerprints`; fingerprints with
+/// no stored records are left out
+/// Counts only rows inserted after the one with id `after_id`
+fn count_fingerprints(
+    conn: &Connection,
+    dataset_id: &str,
+    fingerprints: &[RecordFingerprint],
+    after_id: i64,
+) -> Result<StoredCounts, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT COUNT(*) FROM usage_records WHERE dataset_id = ?1 AND fingerprint = ?2 AND id > ?3",
+        )
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+
+    let mut counts = StoredCounts::new();
+    for fingerprint in fingerprints {
+        let count: u32 = stmt
+            .query_row(params![dataset_id, fingerprint, after_id], |row| row.get(0))
+            .map_err(|e| format!("Error counting stored data: {}", e))?;
+        if count > 0 {
+            counts.insert(*fingerprint, count);
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Records of a dataset matching `query`, in insertion order; `through_id` leaves out
+/// rows inserted after the one with that id
+fn query_records(
+    conn: &Connection,
+    dataset_id: &str,
+    query: &UsageQuery,
+    through_id: Option<i64>,
+) -> Result<Vec<UsageData>, String> {
+    // Narrow the scan in SQL, then apply the exact date semantics in Rust
+    let mut conditions = vec!["dataset_id = ?1".to_string()];
+    let mut values: Vec<Value> = vec![Value::Text(dataset_id.to_string())];
+
+    if let Some(ref model) = query.model {
+        values.push(Value::Text(model.clone()));
+        conditions.push(format!("model = ?{}", values.len()));
+    }
+    if let Some(ref kind) = query.kind {
+        values.push(Value::Text(kind.clone()));
+        conditions.push(format!("kind = ?{}", values.len()));
+    }
+    if let Some(ref user) = query.user {
+        values.push(Value::Text(user.clone()));
+        conditions.push(format!("user = ?{}", values.len()));
+    }
+    // The SQL filter can only compare the date as written, in the record's own UTC
+    // offset; `matches` then checks the exact date in the query's timezone. Both
+    // offsets lie between -12:00 and +14:00, so the two wall clocks of one instant are
+    // at most 26 hours apart, which moves the calendar date by at most two days
+    // (23:00 at -12:00 is already 01:00 two days later at +14:00).
+    let slack = if query.timezone.is_some() { Days::new(2) } else { Days::new(0) };
+    if let Some(start) = query.start_date.and_then(|start| start.checked_sub_days(slack)) {
+        values.push(Value::Text(start.format("%Y-%m-%d").to_string()));
+        conditions.push(format!("substr(date, 1, 10) >= ?{}", values.len()));
+    }
+    if let Some(end) = query.end_date.and_then(|end| end.checked_add_days(slack)) {
+        values.push(Value::Text(end.format("%Y-%m-%d").to_string()));
+        conditions.push(format!("substr(date, 1, 10) <= ?{}", values.len()));
+    }
+    if let Some(id) = through_id {
+        values.push(Value::Integer(id));
+        conditions.push(format!("id <= ?{}", values.len()));
+    }
+
+    let sql = format!("{} WHERE {} ORDER BY id", SELECT_SQL, conditions.join(" AND "));
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+
+    let rows = stmt
+        .query_map(params_from_iter(values), row_to_usage)
+        .map_err(|e| format!("Error querying stored data: {}", e))?;
+
+    let mut records = Vec::new();
+    for row in rows {
+        let usage = row.map_err(|e| format!("Error reading stored data: {}", e))?;
+        if query.matches(&usage) {
+            records.push(usage);
+        }
+    }
+
+    Ok(records)
+}
+
+fn row_to_usage(row: &Row) -> rusqlite::Result<UsageData> {
+    Ok(UsageData {
+        date: row.get(0)?,
+        kind: row.get(1)?,
+        model: row.get(2)?,
+        max_mode: row.get(3)?,
+        input_with_cache: row.get(4)?,
+        input_without_cache: row.get(5)?,
+        cache_read: row.get(6)?,
+        output_tokens: row.get(7)?,
+        total_tokens: row.get(8)?,
+        cost: row.get(9)?,
+        user: row.get(10)?,
+        requests: row.get(11)?,
+        import_id: row.get(12)?,
+    })
+}
+
+fn row_to_import(row: &Row) -> rusqlite::Result<ImportRecord> {
+    Ok(ImportRecord {
+        id: row.get(0)?,
+        file_name: row.get(1)?,
+        content_hash: row.get(2)?,
+        created_at: row.get(3)?,
+        record_count: row.get(4)?,
+        date_range: match (row.get(5)?, row.get(6)?) {
+            (Some(start), Some(end)) => Some(DateRange { start, end }),
+            _ => None,
+        },
+    })
+}
+
+fn load_imports(conn: &Connection, dataset_id: &str) -> Result<Vec<ImportRecord>, String> {
+    let mut stmt = conn
+        .prepare_cached(&format!("{} WHERE dataset_id = ?1 ORDER BY created_at, rowid", SELECT_IMPORT_SQL))
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+
+    let rows = stmt
+        .query_map([dataset_id], row_to_import)
+        .map_err(|e| format!("Error querying imports: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Error reading imports: {}", e))
+}
+
+/// Claims of the dataset's recorded imports on records with `fingerprint`, oldest
+/// import first; imports that skipped none of them are left out
+fn import_claims(
+    conn: &Connection,
+    dataset_id: &str,
+    fingerprint: &RecordFingerprint,
+) -> Result<Vec<ImportClaim>, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT d.import_id, d.count,
+                (SELECT COUNT(*) FROM usage_records r
+                    WHERE r.dataset_id = d.dataset_id AND r.import_id = d.import_id AND r.fingerprint = d.fingerprint)
+            FROM import_duplicates d JOIN imports i ON i.id = d.import_id
+            WHERE d.dataset_id = ?1 AND d.fingerprint = ?2
+            ORDER BY i.created_at, i.rowid",
+        )
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+
+    let rows = stmt
+        .query_map(params![dataset_id, fingerprint], |row| {
+            Ok(ImportClaim {
+                import_id: row.get(0)?,
+                skipped: row.get(1)?,
+                stored: row.get(2)?,
+            })
+        })
+        .map_err(|e| format!("Error querying imports: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Error reading imports: {}", e))
+}
+
+/// Move `count` of the records with `fingerprint` from the import `from` to `import`,
+/// which skipped them when it was stored
+fn hand_over_records(
+    conn: &Connection,
+    dataset_id: &str,
+    from: &str,
+    fingerprint: &RecordFingerprint,
+    import: &mut ImportRecord,
+    count: u32,
+) -> Result<(), String> {
+    let records = conn
+        .prepare_cached(
+            "SELECT id, date FROM usage_records
+            WHERE dataset_id = ?1 AND import_id = ?2 AND fingerprint = ?3 ORDER BY id LIMIT ?4",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![dataset_id, from, fingerprint, count], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| format!("Error reading import records: {}", e))?;
+
+    let mut update = conn
+        .prepare_cached("UPDATE usage_records SET import_id = ?1 WHERE id = ?2")
+        .map_err(|e| format!("Error preparing query: {}", e))?;
+    for (id, date) in &records {
+        update
+            .execute(params![import.id, id])
+            .map_err(|e| format!("Error moving import records: {}", e))?;
+        DateRange::include(&mut import.date_range, date);
+    }
+    import.record_count += records.len();
+
+    conn.execute(
+        "UPDATE import_duplicates SET count = count - ?1 WHERE import_id = ?2 AND fingerprint = ?3",
+        params![count, import.id, fingerprint],
+    )
+    .and_then(|_| {
+        conn.execute(
+            "DELETE FROM import_duplicates WHERE import_id = ?1 AND fingerprint = ?2 AND count <= 0",
+            params![import.id, fingerprint],
+        )
+    })
+    .map_err(|e| format!("Error updating import duplicates: {}", e))?;
+    conn.execute(
+        "UPDATE imports SET record_count = ?1, start_date = ?2, end_date = ?3 WHERE id = ?4",
+        params![
+            import.record_count,
+            import.date_range.as_ref().map(|range| &range.start),
+            import.date_range.as_ref().map(|range| &range.end),
+            import.id,
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Error updating import: {}", e))
+}
+
+const SELECT_BUDGET_SQL: &str = "SELECT id, name, amount, period, model, kind, user, thresholds, created_at
+    FROM budgets";
+
+fn row_to_budget(row: &Row) -> rusqlite::Result<Budget> {
+    let period: String = row.get(3)?;
+    let thresholds: String = row.get(7)?;
+    Ok(Budget {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        amount: row.get(2)?,
+        period: BudgetPeriod::parse(&period).map_err(|e| invalid_column(3, e))?,
+        scope: BudgetScope {
+            model: row.get(4)?,
+            kind: row.get(5)?,
+            user: row.get(6)?,
+        },
+        thresholds: thresholds
+            .split(',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().map_err(|e| invalid_column(7, format!("{}", e))))
+            .collect::<Result<_, _>>()?,
+        created_at: row.get(8)?,
+    })
+}
+
+const SELECT_WEBHOOK_SQL: &str = "SELECT id, url, format, secret, events, created_at FROM webhooks";
+
+const SELECT_DELIVERY_SQL: &str = "SELECT id, webhook_id, event_id, event, url, success, attempts,
+        status_code, error, created_at
+    FROM webhook_deliveries";
+
+fn invalid_column(index: usize, e: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, e.into())
+}
+
+fn row_to_webhook(row: &Row) -> rusqlite::Result<Webhook> {
+    let format: String = row.get(2)?;
+    let events: String = row.get(4)?;
+    Ok(Webhook {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        format: WebhookFormat::parse(&format).map_err(|e| invalid_column(2, e))?,
+        secret: row.get(3)?,
+        events: events
+            .split(',')
+            .filter(|value| !value.is_empty())
+            .map(|value| WebhookEventKind::parse(value).map_err(|e| invalid_column(4, e)))
+            .collect::<Result<_, _>>()?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn row_to_delivery(row: &Row) -> rusqlite::Result<WebhookDelivery> {
+    let event: String = row.get(3)?;
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        webhook_id: row.get(1)?,
+        event_id: row.get(2)?,
+        event: WebhookEventKind::parse(&event).map_err(|e| invalid_column(3, e))?,
+        url: row.get(4)?,
+        success: row.get(5)?,
+        attempts: row.get(6)?,
+        status_code: row.get(7)?,
+        error: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+fn row_to_dataset(row: &Row) -> rusqlite::Result<Dataset> {
+    Ok(Dataset {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        record_count: row.get(3)?,
+        billing_cycle: BillingCycleConfig {
+            anchor_day: row.get(4)?,
+            timezone: row.get(5)?,
+        },
+    })
+}
+
+fn find_dataset(conn: &Connection, dataset_id: &str) -> Result<Option<Dataset>, String> {
+    conn.query_row(
+        &format!("{} WHERE d.id = ?1", SELECT_DATASET_SQL),
+        [dataset_id],
+        row_to_dataset,
+    )
+    .optional()
+    .map_err(|e| format!("Error reading dataset: {}", e))
+}
+
+fn ensure_dataset(conn: &Connection, dataset_id: &str) -> Result<(), String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM datasets WHERE id = ?1)",
+            [dataset_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Error reading dataset: {}", e))?;
+
+    if exists {
+        Ok(())
+    } else {
+        Err(format!("Dataset '{}' not found", dataset_id))
+    }
+}
+
+/// Writes into an open transaction on the store's connection; the connection stays
+/// locked until the writer is committed or dropped
+struct SqliteBatchWriter<'a> {
+    conn: MutexGuard<'a, Connection>,
+    dataset_id: String,
+    mode: WriteMode,
+    /// Highest record id when the write began; rows up to it are the prior records,
+    /// which replace mode deletes on commit
+    prior_max_id: i64,
+    prior_imports: Vec<ImportRecord>,
+    finished: bool,
+}
+
+impl BatchWriter for SqliteBatchWriter<'_> {
+    fn write(&mut self, records: &[UsageData]) -> Result<(), String> {
+        insert_records(&self.conn, &self.dataset_id, records)
+    }
+
+    fn stored_counts(&mut self, fingerprints: &[RecordFingerprint]) -> Result<StoredCounts, String> {
+        let after_id = match self.mode {
+            WriteMode::Append => 0,
+            WriteMode::Replace => self.prior_max_id,
+        };
+        count_fingerprints(&self.conn, &self.dataset_id, fingerprints, after_id)
+    }
+
+    fn prior_imports(&self) -> &[ImportRecord] {
+        &self.prior_imports
+    }
+
+    fn prior_records(&mut self, query: &UsageQuery) -> Result<Vec<UsageData>, String> {
+        query_records(&self.conn, &self.dataset_id, query, Some(self.prior_max_id))
+    }
+
+    fn record_import(&mut self, import: &ImportRecord, skipped: &StoredCounts) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "INSERT INTO import_duplicates (import_id, dataset_id, fingerprint, count) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .map_err(|e| format!("Error preparing insert: {}", e))?;
+        for (fingerprint, count) in skipped {
+            stmt.execute(params![import.id, self.dataset_id, fingerprint, count])
+                .map_err(|e| format!("Error recording import duplicates: {}", e))?;
+        }
+        drop(stmt);
+
+        self.conn
+            .execute(
+                "INSERT INTO imports (
+                    id, dataset_id, file_name, content_hash, created_at, record_count, start_date, end_date
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    import.id,
+                    self.dataset_id,
+                    import.file_name,
+                    import.content_hash,
+                    import.created_at,
+                    import.record_count,
+                    import.date_range.as_ref().map(|range| &range.start),
+                    import.date_range.as_ref().map(|range| &range.end),
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Error recording import: {}", e))
+    }
+
+    fn commit(mut self: Box<Self>) -> Result<(), String> {
+        if self.mode == WriteMode::Replace {
+            self.conn
+                .execute(
+                    "DELETE FROM usage_records WHERE dataset_id = ?1 AND id <= ?2",
+                    params![self.dataset_id, self.prior_max_id],
+                )
+                .map_err(|e| format!("Error clearing stored data: {}", e))?;
+        }
+        self.conn
+            .execute_batch("COMMIT")
+            .map_err(|e| format!("Error committing data: {}", e))?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteBatchWriter<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl UsageStore for SqliteStore {
+    fn list_datasets(&self) -> Result<Vec<Dataset>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!("{} ORDER BY d.created_at, d.rowid", SELECT_DATASET_SQL))
+            .map_err(|e| format!("Error preparing query: {}", e))?;
+
+        let rows = stmt
+            .query_map([], row_to_dataset)
+            .map_err(|e| format!("Error querying datasets: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Error reading datasets: {}", e))
+    }
+
+    fn get_dataset(&self, dataset_id: &str) -> Result<Option<Dataset>, String> {
+        let conn = self.conn.lock().unwrap();
+        find_dataset(&conn, dataset_id)
+    }
+
+    fn create_dataset(&self, name: &str) -> Result<Dataset, String> {
+        let name = validate_dataset_name(name)?;
+        let conn = self.conn.lock().unwrap();
+
+        let mut taken = conn
+            .prepare("SELECT id FROM datasets")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| format!("Error reading datasets: {}", e))?;
+        taken.sort_unstable();
+
+        let id = generate_dataset_id(&name, |candidate| {
+            taken.binary_search_by(|existing| existing.as_str().cmp(candidate)).is_ok()
+        });
+        let created_at = chrono::Utc::now().to_rfc3339();
+
+        conn.execute(
+            "INSERT INTO datasets (id, name, created_at) VALUES (?1, ?2, ?3)",
+            params![id, name, created_at],
+        )
+        .map_err(|e| format!("Error creating dataset: {}", e))?;
+
+        Ok(Dataset {
+            id,
+            name,
+            created_at,
+            record_count: 0,
+            billing_cycle: BillingCycleConfig::default(),
+        })
+    }
+
+    fn rename_dataset(&self, dataset_id: &str, name: &str) -> Result<Option<Dataset>, String> {
+        let name = validate_dataset_name(name)?;
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE datasets SET name = ?1 WHERE id = ?2",
+            params![name, dataset_id],
+        )
+        .map_err(|e| format!("Error renaming dataset: {}", e))?;
+
+        find_dataset(&conn, dataset_id)
+    }
+
+    fn set_billing_cycle(&self, dataset_id: &str, config: &BillingCycleConfig) -> Result<Option<Dataset>, String> {
+        config.validate()?;
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE datasets SET billing_anchor_day = ?1, billing_timezone = ?2 WHERE id = ?3",
+            params![config.anchor_day, config.timezone, dataset_id],
+        )
+        .map_err(|e| format!("Error updating billing cycle: {}", e))?;
+
+        find_dataset(&conn, dataset_id)
+    }
+
+    fn delete_dataset(&self, dataset_id: &str) -> Result<bool, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+        tx.execute("DELETE FROM usage_records WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset records: {}", e))?;
+        tx.execute("DELETE FROM imports WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset imports: {}", e))?;
+        tx.execute("DELETE FROM import_duplicates WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset imports: {}", e))?;
+        tx.execute("DELETE FROM budgets WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset budgets: {}", e))?;
+        tx.execute("DELETE FROM webhooks WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset webhooks: {}", e))?;
+        tx.execute("DELETE FROM webhook_deliveries WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset webhook deliveries: {}", e))?;
+        let deleted = tx
+            .execute("DELETE FROM datasets WHERE id = ?1", [dataset_id])
+            .map_err(|e| format!("Error deleting dataset: {}", e))?;
+
+        tx.commit().map_err(|e| format!("Error committing data: {}", e))?;
+        Ok(deleted > 0)
+    }
+
+    fn insert_batch(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+        insert_records(&tx, dataset_id, records)?;
+
+        tx.commit().map_err(|e| format!("Error committing data: {}", e))
+    }
+
+    fn replace_all(&self, dataset_id: &str, records: &[UsageData]) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+        tx.execute("DELETE FROM usage_records WHERE dataset_id = ?1", [dataset_id])
+            .map_err(|e| format!("Error clearing stored data: {}", e))?;
+        insert_records(&tx, dataset_id, records)?;
+
+        tx.commit().map_err(|e| format!("Error committing data: {}", e))
+    }
+
+    fn query(&self, dataset_id: &str, query: &UsageQuery) -> Result<Vec<UsageData>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        query_records(&conn, dataset_id, query, None)
+    }
+
+    fn begin_write(&self, dataset_id: &str, mode: WriteMode) -> Result<Box<dyn BatchWriter + '_>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.execute_batch("BEGIN IMMEDIATE")
+            .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+        let mut writer = SqliteBatchWriter {
+            conn,
+            dataset_id: dataset_id.to_string(),
+            mode,
+            prior_max_id: 0,
+            prior_imports: Vec::new(),
+            finished: false,
+        };
+        writer.prior_max_id = writer
+            .conn
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM usage_records", [], |row| row.get(0))
+            .map_err(|e| format!("Error reading stored data: {}", e))?;
+        writer.prior_imports = load_imports(&writer.conn, dataset_id)?;
+
+        // The replaced records stay readable through `prior_records` until commit
+        if mode == WriteMode::Replace {
+            writer
+                .conn
+                .execute("DELETE FROM imports WHERE dataset_id = ?1", [dataset_id])
+                .map_err(|e| format!("Error clearing imports: {}", e))?;
+            writer
+                .conn
+                .execute("DELETE FROM import_duplicates WHERE dataset_id = ?1", [dataset_id])
+                .map_err(|e| format!("Error clearing imports: {}", e))?;
+        }
+
+        Ok(Box::new(writer))
+    }
+
+    fn count(&self, dataset_id: &str) -> Result<usize, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.query_row(
+            "SELECT COUNT(*) FROM usage_records WHERE dataset_id = ?1",
+            [dataset_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Error counting stored data: {}", e))
+    }
+
+    fn clear(&self, dataset_id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.execute("DELETE FROM usage_records WHERE dataset_id = ?1", [dataset_id])
+            .map(|_| ())
+            .map_err(|e| format!("Error clearing stored data: {}", e))
+    }
+
+    fn list_imports(&self, dataset_id: &str) -> Result<Vec<ImportRecord>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        load_imports(&conn, dataset_id)
+    }
+
+    fn delete_import(&self, dataset_id: &str, import_id: &str) -> Result<Option<usize>, String> {
+        let mut conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+        let mut imports = load_imports(&tx, dataset_id)?;
+        if !imports.iter().any(|import| import.id == import_id) {
+            return Ok(None);
+        }
+        tx.execute(
+            "DELETE FROM imports WHERE dataset_id = ?1 AND id = ?2",
+            params![dataset_id, import_id],
+        )
+        .and_then(|_| {
+            tx.execute(
+                "DELETE FROM import_duplicates WHERE dataset_id = ?1 AND import_id = ?2",
+                params![dataset_id, import_id],
+            )
+        })
+        .map_err(|e| format!("Error deleting import: {}", e))?;
+
+        // Records a remaining import skipped as duplicates of these stay, now owned by it
+        let owned = tx
+            .prepare(
+                "SELECT fingerprint, COUNT(*) FROM usage_records
+                WHERE dataset_id = ?1 AND import_id = ?2 AND fingerprint IS NOT NULL GROUP BY fingerprint",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![dataset_id, import_id], |row| {
+                    Ok((row.get::<_, RecordFingerprint>(0)?, row.get::<_, u32>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| format!("Error reading import records: {}", e))?;
+        for (fingerprint, count) in owned {
+            let claims = import_claims(&tx, dataset_id, &fingerprint)?;
+            if claims.is_empty() {
+                continue;
+            }
+            let remaining: u32 = tx
+                .query_row(
+                    "SELECT COUNT(*) FROM usage_records
+                    WHERE dataset_id = ?1 AND fingerprint = ?2 AND import_id IS NOT ?3",
+                    params![dataset_id, fingerprint, import_id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("Error counting stored data: {}", e))?;
+            if let Some((claim, kept)) = hand_over(count, remaining, &claims) {
+                let Some(import) = imports.iter_mut().find(|import| import.id == claim.import_id) else {
+                    continue;
+                };
+                hand_over_records(&tx, dataset_id, import_id, &fingerprint, import, kept)?;
+            }
+        }
+
+        let deleted = tx
+            .execute(
+                "DELETE FROM usage_records WHERE dataset_id = ?1 AND import_id = ?2",
+                params![dataset_id, import_id],
+            )
+            .map_err(|e| format!("Error deleting import records: {}", e))?;
+
+        tx.commit().map_err(|e| format!("Error committing data: {}", e))?;
+        Ok(Some(deleted))
+    }
+
+    fn list_budgets(&self, dataset_id: &str) -> Result<Vec<Budget>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let mut stmt = conn
+            .prepare_cached(&format!("{} WHERE dataset_id = ?1 ORDER BY created_at, rowid", SELECT_BUDGET_SQL))
+            .map_err(|e| format!("Error preparing query: {}", e))?;
+
+        let rows = stmt
+            .query_map([dataset_id], row_to_budget)
+            .map_err(|e| format!("Error querying budgets: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Error reading budgets: {}", e))
+    }
+
+    fn save_budget(&self, dataset_id: &str, budget: &Budget) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let thresholds: Vec<String> = budget.thresholds.iter().map(u32::to_string).collect();
+
+        conn.execute(
+            "INSERT INTO budgets (
+                id, dataset_id, name, amount, period, model, kind, user, thresholds, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                amount = excluded.amount,
+                period = excluded.period,
+                model = excluded.model,
+                kind = excluded.kind,
+                user = excluded.user,
+                thresholds = excluded.thresholds
+            WHERE budgets.dataset_id = excluded.dataset_id",
+            params![
+                budget.id,
+                dataset_id,
+                budget.name,
+                budget.amount,
+                budget.period.as_str(),
+                budget.scope.model,
+                budget.scope.kind,
+                budget.scope.user,
+                thresholds.join(","),
+                budget.created_at,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Error saving budget: {}", e))
+    }
+
+    fn delete_budget(&self, dataset_id: &str, budget_id: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.execute(
+            "DELETE FROM budgets WHERE dataset_id = ?1 AND id = ?2",
+            params![dataset_id, budget_id],
+        )
+        .map(|deleted| deleted > 0)
+        .map_err(|e| format!("Error deleting budget: {}", e))
+    }
+
+    fn list_webhooks(&self, dataset_id: &str) -> Result<Vec<Webhook>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let mut stmt = conn
+            .prepare_cached(&format!("{} WHERE dataset_id = ?1 ORDER BY created_at, rowid", SELECT_WEBHOOK_SQL))
+            .map_err(|e| format!("Error preparing query: {}", e))?;
+
+        let rows = stmt
+            .query_map([dataset_id], row_to_webhook)
+            .map_err(|e| format!("Error querying webhooks: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Error reading webhooks: {}", e))
+    }
+
+    fn save_webhook(&self, dataset_id: &str, webhook: &Webhook) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let events: Vec<&str> = webhook.events.iter().map(WebhookEventKind::as_str).collect();
+
+        conn.execute(
+            "INSERT INTO webhooks (id, dataset_id, url, format, secret, events, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(id) DO UPDATE SET
+                url = excluded.url,
+                format = excluded.format,
+                secret = excluded.secret,
+                events = excluded.events
+            WHERE webhooks.dataset_id = excluded.dataset_id",
+            params![
+                webhook.id,
+                dataset_id,
+                webhook.url,
+                webhook.format.as_str(),
+                webhook.secret,
+                events.join(","),
+                webhook.created_at,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Error saving webhook: {}", e))
+    }
+
+    fn delete_webhook(&self, dataset_id: &str, webhook_id: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.execute(
+            "DELETE FROM webhooks WHERE dataset_id = ?1 AND id = ?2",
+            params![dataset_id, webhook_id],
+        )
+        .map(|deleted| deleted > 0)
+        .map_err(|e| format!("Error deleting webhook: {}", e))
+    }
+
+    fn record_delivery(&self, dataset_id: &str, delivery: &WebhookDelivery) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        conn.execute(
+            "INSERT INTO webhook_deliveries (
+                id, dataset_id, webhook_id, event_id, event, url, success, attempts, status_code, error, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                delivery.id,
+                dataset_id,
+                delivery.webhook_id,
+                delivery.event_id,
+                delivery.event.as_str(),
+                delivery.url,
+                delivery.success,
+                delivery.attempts,
+                delivery.status_code,
+                delivery.error,
+                delivery.created_at,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Error recording webhook delivery: {}", e))
+    }
+
+    fn list_deliveries(&self, dataset_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, String> {
+        let conn = self.conn.lock().unwrap();
+        ensure_dataset(&conn, dataset_id)?;
+        let mut stmt = conn
+            .prepare_cached(&format!(
+                "{} WHERE dataset_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT ?2",
+                SELECT_DELIVERY_SQL
+            ))
+            .map_err(|e| format!("Error preparing query: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![dataset_id, limit as i64], row_to_delivery)
+            .map_err(|e| format!("Error querying webhook deliveries: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Error reading webhook deliveries: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::budget::BudgetRequest;
+    use crate::models::webhook::WebhookRequest;
+    use crate::models::dataset::DEFAULT_DATASET_ID;
+    use chrono::NaiveDate;
+
+    fn create_test_record(date: &str, model: &str) -> UsageData {
+        UsageData {
+            date: date.to_string(),
+            kind: "Included".to_string(),
+            model: model.to_string(),
+            max_mode: false,
+            input_with_cache: 100,
+            input_without_cache: 50,
+            cache_read: 25,
+            output_tokens: 75,
+            total_tokens: 250,
+            cost: 0.05,
+            user: None,
+            requests: 1.0,
+            import_id: None,
+        }
+    }
+
+    fn create_test_import(file_name: &str, content_hash: &str, record_count: usize) -> ImportRecord {
+        let date_range = DateRange {
+            start: "2024-01-01T10:00:00Z".to_string(),
+            end: "2024-01-02T10:00:00Z".to_string(),
+        };
+        ImportRecord::new(ImportRecord::generate_id(), file_name, content_hash, record_count, Some(date_range))
+    }
+
+    #[test]
+    fn test_migrations_set_schema_version() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_replace_all_and_load() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store
+            .replace_all(DEFAULT_DATASET_ID, &[
+                create_test_record("2024-01-01T10:00:00Z", "auto"),
+                create_test_record("2024-01-01T11:00:00Z", "gpt-4"),
+            ])
+            .unwrap();
+        store
+            .replace_all(DEFAULT_DATASET_ID, &[create_test_record("2024-01-02T10:00:00Z", "gpt-4")])
+            .unwrap();
+
+        let data = store.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].date, "2024-01-02T10:00:00Z");
+        assert_eq!(data[0].model, "gpt-4");
+        assert_eq!(data[0].total_tokens, 250);
+    }
+
+    #[test]
+    fn test_user_and_requests_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut team_record = create_test_record("2024-01-01T10:00:00Z", "auto");
+        team_record.user = Some("alice@example.com".to_string());
+        team_record.requests = 2.5;
+        store
+            .insert_batch(DEFAULT_DATASET_ID, &[team_record, create_test_record("2024-01-01T11:00:00Z", "auto")])
+            .unwrap();
+
+        let alice = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                user: Some("alice@example.com".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].requests, 2.5);
+
+        let data = store.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(data[1].user, None);
+        assert_eq!(data[1].requests, 1.0);
+    }
+
+    #[test]
+    fn test_data_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("usage.db");
+
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store
+                .replace_all(DEFAULT_DATASET_ID, &[create_test_record("2024-01-01T10:00:00Z", "auto")])
+                .unwrap();
+        }
+
+        let reopened = SqliteStore::open(&path).unwrap();
+        let data = reopened.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].model, "auto");
+        assert_eq!(reopened.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_billing_cycle_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let dataset = store.create_dataset("Team").unwrap();
+        assert_eq!(dataset.billing_cycle, BillingCycleConfig::default());
+
+        let config = BillingCycleConfig {
+            anchor_day: 17,
+            timezone: Some("Asia/Tokyo".to_string()),
+        };
+        let updated = store.set_billing_cycle(&dataset.id, &config).unwrap().unwrap();
+        assert_eq!(updated.billing_cycle, config);
+        assert_eq!(store.get_dataset(&dataset.id).unwrap().unwrap().billing_cycle, config);
+
+        let invalid = BillingCycleConfig {
+            anchor_day: 32,
+            timezone: None,
+        };
+        assert!(store.set_billing_cycle(&dataset.id, &invalid).is_err());
+        assert!(store.set_billing_cycle("missing", &config).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_budget_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut budget = Budget::new(BudgetRequest {
+            name: "GPT-4".to_string(),
+            amount: 120.0,
+            period: BudgetPeriod::Weekly,
+            scope: BudgetScope {
+                model: Some("gpt-4".to_string()),
+                ..Default::default()
+            },
+            thresholds: Some(vec![90, 75]),
+        })
+        .unwrap();
+        store.save_budget(DEFAULT_DATASET_ID, &budget).unwrap();
+        assert_eq!(store.list_budgets(DEFAULT_DATASET_ID).unwrap(), vec![budget.clone()]);
+
+        budget.amount = 150.0;
+        store.save_budget(DEFAULT_DATASET_ID, &budget).unwrap();
+        let stored = store.list_budgets(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].amount, 150.0);
+        assert_eq!(stored[0].thresholds, vec![75, 90]);
+
+        let other = store.create_dataset("Other").unwrap();
+        assert!(store.list_budgets(&other.id).unwrap().is_empty());
+        assert!(!store.delete_budget(&other.id, &budget.id).unwrap());
+        assert!(store.delete_budget(DEFAULT_DATASET_ID, &budget.id).unwrap());
+        assert!(store.list_budgets(DEFAULT_DATASET_ID).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_webhooks_and_delivery_log_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let webhook = Webhook::new(WebhookRequest {
+            url: "https://hooks.example.com/usage".to_string(),
+            format: Some(WebhookFormat::Slack),
+            secret: Some("s3cret".to_string()),
+            events: Some(vec![WebhookEventKind::BudgetThreshold]),
+        })
+        .unwrap();
+        store.save_webhook(DEFAULT_DATASET_ID, &webhook).unwrap();
+        assert_eq!(store.list_webhooks(DEFAULT_DATASET_ID).unwrap(), vec![webhook.clone()]);
+
+        let delivery = |id: &str, created_at: &str| WebhookDelivery {
+            id: id.to_string(),
+            webhook_id: webhook.id.clone(),
+            event_id: "event".to_string(),
+            event: WebhookEventKind::BudgetThreshold,
+            url: webhook.url.clone(),
+            success: id == "second",
+            attempts: 2,
+            status_code: Some(503),
+            error: None,
+            created_at: created_at.to_string(),
+        };
+        store
+            .record_delivery(DEFAULT_DATASET_ID, &delivery("first", "2024-01-01T00:00:00Z"))
+            .unwrap();
+        store
+            .record_delivery(DEFAULT_DATASET_ID, &delivery("second", "2024-01-01T00:01:00Z"))
+            .unwrap();
+
+        let log = store.list_deliveries(DEFAULT_DATASET_ID, 1).unwrap();
+        assert_eq!(log, vec![delivery("second", "2024-01-01T00:01:00Z")]);
+
+        assert!(store.delete_webhook(DEFAULT_DATASET_ID, &webhook.id).unwrap());
+        assert!(store.list_webhooks(DEFAULT_DATASET_ID).unwrap().is_empty());
+        assert_eq!(store.list_deliveries(DEFAULT_DATASET_ID, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_query_filters_and_count() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store
+            .insert_batch(DEFAULT_DATASET_ID, &[
+                create_test_record("2024-01-01T10:00:00Z", "auto"),
+                create_test_record("2024-01-02T10:00:00Z", "gpt-4"),
+            ])
+            .unwrap();
+        store
+            .insert_batch(DEFAULT_DATASET_ID, &[create_test_record("2024-01-03T10:00:00Z", "gpt-4")])
+            .unwrap();
+        assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), 3);
+
+        let by_model = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                model: Some("gpt-4".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_model.len(), 2);
+
+        let by_range = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 2),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_range.len(), 1);
+        assert_eq!(by_range[0].date, "2024-01-02T10:00:00Z");
+
+        store.clear(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_timezone_query_reaches_records_written_two_days_off() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store
+            .insert_batch(DEFAULT_DATASET_ID, &[
+                // 01:00 on Jan 2 in Kiritimati (+14:00)
+                create_test_record("2023-12-31T23:00:00-12:00", "auto"),
+                // 11:00 on Jan 1 in Kiritimati
+                create_test_record("2023-12-31T09:00:00-12:00", "gpt-4"),
+                // 22:30 on Jan 1 at -12:00
+                create_test_record("2024-01-03T00:30:00+14:00", "claude"),
+            ])
+            .unwrap();
+
+        let in_kiritimati = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 2),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 2),
+                timezone: Some(chrono_tz::Pacific::Kiritimati),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(in_kiritimati.len(), 1);
+        assert_eq!(in_kiritimati[0].model, "auto");
+
+        let at_minus_twelve = store
+            .query(DEFAULT_DATASET_ID, &UsageQuery {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 1),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 1),
+                timezone: Some(chrono_tz::Etc::GMTPlus12),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(at_minus_twelve.len(), 1);
+        assert_eq!(at_minus_twelve[0].model, "claude");
+    }
+
+    #[test]
+    fn test_dataset_lifecycle() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.list_datasets().unwrap().len(), 1);
+
+        let team = store.create_dataset("Team Export").unwrap();
+        assert_eq!(team.id, "team-export");
+        store
+            .insert_batch(&team.id, &[create_test_record("2024-01-01T10:00:00Z", "auto")])
+            .unwrap();
+        assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), 0);
+
+        let renamed = store.rename_dataset(&team.id, "Platform team").unwrap().unwrap();
+        assert_eq!(renamed.name, "Platform team");
+        assert_eq!(renamed.record_count, 1);
+
+        assert!(store.delete_dataset(&team.id).unwrap());
+        assert!(!store.delete_dataset(&team.id).unwrap());
+        assert!(store.insert_batch(&team.id, &[]).is_err());
+    }
+
+    #[test]
+    fn test_batch_writer_commits_or_rolls_back() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store
+            .replace_all(DEFAULT_DATASET_ID, &[create_test_record("2024-01-01T10:00:00Z", "auto")])
+            .unwrap();
+
+        {
+            let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+            writer.write(&[create_test_record("2024-01-02T10:00:00Z", "gpt-4")]).unwrap();
+            // Dropped without commit
+        }
+        assert_eq!(store.load_all(DEFAULT_DATASET_ID).unwrap()[0].model, "auto");
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        writer.write(&[create_test_record("2024-01-02T10:00:00Z", "gpt-4")]).unwrap();
+        writer.write(&[create_test_record("2024-01-03T10:00:00Z", "gpt-4")]).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_imports_are_recorded_on_commit() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let first = create_test_import("january.csv", "abc", 1);
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        assert!(writer.prior_imports().is_empty());
+        writer.write(&[create_test_record("2024-01-01T10:00:00Z", "auto")]).unwrap();
+        writer.record_import(&first, &StoredCounts::new()).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(store.list_imports(DEFAULT_DATASET_ID).unwrap(), vec![first.clone()]);
+
+        // A rolled back write leaves no import behind
+        {
+            let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+            assert_eq!(writer.prior_imports(), std::slice::from_ref(&first));
+            writer.record_import(&create_test_import("february.csv", "def", 0), &StoredCounts::new()).unwrap();
+        }
+        assert_eq!(store.list_imports(DEFAULT_DATASET_ID).unwrap().len(), 1);
+
+        let second = create_test_import("february.csv", "def", 1);
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+        assert_eq!(writer.prior_imports(), &[first]);
+        writer.record_import(&second, &StoredCounts::new()).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(store.list_imports(DEFAULT_DATASET_ID).unwrap(), vec![second]);
+    }
+
+    #[test]
+    fn test_writer_counts_stored_fingerprints() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let repeated = create_test_record("2024-01-01T10:00:00Z", "auto");
+        let absent = create_test_record("2024-01-02T10:00:00Z", "auto");
+        store.insert_batch(DEFAULT_DATASET_ID, &[repeated.clone(), repeated.clone()]).unwrap();
+        let fingerprints = [fingerprint(&repeated), fingerprint(&absent)];
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        let counts = writer.stored_counts(&fingerprints).unwrap();
+        assert_eq!(counts, StoredCounts::from([(fingerprints[0], 2)]));
+        drop(writer);
+
+        // Replacing starts from an empty dataset
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+        assert!(writer.stored_counts(&fingerprints).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replacing_writer_reads_replaced_records_until_commit() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let old_auto = create_test_record("2024-01-01T10:00:00Z", "auto");
+        let old_gpt = create_test_record("2024-01-02T10:00:00Z", "gpt-4");
+        store.insert_batch(DEFAULT_DATASET_ID, &[old_auto, old_gpt]).unwrap();
+        let new = create_test_record("2024-01-03T10:00:00Z", "auto");
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+        writer.write(std::slice::from_ref(&new)).unwrap();
+        let query = UsageQuery {
+            model: Some("auto".to_string()),
+            ..Default::default()
+        };
+        let prior = writer.prior_records(&query).unwrap();
+        assert_eq!(prior.len(), 1);
+        assert_eq!(prior[0].date, "2024-01-01T10:00:00Z");
+        // Only what this write stored counts towards duplicates
+        let fingerprints = [fingerprint(&new)];
+        assert_eq!(writer.stored_counts(&fingerprints).unwrap()[&fingerprints[0]], 1);
+        writer.commit().unwrap();
+
+        let stored = store.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].date, "2024-01-03T10:00:00Z");
+    }
+
+    #[test]
+    fn test_fingerprint_migration_backfills_stored_records() {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..FINGERPRINT_MIGRATION - 1] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", FINGERPRINT_MIGRATION - 1).unwrap();
+        conn.execute(
+            "INSERT INTO usage_records (
+                date, kind, model, max_mode, input_with_cache, input_without_cache,
+                cache_read, output_tokens, total_tokens, cost
+            ) VALUES ('2024-01-01T10:00:00Z', 'Included', 'auto', 0, 100, 50, 25, 75, 250, 0.05)",
+            [],
+        )
+        .unwrap();
+
+        let store = SqliteStore::from_connection(conn).unwrap();
+        let record = create_test_record("2024-01-01T10:00:00Z", "auto");
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        let counts = writer.stored_counts(&[fingerprint(&record)]).unwrap();
+        assert_eq!(counts.get(&fingerprint(&record)), Some(&1));
+    }
+
+    #[test]
+    fn test_delete_import_removes_its_records() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut imports = Vec::new();
+        for (file_name, date) in [("january.csv", "2024-01-01T10:00:00Z"), ("february.csv", "2024-02-01T10:00:00Z")] {
+            let import = create_test_import(file_name, file_name, 1);
+            let mut record = create_test_record(date, "auto");
+            record.import_id = Some(import.id.clone());
+
+            let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+            writer.write(&[record]).unwrap();
+            writer.record_import(&import, &StoredCounts::new()).unwrap();
+            writer.commit().unwrap();
+            imports.push(import);
+        }
+        assert_eq!(store.list_imports(DEFAULT_DATASET_ID).unwrap(), imports);
+
+        assert_eq!(store.delete_import(DEFAULT_DATASET_ID, &imports[0].id).unwrap(), Some(1));
+        assert_eq!(store.delete_import(DEFAULT_DATASET_ID, &imports[0].id).unwrap(), None);
+
+        let data = store.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].import_id.as_deref(), Some(imports[1].id.as_str()));
+        assert_eq!(store.list_imports(DEFAULT_DATASET_ID).unwrap(), vec![imports[1].clone()]);
+    }
+
+    #[test]
+    fn test_delete_import_hands_skipped_records_over() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let shared = create_test_record("2024-01-01T10:00:00Z", "auto");
+        let first = create_test_import("week1.csv", "abc", 1);
+        let second = create_test_import("week2.csv", "def", 0);
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        let mut record = shared.clone();
+        record.import_id = Some(first.id.clone());
+        writer.write(&[record]).unwrap();
+        writer.record_import(&first, &StoredCounts::new()).unwrap();
+        writer.commit().unwrap();
+
+        let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Append).unwrap();
+        writer.record_import(&second, &StoredCounts::from([(fingerprint(&shared), 1)])).unwrap();
+        writer.commit().unwrap();
+
+        assert_eq!(store.delete_import(DEFAULT_DATASET_ID, &first.id).unwrap(), Some(0));
+        let data = store.load_all(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].import_id.as_deref(), Some(second.id.as_str()));
+        let imports = store.list_imports(DEFAULT_DATASET_ID).unwrap();
+        assert_eq!(imports[0].record_count, 1);
+
+        // The handed over record is the second import's own now
+        assert_eq!(store.delete_import(DEFAULT_DATASET_ID, &second.id).unwrap(), Some(1));
+        assert!(store.load_all(DEFAULT_DATASET_ID).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use flate2::read::MultiGzDecoder;
+use std::cell::Cell;
+use std::io::{self, Read};
+use zip::ZipArchive;
+
+/// How an uploaded usage export is packaged, detected from its file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadFormat {
+    Csv,
+    /// A single gzip-compressed CSV (`.csv.gz`)
+    Gzip,
+    /// A zip archive holding one or more CSV files
+    Zip,
+}
+
+impl UploadFormat {
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".csv") {
+            Some(UploadFormat::Csv)
+        } else if file_name.ends_with(".csv.gz") {
+            Some(UploadFormat::Gzip)
+        } else if file_name.ends_with(".zip") {
+            Some(UploadFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why an upload could not be unpacked
+#[derive(Debug)]
+pub enum UnpackError {
+    /// The compressed data or archive is malformed
+    Invalid(String),
+    /// The decompressed data exceeded the size limit
+    TooLarge(String),
+    /// The callback rejected one of the CSV files
+    Entry(String),
+}
+
+/// Call `f` with the name and decompressed contents of every CSV in the upload.
+/// Plain and gzip uploads are streamed; zip archives are spooled to a temporary file
+/// first because their index sits at the end. Non-CSV archive entries are skipped.
+/// Compressed uploads may expand to at most `max_size` bytes, summed over every CSV
+/// they contain.
+pub fn for_each_csv<R, F>(
+    reader: R,
+    format: UploadFormat,
+    file_name: &str,
+    max_size: u64,
+    mut f: F,
+) -> Result<(), UnpackError>
+where
+    R: Read,
+    F: FnMut(&str, &mut dyn Read) -> Result<(), String>,
+{
+    let budget = DecompressionBudget::new(max_size);
+
+    match format {
+        UploadFormat::Csv => f(file_name, &mut { reader }).map_err(UnpackError::Entry),
+        UploadFormat::Gzip => {
+            let mut decoder = budget.limit(MultiGzDecoder::new(reader));
+            f(file_name, &mut decoder).map_err(|e| budget.classify(e))
+        }
+        UploadFormat::Zip => {
+            let mut spool = tempfile::tempfile()
+                .map_err(|e| UnpackError::Invalid(format!("Error buffering archive: {}", e)))?;
+            io::copy(&mut { reader }, &mut spool)
+                .map_err(|e| UnpackError::Invalid(format!("Error reading archive: {}", e)))?;
+
+            let mut archive = ZipArchive::new(spool)
+                .map_err(|e| UnpackError::Invalid(format!("Invalid zip archive: {}", e)))?;
+
+            let mut csv_count = 0;
+            for index in 0..archive.len() {
+                let entry = archive
+                    .by_index(index)
+                    .map_err(|e| UnpackError::Invalid(format!("Invalid zip archive: {}", e)))?;
+                let name = entry.name().to_string();
+                if entry.is_dir() || !is_csv_entry(&name) {
+                    continue;
+                }
+
+                csv_count += 1;
+                let mut entry = budget.limit(entry);
+                f(&name, &mut entry).map_err(|e| budget.classify(format!("{}: {}", name, e)))?;
+            }
+
+            if csv_count == 0 {
+                return Err(UnpackError::Invalid("Zip archive contains no CSV files".to_string()));
+            }
+            Ok(())
+        }
+    }
+}
+
+// Skips macOS resource forks, which carry a .csv name but are not CSV data
+fn is_csv_entry(name: &str) -> bool {
+    let base_name = name.rsplit('/').next().unwrap_or(name);
+    name.to_lowercase().ends_with(".csv") && !name.starts_with("__MACOSX/") && !base_name.starts_with("._")
+}
+
+/// Decompressed bytes still allowed, shared by every entry of one upload
+struct DecompressionBudget {
+    limit: u64,
+    remaining: Cell<u64>,
+    exceeded: Cell<bool>,
+}
+
+impl DecompressionBudget {
+    fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            remaining: Cell::new(limit),
+            exceeded: Cell::new(false),
+        }
+    }
+
+    fn limit<R: Read>(&self, inner: R) -> LimitedReader<'_, R> {
+        LimitedReader { inner, budget: self }
+    }
+
+    /// Attribute a failure to the size limit if the budget ran out
+    fn classify(&self, error: String) -> UnpackError {
+        if self.exceeded.get() {
+            UnpackError::TooLarge(format!(
+                "Decompressed data exceeds maximum allowed size ({} bytes)",
+                self.limit
+            ))
+        } else {
+            UnpackError::Entry(error)
+        }
+    }
+}
+
+struct LimitedReader<'a, R> {
+    inner: R,
+    budget: &'a DecompressionBudget,
+}
+
+impl<R: Read> Read for LimitedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.budget.remaining.get();
+        if remaining == 0 {
+            // Only an error if there is data beyond the budget
+            let mut probe = [0u8; 1];
+            if self.inner.read(&mut probe)? == 0 {
+                return Ok(0);
+            }
+            self.budget.exceeded.set(true);
+            return Err(io::Error::other("decompressed data exceeds the upload size limit"));
+        }
+
+        let max = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+        self.budget.remaining.set(remaining - read as u64);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    fn collect(reader: &[u8], format: UploadFormat) -> Result<Vec<(String, String)>, UnpackError> {
+        let mut files = Vec::new();
+        for_each_csv(reader, format, "upload", u64::MAX, |name, contents| {
+            let mut text = String::new();
+            contents.read_to_string(&mut text).map_err(|e| e.to_string())?;
+            files.push((name.to_string(), text));
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    #[test]
+    fn test_detects_format_from_file_name() {
+        assert_eq!(UploadFormat::from_file_name("usage.CSV"), Some(UploadFormat::Csv));
+        assert_eq!(UploadFormat::from_file_name("usage.csv.gz"), Some(UploadFormat::Gzip));
+        assert_eq!(UploadFormat::from_file_name("exports.zip"), Some(UploadFormat::Zip));
+        assert_eq!(UploadFormat::from_file_name("usage.txt"), None);
+    }
+
+    #[test]
+    fn test_gzip_is_decompressed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let files = collect(&compressed, UploadFormat::Gzip).unwrap();
+        assert_eq!(files, vec![("upload".to_string(), "a,b\n1,2\n".to_string())]);
+    }
+
+    #[test]
+    fn test_zip_yields_every_csv_entry() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [
+            ("january.csv", "a\n1\n"),
+            ("notes.txt", "skip me"),
+            ("__MACOSX/._january.csv", "junk"),
+            ("team/february.csv", "a\n2\n"),
+        ] {
+            writer.start_file(name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let archive = writer.finish().unwrap().into_inner();
+
+        let files = collect(&archive, UploadFormat::Zip).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["january.csv", "team/february.csv"]);
+        assert_eq!(files[1].1, "a\n2\n");
+    }
+
+    #[test]
+    fn test_invalid_archives_are_rejected() {
+        assert!(matches!(collect(b"not a zip", UploadFormat::Zip), Err(UnpackError::Invalid(_))));
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("readme.txt", SimpleFileOptions::default()).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+        assert!(matches!(collect(&archive, UploadFormat::Zip), Err(UnpackError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_gzip_expanding_past_size_limit_is_rejected() {
+        // Zeros compress about a thousandfold, so this is a small upload
+        let max_size = 16 * 1024 * 1024;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        let block = vec![0u8; 1024 * 1024];
+        for _ in 0..=max_size / block.len() {
+            encoder.write_all(&block).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < max_size);
+
+        let result = for_each_csv(&compressed[..], UploadFormat::Gzip, "bomb.csv.gz", max_size as u64, |_, contents| {
+            io::copy(contents, &mut io::sink()).map(|_| ()).map_err(|e| e.to_string())
+        });
+        assert!(matches!(result, Err(UnpackError::TooLarge(_))));
+    }
+
+    #[test]
+    fn test_budget_stops_oversized_streams() {
+        let budget = DecompressionBudget::new(4);
+        let mut reader = budget.limit(&b"123456"[..]);
+        let mut contents = Vec::new();
+        assert!(reader.read_to_end(&mut contents).is_err());
+        assert!(matches!(budget.classify("x".to_string()), UnpackError::TooLarge(_)));
+
+        let budget = DecompressionBudget::new(6);
+        let mut contents = Vec::new();
+        budget.limit(&b"123456"[..]).read_to_end(&mut contents).unwrap();
+        assert!(matches!(budget.classify("x".to_string()), UnpackError::Entry(_)));
+    }
+}
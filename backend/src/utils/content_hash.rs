@@ -0,0 +1,49 @@
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+/// `Read` adapter computing the SHA-256 of everything read through it
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Read whatever the consumer left unread and return the hex-encoded digest of
+    /// the whole stream
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_unread_remainder() {
+        let mut reader = HashingReader::new(&b"abc"[..]);
+        let mut first = [0u8; 1];
+        reader.read_exact(&mut first).unwrap();
+
+        assert_eq!(
+            reader.finish().unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use chrono::{DateTime, Utc, NaiveDateTime};
+use chrono_tz::Tz;
+
+/// Server-wide timezone for bucketing and date filters, unless a request names its own
+const DEFAULT_TIMEZONE: &str = "UTC";
 
 // Date utility functions
-#[allow(dead_code)]
 pub fn parse_date(date_str: &str) -> Result<DateTime<Utc>, String> {
     // Try parsing ISO 8601 format first
     if let Ok(dt) = DateTime::parse_from_rfc3339(date_str) {
@@ -19,4 +22,57 @@ pub fn parse_date(date_str: &str) -> Result<DateTime<Utc>, String> {
 #[allow(dead_code)]
 pub fn format_date_for_display(dt: &DateTime<Utc>) -> String {
     dt.format("%Y-%m-%d %H:%M:%S UTC").to_string()
-}
\ No newline at end of file
+}
+
+/// Parse a record's date and express it as wall-clock time in `timezone`.
+/// Conversion goes through the UTC instant, so DST transitions are applied.
+pub fn to_local(date_str: &str, timezone: Tz) -> Option<DateTime<Tz>> {
+    parse_date(date_str).ok().map(|dt| dt.with_timezone(&timezone))
+}
+
+/// Local calendar day (`YYYY-MM-DD`) of a record in `timezone`; unparseable dates
+/// fall back to their first ten characters
+pub fn local_day(date_str: &str, timezone: Tz) -> String {
+    match to_local(date_str, timezone) {
+        Some(local) => local.date_naive().to_string(),
+        None => date_str.chars().take(10).collect(),
+    }
+}
+
+/// Look up an IANA timezone name such as `Asia/Tokyo`
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("Unknown timezone '{}'. Expected an IANA name such as 'Asia/Tokyo'", name))
+}
+
+/// Default timezone configured through `DEFAULT_TIMEZONE` (`UTC` when unset)
+pub fn timezone_from_env() -> Result<Tz, String> {
+    let name = std::env::var("DEFAULT_TIMEZONE").unwrap_or_else(|_| DEFAULT_TIMEZONE.to_string());
+    parse_timezone(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    #[test]
+    fn test_to_local_follows_dst() {
+        let new_york = parse_timezone("America/New_York").unwrap();
+
+        // 2024-03-10 02:00 EST jumps to 03:00 EDT
+        assert_eq!(to_local("2024-03-10T06:30:00Z", new_york).unwrap().hour(), 1);
+        assert_eq!(to_local("2024-03-10T07:30:00Z", new_york).unwrap().hour(), 3);
+
+        let tokyo = parse_timezone("Asia/Tokyo").unwrap();
+        let local = to_local("2024-01-01T20:00:00Z", tokyo).unwrap();
+        assert_eq!(local.format("%Y-%m-%d %H").to_string(), "2024-01-02 05");
+    }
+
+    #[test]
+    fn test_parse_timezone_rejects_unknown_names() {
+        assert!(parse_timezone("Asia/Tokyo").is_ok());
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+}
@@ -1,2 +1,5 @@
 pub mod validation;
-pub mod date_utils;
\ No newline at end of file
+pub mod date_utils;
+pub mod spool;
+pub mod archive;
+pub mod content_hash;
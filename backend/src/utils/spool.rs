@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{self, BufReader, Seek, SeekFrom};
+use tokio::io::AsyncWriteExt;
+
+/// Upload bytes buffered in an anonymous temporary file while they arrive, so the
+/// parser and the store only ever read local data. Holding a store transaction open
+/// while waiting on a slow client would block every other store call.
+pub struct UploadSpool {
+    file: tokio::fs::File,
+    len: usize,
+}
+
+impl UploadSpool {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            file: tokio::fs::File::from_std(tempfile::tempfile()?),
+            len: 0,
+        })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.write_all(chunk).await?;
+        self.len += chunk.len();
+        Ok(())
+    }
+
+    /// Bytes spooled so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reader over everything spooled, from the first byte
+    pub async fn into_reader(mut self) -> io::Result<BufReader<File>> {
+        self.file.flush().await?;
+        let mut file = self.file.into_std().await;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_spooled_chunks_are_read_back_in_order() {
+        let mut spool = UploadSpool::new().unwrap();
+        assert!(spool.is_empty());
+        spool.write(b"Date,Kind\n").await.unwrap();
+        spool.write(b"2024-01-01,Included\n").await.unwrap();
+        assert_eq!(spool.len(), 30);
+
+        let mut contents = String::new();
+        spool.into_reader().await.unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "Date,Kind\n2024-01-01,Included\n");
+    }
+}
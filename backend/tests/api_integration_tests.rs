@@ -0,0 +1,1157 @@
+use axum_test::TestServer;
+use cursor_usage_dashboard_backend::{
+    routes::create_router,
+    services::{
+        pricing::PricingCatalog,
+        webhook_dispatcher::{RetryPolicy, WebhookDispatcher},
+    },
+    state::AppState,
+    storage::sqlite::SqliteStore,
+};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::Value;
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+const BOUNDARY: &str = "----formdata-test-boundary";
+
+fn create_test_csv_data() -> String {
+    "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+     2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+     2024-01-02T11:00:00Z,Included,gpt-4,Yes,200,100,50,150,500,0.15".to_string()
+}
+
+fn create_multipart_body(content: &str, filename: &str) -> String {
+    format!(
+        "--{boundary}\r\n\
+         Content-Disposition: form-data; name=\"csvFile\"; filename=\"{filename}\"\r\n\
+         Content-Type: text/csv\r\n\
+         \r\n\
+         {content}\r\n\
+         --{boundary}--\r\n",
+        boundary = BOUNDARY,
+        filename = filename,
+        content = content
+    )
+}
+
+fn create_multipart_bytes(content: &[u8], filename: &str) -> Vec<u8> {
+    let mut body = format!(
+        "--{boundary}\r\n\
+         Content-Disposition: form-data; name=\"csvFile\"; filename=\"{filename}\"\r\n\
+         Content-Type: application/octet-stream\r\n\
+         \r\n",
+        boundary = BOUNDARY,
+        filename = filename
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+fn create_test_server() -> TestServer {
+    TestServer::new(create_router(AppState::in_memory())).unwrap()
+}
+
+async fn upload(server: &TestServer, path: &str, content: &str, filename: &str) -> Value {
+    server
+        .post(path)
+        .text(create_multipart_body(content, filename))
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>()
+}
+
+async fn upload_bytes(server: &TestServer, path: &str, content: &[u8], filename: &str) -> Value {
+    server
+        .post(path)
+        .bytes(create_multipart_bytes(content, filename).into())
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>()
+}
+
+#[tokio::test]
+async fn test_upload_then_comprehensive_stats() {
+    let server = create_test_server();
+
+    let response = upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 2);
+    assert_eq!(response["schema_version"], "v1");
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["record_count"], 2);
+    assert_eq!(stats["total_records"], 2);
+
+    let filtered = server
+        .get("/api/stats/comprehensive")
+        .add_query_param("start_date", "2024-01-02")
+        .add_query_param("end_date", "2024-01-02")
+        .await
+        .json::<Value>();
+    assert_eq!(filtered["record_count"], 1);
+    assert_eq!(filtered["total_records"], 2);
+}
+
+#[tokio::test]
+async fn test_servers_do_not_share_stores() {
+    let first = create_test_server();
+    let second = create_test_server();
+
+    upload(&first, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    let stats = second.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["data"], Value::Null);
+    assert!(stats["message"].as_str().unwrap().contains("No data available"));
+}
+
+#[tokio::test]
+async fn test_dataset_scoped_uploads_are_isolated() {
+    let server = create_test_server();
+
+    let created = server
+        .post("/api/datasets")
+        .json(&serde_json::json!({ "name": "Alice" }))
+        .await;
+    created.assert_status(axum::http::StatusCode::CREATED);
+    let dataset_id = created.json::<Value>()["dataset"]["id"].as_str().unwrap().to_string();
+    assert_eq!(dataset_id, "alice");
+
+    upload(&server, "/api/upload", &create_test_csv_data(), "default.csv").await;
+    let response = upload(
+        &server,
+        &format!("/api/datasets/{}/upload", dataset_id),
+        "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+         2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05",
+        "alice.csv",
+    )
+    .await;
+    assert_eq!(response["dataset_id"], "alice");
+
+    let default_stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(default_stats["total_records"], 2);
+    let alice_stats = server
+        .get(&format!("/api/datasets/{}/stats/comprehensive", dataset_id))
+        .await
+        .json::<Value>();
+    assert_eq!(alice_stats["total_records"], 1);
+
+    let renamed = server
+        .patch(&format!("/api/datasets/{}", dataset_id))
+        .json(&serde_json::json!({ "name": "Alice (Q1)" }))
+        .await
+        .json::<Value>();
+    assert_eq!(renamed["dataset"]["name"], "Alice (Q1)");
+
+    let datasets = server.get("/api/datasets").await.json::<Value>();
+    assert_eq!(datasets["datasets"].as_array().unwrap().len(), 2);
+
+    server.delete(&format!("/api/datasets/{}", dataset_id)).await.assert_status_ok();
+    server
+        .get(&format!("/api/datasets/{}/stats/comprehensive", dataset_id))
+        .await
+        .assert_status(axum::http::StatusCode::NOT_FOUND);
+    server
+        .delete("/api/datasets/default")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_failed_upload_keeps_existing_data() {
+    let server = create_test_server();
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    // Valid rows followed by a broken one: nothing from this file may be stored
+    let mut broken = create_test_csv_data();
+    broken.push_str("\n2024-01-03T10:00:00Z,Included,auto,No,100,50,25,75,999,0.05");
+    let response = upload(&server, "/api/upload", &broken, "broken.csv").await;
+    assert_eq!(response["success"], false);
+    assert_eq!(response["error"]["code"], "CSV_PARSE_ERROR");
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["total_records"], 2);
+}
+
+#[tokio::test]
+async fn test_upload_larger_than_default_body_limit() {
+    let server = create_test_server();
+
+    let mut csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n".to_string();
+    for i in 0..50_000 {
+        csv.push_str(&format!(
+            "2024-01-{:02}T{:02}:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n",
+            (i % 30) + 1,
+            i % 24
+        ));
+    }
+    assert!(csv.len() > 2 * 1024 * 1024);
+
+    let response = upload(&server, "/api/upload", &csv, "large.csv").await;
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 50_000);
+}
+
+
+#[tokio::test]
+async fn test_lenient_upload_skips_invalid_rows() {
+    let server = create_test_server();
+
+    let mut csv = create_test_csv_data();
+    csv.push_str("\n2024-01-03T10:00:00Z,Included,auto,No,100,50,25,75,999,0.05");
+    let response = server
+        .post("/api/upload")
+        .add_query_param("mode", "lenient")
+        .text(create_multipart_body(&csv, "partial.csv"))
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>();
+
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 2);
+    assert_eq!(response["parse_report"]["mode"], "lenient");
+    assert_eq!(response["parse_report"]["rejected_rows"], 1);
+    assert_eq!(response["parse_report"]["errors"][0]["line"], 4);
+    assert_eq!(response["parse_report"]["errors"][0]["column"], "Total Tokens");
+
+    let only_invalid = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                        2024-01-03T10:00:00Z,Included,auto,No,100,50,25,75,999,0.05";
+    let response = server
+        .post("/api/upload/append")
+        .add_query_param("mode", "lenient")
+        .text(create_multipart_body(only_invalid, "invalid.csv"))
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>();
+    assert_eq!(response["error"]["code"], "NO_VALID_ROWS");
+    assert_eq!(response["error"]["details"]["rejected_rows"], 1);
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["total_records"], 2);
+}
+
+#[tokio::test]
+async fn test_validate_upload_is_a_dry_run() {
+    let server = create_test_server();
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    let mut csv = create_test_csv_data();
+    csv.push_str("\n2024-01-05T10:00:00Z,Included,claude-4-sonnet,No,100,50,25,75,250,0.05");
+    csv.push_str("\n2024-01-06T10:00:00Z,Included,auto,No,100,50,25,75,999,0.05");
+    let response = upload(&server, "/api/upload/validate", &csv, "next.csv").await;
+
+    assert_eq!(response["success"], true);
+    assert_eq!(response["dry_run"], true);
+    assert_eq!(response["would_succeed"], false);
+    assert_eq!(response["total_rows"], 4);
+    assert_eq!(response["preview"]["valid_rows"], 3);
+    assert_eq!(response["preview"]["invalid_rows"], 0);
+    assert_eq!(response["preview"]["duplicates_of_existing"], 2);
+    assert_eq!(response["preview"]["new_rows"], 1);
+    assert_eq!(response["preview"]["models"]["claude-4-sonnet"], 1);
+    assert_eq!(response["preview"]["date_range"]["end"], "2024-01-05T10:00:00Z");
+    assert_eq!(response["parse_report"]["errors"][0]["line"], 5);
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["total_records"], 2);
+}
+
+#[tokio::test]
+async fn test_gzip_upload() {
+    let server = create_test_server();
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(create_test_csv_data().as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let response = upload_bytes(&server, "/api/upload", &compressed, "usage.csv.gz").await;
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 2);
+
+    let response = upload_bytes(&server, "/api/upload", b"not gzip", "broken.csv.gz").await;
+    assert_eq!(response["error"]["code"], "CSV_PARSE_ERROR");
+}
+
+#[tokio::test]
+async fn test_zip_upload_with_several_csv_files() {
+    let server = create_test_server();
+
+    let header = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost";
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.start_file("january.csv", SimpleFileOptions::default()).unwrap();
+    writer.write_all(create_test_csv_data().as_bytes()).unwrap();
+    writer.start_file("february.csv", SimpleFileOptions::default()).unwrap();
+    writer
+        .write_all(format!(
+            "{}\n2024-02-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n2024-02-02T10:00:00Z,Included,auto,No,1,1,1,1,9,0.05",
+            header
+        ).as_bytes())
+        .unwrap();
+    writer.start_file("README.txt", SimpleFileOptions::default()).unwrap();
+    writer.write_all(b"ignored").unwrap();
+    let archive = writer.finish().unwrap().into_inner();
+
+    let response = server
+        .post("/api/upload")
+        .add_query_param("mode", "lenient")
+        .bytes(create_multipart_bytes(&archive, "exports.zip").into())
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>();
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 3);
+    assert_eq!(response["parse_report"]["files"], serde_json::json!(["january.csv", "february.csv"]));
+    assert_eq!(response["parse_report"]["errors"][0]["file"], "february.csv");
+    assert_eq!(response["parse_report"]["errors"][0]["line"], 3);
+
+    // Strict mode rejects the whole archive
+    let response = upload_bytes(&server, "/api/upload/append", &archive, "exports.zip").await;
+    assert_eq!(response["error"]["code"], "CSV_PARSE_ERROR");
+    assert!(response["error"]["message"].as_str().unwrap().starts_with("february.csv: "));
+
+    let response = upload_bytes(&server, "/api/upload", b"not a zip", "exports.zip").await;
+    assert_eq!(response["error"]["code"], "INVALID_ARCHIVE");
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["total_records"], 3);
+}
+
+#[tokio::test]
+async fn test_ndjson_request_body_upload() {
+    let server = create_test_server();
+
+    let events = "{\"date\": \"2024-01-01T10:00:00Z\", \"kind\": \"Included\", \"model\": \"auto\", \"total_tokens\": 0, \"cost\": 0.05}\n\
+                  {\"timestamp\": 1704193200, \"model\": \"gpt-4\", \"outputTokens\": 10, \"cost\": 0.15, \"email\": \"alice@example.com\"}\n";
+    let response = server
+        .post("/api/upload")
+        .text(events)
+        .content_type("application/x-ndjson")
+        .await
+        .json::<Value>();
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 2);
+    assert_eq!(response["schema_version"], "json");
+
+    let response = server
+        .post("/api/upload/append")
+        .text("{\"date\": \"2024-01-03T10:00:00Z\", \"model\": \"auto\", \"cost\": -1}")
+        .content_type("application/x-ndjson")
+        .await
+        .json::<Value>();
+    assert_eq!(response["error"]["code"], "JSON_PARSE_ERROR");
+    assert!(response["error"]["message"].as_str().unwrap().contains("Negative cost"));
+
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(stats["total_records"], 2);
+    assert_eq!(stats["summary"]["user_breakdown"][0]["key"], "alice@example.com");
+}
+
+#[tokio::test]
+async fn test_json_array_file_upload() {
+    let server = create_test_server();
+
+    let events = r#"[
+        {"date": "2024-01-01T10:00:00Z", "model": "auto", "input_with_cache": 100, "total_tokens": 100, "cost": 0.05},
+        {"date": "2024-01-02T10:00:00Z", "model": "auto", "input_with_cache": 100, "total_tokens": 99, "cost": 0.05}
+    ]"#;
+    let response = server
+        .post("/api/upload")
+        .add_query_param("mode", "lenient")
+        .bytes(create_multipart_bytes(events.as_bytes(), "events.json").into())
+        .content_type(&format!("multipart/form-data; boundary={}", BOUNDARY))
+        .await
+        .json::<Value>();
+    assert_eq!(response["success"], true);
+    assert_eq!(response["record_count"], 1);
+    assert_eq!(response["parse_report"]["files"], serde_json::json!(["events.json"]));
+    assert_eq!(response["parse_report"]["errors"][0]["line"], 2);
+
+    let response = upload(&server, "/api/upload", "{}", "events.txt").await;
+    assert_eq!(response["error"]["code"], "INVALID_FILE_TYPE");
+}
+
+#[tokio::test]
+async fn test_identical_upload_is_a_no_op() {
+    let server = create_test_server();
+
+    let first = upload(&server, "/api/upload/append", &create_test_csv_data(), "usage.csv").await;
+    assert_eq!(first["success"], true);
+    assert_eq!(first["duplicate"], false);
+    assert_eq!(first["import"]["file_name"], "usage.csv");
+    assert_eq!(first["import"]["record_count"], 2);
+    let hash = first["import"]["content_hash"].as_str().unwrap();
+    assert_eq!(hash.len(), 64);
+
+    // Same bytes under another name are still recognised
+    let again = upload(&server, "/api/upload/append", &create_test_csv_data(), "copy.csv").await;
+    assert_eq!(again["success"], true);
+    assert_eq!(again["duplicate"], true);
+    assert_eq!(again["duplicate_of"]["id"], first["import"]["id"]);
+    assert_eq!(again["new_records"], 0);
+    assert_eq!(again["total_records"], 2);
+
+    let preview = upload(&server, "/api/upload/validate", &create_test_csv_data(), "usage.csv").await;
+    assert_eq!(preview["content_hash"], hash);
+    assert_eq!(preview["previous_import"]["id"], first["import"]["id"]);
+
+    // Replacing the dataset with the file it already holds changes nothing either
+    let replaced = upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+    assert_eq!(replaced["duplicate"], true);
+    assert_eq!(replaced["record_count"], 0);
+
+    let other = format!("{}\n2024-01-03T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05", create_test_csv_data());
+    let appended = upload(&server, "/api/upload/append", &other, "usage.csv").await;
+    assert_eq!(appended["duplicate"], false);
+    assert_ne!(appended["import"]["id"], first["import"]["id"]);
+    assert_eq!(appended["total_records"], 3);
+}
+
+#[tokio::test]
+async fn test_append_skips_rows_of_overlapping_exports() {
+    let server = create_test_server();
+    let row = "2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05";
+
+    let first = upload(&server, "/api/upload/append", &format!("{}\n{}", create_test_csv_data(), row), "week1.csv").await;
+    assert_eq!(first["new_records"], 3);
+    assert_eq!(first["duplicate_records"], 0);
+
+    // Same rows plus one more identical request and a new day
+    let overlapping = format!(
+        "{}\n{row}\n{row}\n2024-01-04T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05",
+        create_test_csv_data(),
+        row = row
+    );
+    let second = upload(&server, "/api/upload/append", &overlapping, "week2.csv").await;
+    assert_eq!(second["duplicate"], false);
+    assert_eq!(second["new_records"], 2);
+    assert_eq!(second["duplicate_records"], 3);
+    assert_eq!(second["import"]["record_count"], 2);
+    assert_eq!(second["total_records"], 5);
+}
+
+#[tokio::test]
+async fn test_import_history_and_rollback() {
+    let server = create_test_server();
+
+    let first = upload(&server, "/api/upload/append", &create_test_csv_data(), "january.csv").await;
+    let second_csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                      2024-02-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+                      2024-02-03T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05";
+    let second = upload(&server, "/api/upload/append", second_csv, "february.csv").await;
+    assert_eq!(second["total_records"], 4);
+    assert!(second["data"][0]["import_id"].is_string());
+
+    let history = server.get("/api/imports").await.json::<Value>();
+    let imports = history["imports"].as_array().unwrap();
+    assert_eq!(imports.len(), 2);
+    assert_eq!(imports[0]["id"], first["import"]["id"]);
+    assert_eq!(imports[1]["file_name"], "february.csv");
+    assert_eq!(imports[1]["record_count"], 2);
+    assert_eq!(imports[1]["date_range"]["start"], "2024-02-01T10:00:00Z");
+    assert_eq!(imports[1]["date_range"]["end"], "2024-02-03T10:00:00Z");
+
+    let path = format!("/api/imports/{}", second["import"]["id"].as_str().unwrap());
+    let response = server.delete(&path).await.json::<Value>();
+    assert_eq!(response["success"], true);
+    assert_eq!(response["deleted_records"], 2);
+    assert_eq!(response["total_records"], 2);
+    assert!((response["summary"]["total_cost"].as_f64().unwrap() - 0.20).abs() < 1e-9);
+
+    let response = server.delete(&path).await;
+    response.assert_status_not_found();
+    assert_eq!(response.json::<Value>()["error"]["code"], "IMPORT_NOT_FOUND");
+
+    // The rolled back file can be imported again
+    let again = upload(&server, "/api/upload/append", second_csv, "february.csv").await;
+    assert_eq!(again["duplicate"], false);
+    assert_eq!(again["new_records"], 2);
+
+    let scoped = server.get("/api/datasets/default/imports").await.json::<Value>();
+    assert_eq!(scoped["imports"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_rollback_keeps_rows_a_later_import_skipped() {
+    let server = create_test_server();
+
+    let first = upload(&server, "/api/upload/append", &create_test_csv_data(), "week1.csv").await;
+    // The second export repeats the gpt-4 row of the first and adds a new day
+    let overlapping = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                       2024-01-02T11:00:00Z,Included,gpt-4,Yes,200,100,50,150,500,0.15\n\
+                       2024-01-03T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05";
+    let second = upload(&server, "/api/upload/append", overlapping, "week2.csv").await;
+    assert_eq!(second["new_records"], 1);
+    assert_eq!(second["duplicate_records"], 1);
+
+    let path = format!("/api/imports/{}", first["import"]["id"].as_str().unwrap());
+    let response = server.delete(&path).await.json::<Value>();
+    assert_eq!(response["deleted_records"], 1);
+    assert_eq!(response["total_records"], 2);
+    let data = response["data"].as_array().unwrap();
+    assert!(data.iter().all(|usage| usage["import_id"] == second["import"]["id"]));
+    assert!(data.iter().any(|usage| usage["date"] == "2024-01-02T11:00:00Z"));
+
+    // The surviving import now owns the row it had skipped
+    let history = server.get("/api/imports").await.json::<Value>();
+    let imports = history["imports"].as_array().unwrap();
+    assert_eq!(imports.len(), 1);
+    assert_eq!(imports[0]["record_count"], 2);
+    assert_eq!(imports[0]["date_range"]["start"], "2024-01-02T11:00:00Z");
+    assert_eq!(imports[0]["date_range"]["end"], "2024-01-03T10:00:00Z");
+
+    // Rolling that one back as well empties the dataset
+    let path = format!("/api/imports/{}", second["import"]["id"].as_str().unwrap());
+    let response = server.delete(&path).await.json::<Value>();
+    assert_eq!(response["deleted_records"], 2);
+    assert_eq!(response["total_records"], 0);
+}
+
+#[tokio::test]
+async fn test_stats_in_requested_timezone() {
+    let server = create_test_server();
+    let response = upload(
+        &server,
+        "/api/upload",
+        "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+         2024-01-01T16:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+         2024-01-02T10:00:00Z,Included,auto,No,40,20,10,30,100,0.05",
+        "usage.csv",
+    )
+    .await;
+    assert_eq!(response["success"], true);
+
+    let utc = server.get("/api/stats/comprehensive").await.json::<Value>();
+    assert_eq!(utc["timezone"], "UTC");
+    assert_eq!(utc["comprehensive_stats"]["peak_usage"]["peak_hour"], 16);
+
+    // 16:00Z is 01:00 on the 2nd in Tokyo, so both records fall on that day
+    let tokyo = server
+        .get("/api/stats/comprehensive")
+        .add_query_param("timezone", "Asia/Tokyo")
+        .add_query_param("start_date", "2024-01-02")
+        .add_query_param("end_date", "2024-01-02")
+        .await
+        .json::<Value>();
+    assert_eq!(tokyo["timezone"], "Asia/Tokyo");
+    assert_eq!(tokyo["record_count"], 2);
+    assert_eq!(tokyo["comprehensive_stats"]["peak_usage"]["peak_hour"], 1);
+    assert_eq!(tokyo["comprehensive_stats"]["peak_usage"]["peak_day"], "2024-01-02");
+
+    let invalid = server
+        .get("/api/stats/comprehensive")
+        .add_query_param("tz", "Mars/Olympus_Mons")
+        .await;
+    invalid.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.json::<Value>()["error"]["code"], "INVALID_TIMEZONE");
+}
+
+#[tokio::test]
+async fn test_timeseries_endpoint() {
+    let server = create_test_server();
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    let daily = server.get("/api/stats/timeseries").await;
+    daily.assert_status_ok();
+    let daily = daily.json::<Value>();
+    assert_eq!(daily["timeseries"]["granularity"], "daily");
+    assert_eq!(daily["timeseries"]["buckets"].as_array().unwrap().len(), 2);
+    assert_eq!(daily["timeseries"]["buckets"][1]["total_tokens"], 500);
+
+    let hourly = server
+        .get("/api/stats/timeseries")
+        .add_query_param("granularity", "hourly")
+        .add_query_param("by_model", "true")
+        .add_query_param("timezone", "Asia/Tokyo")
+        .await
+        .json::<Value>();
+    let buckets = hourly["timeseries"]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 26);
+    assert_eq!(buckets[0]["start"], "2024-01-01T19:00:00+09:00");
+    assert_eq!(buckets[1]["total_tokens"], 0);
+    let by_model = hourly["timeseries"]["by_model"].as_array().unwrap();
+    assert_eq!(by_model.len(), 2);
+    assert_eq!(by_model[1]["model"], "gpt-4");
+    assert_eq!(by_model[1]["buckets"][25]["cost"], 0.15);
+
+    let invalid = server
+        .get("/api/stats/timeseries")
+        .add_query_param("granularity", "weekly")
+        .await;
+    invalid.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.json::<Value>()["error"]["code"], "INVALID_GRANULARITY");
+}
+
+#[tokio::test]
+async fn test_cache_savings_use_configured_pricing() {
+    let pricing = PricingCatalog::from_toml_str(
+        "currency = \"EUR\"\n\
+         [default]\n\
+         input = 4.0\n\
+         cache_write = 4.0\n\
+         cache_read = 1.0\n\
+         output = 8.0",
+    )
+    .unwrap();
+    let server = TestServer::new(create_router(AppState::in_memory().with_pricing(Arc::new(pricing)))).unwrap();
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    // Each cache-read token saves 4.0 - 1.0 per million: 25 and 50 tokens
+    let stats = server.get("/api/stats/comprehensive").await.json::<Value>();
+    let efficiency = &stats["comprehensive_stats"]["cost_efficiency"];
+    assert_eq!(efficiency["currency"], "EUR");
+    assert!((efficiency["cache_savings"].as_f64().unwrap() - 225e-6).abs() < 1e-12);
+    assert_eq!(efficiency["unpriced_models"].as_array().unwrap().len(), 0);
+
+    let gpt4 = stats["model_stats"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|model| model["model"] == "gpt-4")
+        .unwrap();
+    assert!((gpt4["cache_savings"].as_f64().unwrap() - 150e-6).abs() < 1e-12);
+}
+
+#[tokio::test]
+async fn test_cost_audit_flags_divergent_rows() {
+    let pricing = PricingCatalog::from_toml_str(
+        "[models.auto]\ninput = 100.0\ncache_write = 100.0\ncache_read = 100.0\noutput = 100.0",
+    )
+    .unwrap();
+    let server = TestServer::new(create_router(AppState::in_memory().with_pricing(Arc::new(pricing)))).unwrap();
+    let csv_data = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+                    2024-01-01T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,0.05\n\
+                    2024-01-02T11:00:00Z,Usage-based,gpt-4,Yes,200,100,50,150,500,0.15\n\
+                    2024-01-02T12:00:00Z,Included,auto,No,100,50,25,75,250,0.00";
+    upload(&server, "/api/upload", csv_data, "usage.csv").await;
+
+    // auto: 250 tokens at 100 per million = 0.025 expected, 0.05 charged; gpt-4 is unpriced
+    // and the included row is not billed
+    let response = server
+        .get("/api/stats/cost-audit")
+        .add_query_param("limit", "5")
+        .await;
+    response.assert_status_ok();
+    let audit = &response.json::<Value>()["audit"];
+    assert_eq!(audit["groups"].as_array().unwrap().len(), 1);
+    assert_eq!(audit["groups"][0]["date"], "2024-01-01");
+    assert!((audit["expected_total"].as_f64().unwrap() - 0.025).abs() < 1e-9);
+    assert_eq!(audit["flagged_count"], 1);
+    assert_eq!(audit["top_rows"][0]["model"], "auto");
+    assert_eq!(audit["unpriced_models"][0], "gpt-4");
+    assert_eq!(audit["no_charge_records"], 1);
+
+    let lenient = server
+        .get("/api/stats/cost-audit")
+        .add_query_param("tolerance", "2")
+        .await
+        .json::<Value>();
+    assert_eq!(lenient["audit"]["flagged_count"], 0);
+
+    server
+        .get("/api/stats/cost-audit")
+        .add_query_param("tolerance", "-1")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_model_substitution_simulation() {
+    let pricing = PricingCatalog::from_toml_str(
+        "[models.gpt-4]\ninput = 30.0\ncache_write = 30.0\ncache_read = 30.0\noutput = 30.0\n\
+         [models.auto]\ninput = 3.0\ncache_write = 3.0\ncache_read = 3.0\noutput = 3.0",
+    )
+    .unwrap();
+    let server = TestServer::new(create_router(AppState::in_memory().with_pricing(Arc::new(pricing)))).unwrap();
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+
+    // gpt-4 list prices are ten times auto's, so its 0.15 charge projects to 0.015
+    let response = server
+        .post("/api/stats/simulate")
+        .json(&serde_json::json!({ "substitutions": { "gpt-4": "auto" } }))
+        .await;
+    response.assert_status_ok();
+    let simulation = &response.json::<Value>()["simulation"];
+    assert_eq!(simulation["substituted_records"], 1);
+    assert!((simulation["actual_cost"].as_f64().unwrap() - 0.20).abs() < 1e-9);
+    assert!((simulation["projected_cost"].as_f64().unwrap() - 0.065).abs() < 1e-9);
+    assert_eq!(simulation["groups"][1]["model"], "gpt-4");
+    assert_eq!(simulation["groups"][1]["projected_model"], "auto");
+
+    let unknown = server
+        .post("/api/stats/simulate")
+        .json(&serde_json::json!({ "substitutions": { "gpt-4": "nonexistent" } }))
+        .await;
+    unknown.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(unknown.json::<Value>()["error"]["code"], "UNKNOWN_MODEL");
+}
+
+#[tokio::test]
+async fn test_forecast_endpoint() {
+    let server = create_test_server();
+
+    let empty = server.get("/api/stats/forecast").await.json::<Value>();
+    assert_eq!(empty["forecast"], Value::Null);
+
+    upload(&server, "/api/upload", &create_test_csv_data(), "usage.csv").await;
+    let response = server.get("/api/stats/forecast").await;
+    response.assert_status_ok();
+    let forecast = &response.json::<Value>()["forecast"];
+    assert_eq!(forecast["as_of"], "2024-01-02");
+    assert_eq!(forecast["period_end"], "2024-01-31");
+    assert_eq!(forecast["days_remaining"], 29);
+    assert_eq!(forecast["forecasts"][0]["method"], "linear");
+    assert_eq!(forecast["forecasts"][1]["method"], "exponential_smoothing");
+    assert_eq!(forecast["forecasts"][1]["daily"].as_array().unwrap().len(), 29);
+    let projected = forecast["forecasts"][0]["cost"]["projected"].as_f64().unwrap();
+    assert!(projected > forecast["actual_cost"].as_f64().unwrap());
+
+    server
+        .get("/api/stats/forecast")
+        .add_query_param("history_days", "0")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_billing_cycles() {
+    let server = create_test_server();
+    let csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
+               2024-01-10T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,1.00\n\
+               2024-01-20T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,2.00\n\
+               2024-02-16T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,4.00";
+    upload(&server, "/api/upload", csv, "usage.csv").await;
+
+    let config = server.get("/api/billing/config").await.json::<Value>();
+    assert_eq!(config["billing_cycle"]["anchor_day"], 1);
+
+    let updated = server
+        .put("/api/billing/config")
+        .json(&serde_json::json!({ "anchor_day": 15 }))
+        .await;
+    updated.assert_status_ok();
+    assert_eq!(updated.json::<Value>()["billing_cycle"]["anchor_day"], 15);
+
+    let cycles = server.get("/api/billing/cycles").await.json::<Value>();
+    let cycles = cycles["cycles"].as_array().unwrap();
+    assert_eq!(cycles.len(), 3);
+    assert_eq!(cycles[0]["start"], "2023-12-15");
+    assert_eq!(cycles[1]["start"], "2024-01-15");
+    assert_eq!(cycles[1]["end"], "2024-02-14");
+    assert_eq!(cycles[2]["summary"]["total_cost"], 4.0);
+
+    let current = server
+        .get("/api/billing/current")
+        .add_query_param("as_of", "2024-02-20")
+        .await
+        .json::<Value>();
+    let comparison = &current["comparison"];
+    assert_eq!(comparison["current"]["start"], "2024-02-15");
+    assert_eq!(comparison["current"]["total_cost"], 4.0);
+    assert_eq!(comparison["previous_to_date"]["end"], "2024-01-20");
+    assert_eq!(comparison["previous_to_date"]["total_cost"], 2.0);
+    assert_eq!(comparison["cost_change"], 100.0);
+
+    // The forecast now runs to the end of the billing cycle rather than the month
+    let forecast = server.get("/api/stats/forecast").await.json::<Value>();
+    assert_eq!(forecast["forecast"]["period_end"], "2024-03-14");
+
+    // Without as_of both take the cycle of the latest record as the current one
+    let current = server.get("/api/billing/current").await.json::<Value>();
+    assert_eq!(current["as_of"], "2024-02-16");
+    assert_eq!(current["comparison"]["current"]["start"], "2024-02-15");
+    assert_eq!(forecast["as_of"], "2024-02-16");
+
+    let earlier = server
+        .get("/api/stats/forecast")
+        .add_query_param("as_of", "2024-01-31")
+        .await
+        .json::<Value>();
+    assert_eq!(earlier["as_of"], "2024-01-31");
+    assert_eq!(earlier["forecast"]["period_end"], "2024-02-14");
+    assert_eq!(earlier["forecast"]["actual_cost"], 2.0);
+
+    let invalid = server
+        .put("/api/billing/config")
+        .json(&serde_json::json!({ "anchor_day": 0 }))
+        .await;
+    invalid.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.json::<Value>()["error"]["code"], "INVALID_BILLING_CYCLE");
+}
+
+#[tokio::test]
+async fn test_unresolvable_stored_billing_timezone_is_a_validation_error() {
+    // The store only accepts valid configurations, so corrupt the database file directly,
+    // as a timezone name dropped from a newer timezone database would
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.db");
+    let store = Arc::new(SqliteStore::open(&path).unwrap());
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute("UPDATE datasets SET billing_timezone = 'Mars/Olympus_Mons'", [])
+        .unwrap();
+    let server = TestServer::new(create_router(AppState::new(store))).unwrap();
+
+    let response = server.get("/api/billing/cycles").await;
+    response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json::<Value>()["error"]["code"], "INVALID_BILLING_CYCLE");
+}
+
+#[tokio::test]
+async fn test_budgets_are_evaluated_on_import() {
+    let server = create_test_server();
+    let header = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n";
+
+    let created = server
+        .post("/api/budgets")
+        .json(&serde_json::json!({
+            "name": "GPT-4 monthly",
+            "amount": 10.0,
+            "period": "monthly",
+            "scope": { "model": "gpt-4" }
+        }))
+        .await;
+    created.assert_status(axum::http::StatusCode::CREATED);
+    let budget = created.json::<Value>()["budget"].clone();
+    assert_eq!(budget["thresholds"], serde_json::json!([50, 80, 100]));
+    let budget_id = budget["id"].as_str().unwrap().to_string();
+
+    let first = format!(
+        "{}2024-03-01T10:00:00Z,Usage-based,gpt-4,No,100,50,25,75,250,6.00\n\
+         2024-03-02T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,20.00",
+        header
+    );
+    let response = upload(&server, "/api/upload", &first, "first.csv").await;
+    let alerts = response["budget_alerts"].as_array().unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0]["threshold"], 50);
+    assert_eq!(response["budgets"][0]["spent"], 6.0);
+    // 6.00 over two days of a 31-day month
+    assert_eq!(response["budgets"][0]["projected_spend"], 93.0);
+    assert_eq!(response["budgets"][0]["projected_overrun"], 83.0);
+
+    // Only the thresholds this import crosses are reported
+    let second = format!("{}2024-03-03T10:00:00Z,Usage-based,gpt-4,No,100,50,25,75,250,5.00", header);
+    let response = upload(&server, "/api/upload/append", &second, "second.csv").await;
+    let thresholds: Vec<u64> = response["budget_alerts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|alert| alert["threshold"].as_u64().unwrap())
+        .collect();
+    assert_eq!(thresholds, vec![80, 100]);
+
+    let listed = server.get("/api/budgets").await.json::<Value>();
+    assert_eq!(listed["as_of"], "2024-03-03");
+    assert_eq!(listed["statuses"][0]["spent"], 11.0);
+    assert_eq!(listed["statuses"][0]["thresholds_crossed"], serde_json::json!([50, 80, 100]));
+
+    let updated = server
+        .put(&format!("/api/budgets/{}", budget_id))
+        .json(&serde_json::json!({ "name": "GPT-4 weekly", "amount": 50.0, "period": "weekly" }))
+        .await;
+    updated.assert_status_ok();
+    let updated = updated.json::<Value>();
+    assert_eq!(updated["budget"]["id"], budget_id.as_str());
+    // The scope was dropped, so every model counts: Sunday 3rd closes the week of Feb 26th
+    assert_eq!(updated["status"]["period_start"], "2024-02-26");
+    assert_eq!(updated["status"]["spent"], 31.0);
+
+    let invalid = server
+        .post("/api/budgets")
+        .json(&serde_json::json!({ "name": "Bad", "amount": -1.0, "period": "daily" }))
+        .await;
+    invalid.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.json::<Value>()["error"]["code"], "INVALID_BUDGET");
+
+    server
+        .delete(&format!("/api/budgets/{}", budget_id))
+        .await
+        .assert_status_ok();
+    server
+        .delete(&format!("/api/budgets/{}", budget_id))
+        .await
+        .assert_status(axum::http::StatusCode::NOT_FOUND);
+}
+
+/// Requests received by a local webhook receiver: path, signature header and body
+type ReceivedHooks = Arc<Mutex<Vec<(String, Option<String>, Value)>>>;
+
+/// Start an HTTP stand-in for webhook receivers that answers 503 to its first
+/// request and 200 afterwards; returns its base URL and the requests it received
+async fn start_webhook_receiver() -> (String, ReceivedHooks) {
+    use axum::{extract::State, http::{HeaderMap, StatusCode, Uri}, Json, Router};
+
+    let received: ReceivedHooks = Arc::default();
+    let app = Router::new()
+        .fallback(
+            |State(received): State<ReceivedHooks>, uri: Uri, headers: HeaderMap, Json(body): Json<Value>| async move {
+                let mut received = received.lock().unwrap();
+                let signature = headers
+                    .get("x-webhook-signature")
+                    .map(|value| value.to_str().unwrap().to_string());
+                received.push((uri.path().to_string(), signature, body));
+                if received.len() == 1 {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::OK
+                }
+            },
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", address), received)
+}
+
+#[tokio::test]
+async fn test_webhooks_are_notified_of_budget_alerts_on_append() {
+    let (receiver_url, received) = start_webhook_receiver().await;
+    let dispatcher = WebhookDispatcher::with_retry_policy(RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+        timeout: Duration::from_secs(5),
+    });
+    let server = TestServer::new(create_router(
+        AppState::in_memory().with_webhook_dispatcher(Arc::new(dispatcher)),
+    ))
+    .unwrap();
+
+    server
+        .post("/api/budgets")
+        .json(&serde_json::json!({ "name": "Monthly", "amount": 10.0, "period": "monthly", "thresholds": [100] }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    let created = server
+        .post("/api/webhooks")
+        .json(&serde_json::json!({ "url": format!("{}/json", receiver_url), "secret": "s3cret" }))
+        .await;
+    created.assert_status(axum::http::StatusCode::CREATED);
+    assert_eq!(created.json::<Value>()["webhook"]["signed"], true);
+    server
+        .post("/api/webhooks")
+        .json(&serde_json::json!({ "url": format!("{}/slack", receiver_url), "format": "slack" }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+
+    let invalid = server
+        .post("/api/webhooks")
+        .json(&serde_json::json!({ "url": "ftp://example.com" }))
+        .await;
+    invalid.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.json::<Value>()["error"]["code"], "INVALID_WEBHOOK");
+
+    let header = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n";
+    upload(&server, "/api/upload", &format!("{}2024-03-01T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,4.00", header), "first.csv").await;
+    let response = upload(
+        &server,
+        "/api/upload/append",
+        &format!("{}2024-03-02T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,8.00", header),
+        "second.csv",
+    )
+    .await;
+    assert_eq!(response["budget_alerts"][0]["threshold"], 100);
+
+    // Deliveries happen in the background; wait for both to be logged
+    let mut deliveries = Vec::new();
+    for _ in 0..100 {
+        let log = server.get("/api/webhooks/deliveries").await.json::<Value>();
+        deliveries = log["deliveries"].as_array().unwrap().clone();
+        if deliveries.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|delivery| delivery["success"] == true));
+    // The receiver's first answer was a 503, so one delivery needed a retry
+    let mut attempts: Vec<u64> = deliveries.iter().map(|d| d["attempts"].as_u64().unwrap()).collect();
+    attempts.sort_unstable();
+    assert_eq!(attempts, vec![1, 2]);
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    let (_, signature, body) = received.iter().rev().find(|(path, _, _)| path == "/json").unwrap();
+    assert!(signature.as_ref().unwrap().starts_with("sha256="));
+    assert_eq!(body["event"], "budget_threshold");
+    assert_eq!(body["data"]["threshold"], 100);
+    assert_eq!(body["data"]["spent"], 12.0);
+    let (_, signature, body) = received.iter().find(|(path, _, _)| path == "/slack").unwrap();
+    assert!(signature.is_none());
+    assert!(body["text"].as_str().unwrap().contains("reached 100%"));
+}
+
+#[tokio::test]
+async fn test_anomalies_endpoint() {
+    let server = create_test_server();
+    let mut csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n".to_string();
+    let costs = [1.0, 1.2, 0.8, 1.1, 0.9, 1.0, 1.3, 0.7, 1.0, 1.1, 12.0];
+    for (i, cost) in costs.iter().enumerate() {
+        csv.push_str(&format!(
+            "2024-05-{:02}T12:00:00Z,Usage-based,auto,No,100,50,25,75,250,{:.2}\n",
+            i + 1,
+            cost
+        ));
+    }
+    upload(&server, "/api/upload", csv.trim_end(), "usage.csv").await;
+
+    let response = server.get("/api/stats/anomalies").await;
+    response.assert_status_ok();
+    let anomalies = &response.json::<Value>()["anomalies"];
+    assert_eq!(anomalies["window_days"], 14);
+    let daily = anomalies["daily"].as_array().unwrap();
+    assert_eq!(daily.len(), 1);
+    assert_eq!(daily[0]["date"], "2024-05-11");
+    assert_eq!(daily[0]["metric"], "cost");
+    assert_eq!(daily[0]["baseline"], 1.0);
+    assert!(daily[0]["reason"].as_str().unwrap().contains("median"));
+    // Too few requests per model for a p99 baseline
+    assert_eq!(anomalies["request_count"], 0);
+
+    // Nothing is that far from the median
+    let strict = server
+        .get("/api/stats/anomalies")
+        .add_query_param("mad_threshold", "1000")
+        .await
+        .json::<Value>();
+    assert!(strict["anomalies"]["daily"].as_array().unwrap().is_empty());
+
+    server
+        .get("/api/stats/anomalies")
+        .add_query_param("window_days", "0")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sessions_endpoint() {
+    let server = create_test_server();
+    let csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost
+2024-01-01T18:00:00Z,Usage-based,auto,No,100,50,25,75,250,0.50
+2024-01-01T10:50:00Z,Usage-based,gpt-4,No,100,50,25,825,1000,4.00
+2024-01-01T10:25:00Z,Usage-based,auto,No,100,50,25,75,250,0.10
+2024-01-01T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,0.20";
+    upload(&server, "/api/upload", csv, "usage.csv").await;
+
+    let response = server.get("/api/stats/sessions").await;
+    response.assert_status_ok();
+    let sessions = &response.json::<Value>()["sessions"];
+    assert_eq!(sessions["idle_gap_minutes"], 30);
+    assert_eq!(sessions["session_count"], 2);
+    let first = &sessions["sessions"][0];
+    assert_eq!(first["duration_minutes"], 50.0);
+    assert_eq!(first["record_count"], 3);
+    assert_eq!(first["total_tokens"], 1500);
+    assert_eq!(first["dominant_model"], "gpt-4");
+    let most_expensive = sessions["most_expensive"].as_array().unwrap();
+    assert_eq!(most_expensive[0]["start"], first["start"]);
+
+    // Gaps of 25 minutes end a session when the idle gap is 25
+    let split = server
+        .get("/api/stats/sessions")
+        .add_query_param("idle_gap_minutes", "25")
+        .add_query_param("limit", "1")
+        .await
+        .json::<Value>();
+    assert_eq!(split["sessions"]["session_count"], 4);
+    assert_eq!(split["sessions"]["most_expensive"].as_array().unwrap().len(), 1);
+    assert_eq!(split["sessions"]["most_expensive"][0]["total_cost"], 4.0);
+
+    server
+        .get("/api/stats/sessions")
+        .add_query_param("idle_gap_minutes", "0")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_top_usage_endpoint() {
+    let server = create_test_server();
+    let csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost
+2024-01-02T09:00:00Z,Usage-based,auto,No,100,50,25,4825,5000,0.10
+2024-01-01T11:00:00Z,Usage-based,gpt-4,Yes,100,50,25,25,200,2.00
+2024-01-01T10:40:00Z,Usage-based,auto,No,100,50,25,825,1000,0.60
+2024-01-01T10:05:00Z,Included,auto,No,100,50,25,825,1000,0.50";
+    upload(&server, "/api/upload", csv, "usage.csv").await;
+
+    let response = server.get("/api/stats/top").add_query_param("limit", "2").await;
+    response.assert_status_ok();
+    let top = &response.json::<Value>()["top"];
+    assert_eq!(top["metric"], "cost");
+    let requests = top["requests"].as_array().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0]["model"], "gpt-4");
+    assert_eq!(requests[1]["cost"], 0.6);
+    assert_eq!(top["hours"][0]["start"], "2024-01-01T11:00:00+00:00");
+    assert_eq!(top["hours"][1]["record_count"], 2);
+    assert_eq!(top["days"][0]["start"], "2024-01-01T00:00:00+00:00");
+
+    let by_tokens = server
+        .get("/api/stats/top")
+        .add_query_param("metric", "tokens")
+        .add_query_param("model", "auto")
+        .add_query_param("max_mode", "false")
+        .add_query_param("kind", "Usage-based")
+        .await
+        .json::<Value>();
+    assert_eq!(by_tokens["record_count"], 2);
+    assert_eq!(by_tokens["top"]["requests"][0]["total_tokens"], 5000);
+    assert_eq!(by_tokens["top"]["days"][0]["total_tokens"], 5000);
+
+    let max_mode = server
+        .get("/api/stats/top")
+        .add_query_param("max_mode", "true")
+        .await
+        .json::<Value>();
+    assert_eq!(max_mode["record_count"], 1);
+    assert_eq!(max_mode["top"]["requests"][0]["max_mode"], true);
+
+    server
+        .get("/api/stats/top")
+        .add_query_param("metric", "requests")
+        .await
+        .assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_slow_upload_does_not_block_other_requests() {
+    let state = AppState::new(Arc::new(SqliteStore::open_in_memory().unwrap()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, create_router(state)).await.unwrap() });
+
+    // Send the upload's headers and half its body, then stall
+    let body = create_multipart_body(&create_test_csv_data(), "usage.csv").into_bytes();
+    let (first, rest) = body.split_at(body.len() / 2);
+    let mut upload = tokio::net::TcpStream::connect(address).await.unwrap();
+    let head = format!(
+        "POST /api/upload HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\
+         Content-Type: multipart/form-data; boundary={}\r\nContent-Length: {}\r\n\r\n",
+        address,
+        BOUNDARY,
+        body.len()
+    );
+    upload.write_all(head.as_bytes()).await.unwrap();
+    upload.write_all(first).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let datasets = reqwest::Client::new()
+        .get(format!("http://{}/api/datasets", address))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .expect("store calls must not wait for a pending upload");
+    assert!(datasets.status().is_success());
+
+    upload.write_all(rest).await.unwrap();
+    let mut response = String::new();
+    upload.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"record_count\":2"));
+}
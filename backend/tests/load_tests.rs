@@ -1,6 +1,7 @@
 use cursor_usage_dashboard_backend::{
+    models::dataset::DEFAULT_DATASET_ID,
     services::{csv_parser::CsvParser, stats_calculator::StatsCalculator},
-    storage::UPLOADED_DATA,
+    storage::{memory::MemoryStore, UsageStore},
 };
 use std::sync::Arc;
 use std::thread;
@@ -92,6 +93,9 @@ fn test_concurrent_stats_calculation_load() {
             output_tokens: 75 + (i % 75) as u32,
             total_tokens: 250 + (i % 250) as u32,
             cost: 0.05 + (i as f64 * 0.001),
+            user: None,
+            requests: 1.0,
+            import_id: None,
         });
     }
     
@@ -195,11 +199,8 @@ fn test_memory_pressure_large_datasets() {
 
 #[test]
 fn test_concurrent_file_upload_simulation() {
-    // Clear existing data
-    {
-        let mut data_store = UPLOADED_DATA.lock().unwrap();
-        data_store.clear();
-    }
+    // Each test run writes into its own isolated store
+    let store = Arc::new(MemoryStore::new());
     
     let parser = Arc::new(CsvParser::new());
     let num_concurrent_uploads = 5;
@@ -211,6 +212,7 @@ fn test_concurrent_file_upload_simulation() {
     // Simulate concurrent file uploads
     for i in 0..num_concurrent_uploads {
         let parser_clone = Arc::clone(&parser);
+        let store_clone = Arc::clone(&store);
         
         let handle = thread::spawn(move || {
             let csv_data = create_test_csv_data(records_per_upload);
@@ -222,6 +224,7 @@ fn test_concurrent_file_upload_simulation() {
             
             let usage_data = result.unwrap();
             assert_eq!(usage_data.len(), records_per_upload);
+            store_clone.insert_batch(DEFAULT_DATASET_ID, &usage_data).unwrap();
             
             let upload_duration = upload_start.elapsed();
             println!("Upload {} completed in {:?}", i, upload_duration);
@@ -252,6 +255,7 @@ fn test_concurrent_file_upload_simulation() {
     
     // Performance assertions
     assert_eq!(total_records, num_concurrent_uploads * records_per_upload);
+    assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), total_records);
     assert!(total_elapsed.as_secs() < 5, "Concurrent uploads took too long: {:?}", total_elapsed);
     assert!(avg_upload_time.as_millis() < 500, "Average upload time too high: {:?}", avg_upload_time);
     
@@ -1,9 +1,55 @@
 use cursor_usage_dashboard_backend::{
-    models::usage_data::UsageData,
+    models::{dataset::DEFAULT_DATASET_ID, usage_data::UsageData},
     services::{csv_parser::CsvParser, stats_calculator::StatsCalculator},
+    storage::{sqlite::SqliteStore, UsageStore, WriteMode},
 };
+use std::io::Read;
 use std::mem;
 
+/// Reader that generates CSV rows on demand, so the full file never exists in memory
+struct GeneratedCsv {
+    rows_remaining: usize,
+    row_index: usize,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl GeneratedCsv {
+    fn new(num_records: usize) -> Self {
+        Self {
+            rows_remaining: num_records,
+            row_index: 0,
+            pending: b"Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n".to_vec(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for GeneratedCsv {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.pending.len() {
+            if self.rows_remaining == 0 {
+                return Ok(0);
+            }
+            let i = self.row_index;
+            self.pending = format!(
+                "2024-01-{:02}T{:02}:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n",
+                (i % 30) + 1,
+                (i % 24)
+            )
+            .into_bytes();
+            self.position = 0;
+            self.row_index += 1;
+            self.rows_remaining -= 1;
+        }
+
+        let len = buf.len().min(self.pending.len() - self.position);
+        buf[..len].copy_from_slice(&self.pending[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
 fn create_large_csv_data(num_records: usize) -> String {
     let mut csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n".to_string();
     
@@ -67,6 +113,9 @@ fn test_memory_usage_stats_calculation() {
             output_tokens: 75 + (i % 75) as u32,
             total_tokens: 250 + (i % 250) as u32,
             cost: 0.05 + (i as f64 * 0.001),
+            user: None,
+            requests: 1.0,
+            import_id: None,
         });
     }
     
@@ -157,4 +206,51 @@ fn test_large_dataset_processing() {
     // Performance assertions (these are rough guidelines)
     assert!(parse_duration.as_secs() < 5, "Parsing took too long: {:?}", parse_duration);
     assert!(stats_duration.as_secs() < 2, "Stats calculation took too long: {:?}", stats_duration);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_streaming_parse_memory_is_bounded_by_batch_size() {
+    let parser = CsvParser::new();
+    let batch_size = 1000;
+
+    // The largest batch held at once must not depend on how long the input is
+    for &size in &[10_000, 100_000, 250_000] {
+        let mut largest_batch = 0;
+        let mut batches = 0;
+
+        let total = parser
+            .parse_reader(GeneratedCsv::new(size), batch_size, |batch| {
+                largest_batch = largest_batch.max(batch.len());
+                batches += 1;
+                Ok(())
+            })
+            .unwrap();
+
+        let peak_memory = largest_batch * mem::size_of::<UsageData>();
+        println!(
+            "Streamed {} records in {} batches, peak buffered records: {} (~{} bytes)",
+            total, batches, largest_batch, peak_memory
+        );
+
+        assert_eq!(total, size);
+        assert_eq!(batches, size.div_ceil(batch_size));
+        assert!(largest_batch <= batch_size, "Batch of {} exceeded limit", largest_batch);
+    }
+}
+
+#[test]
+fn test_streaming_ingest_into_store() {
+    let parser = CsvParser::new();
+    let store = SqliteStore::open_in_memory().unwrap();
+    let size = 50_000;
+
+    let mut writer = store.begin_write(DEFAULT_DATASET_ID, WriteMode::Replace).unwrap();
+    let total = parser
+        .parse_reader(GeneratedCsv::new(size), 1000, |batch| writer.write(&batch))
+        .unwrap();
+    writer.commit().unwrap();
+
+    assert_eq!(total, size);
+    assert_eq!(store.count(DEFAULT_DATASET_ID).unwrap(), size);
+}
+
@@ -34,6 +34,9 @@ fn create_usage_data(num_records: usize) -> Vec<UsageData> {
             output_tokens: 75 + (i % 75) as u32,
             total_tokens: 250 + (i % 250) as u32,
             cost: 0.05 + (i as f64 * 0.001),
+            user: None,
+            requests: 1.0,
+            import_id: None,
         });
     }
     
@@ -200,7 +203,7 @@ fn test_peak_performance_benchmarks() {
 #[test]
 fn test_memory_usage_patterns() {
     let parser = CsvParser::new();
-    let calculator = StatsCalculator::new();
+    let _calculator = StatsCalculator::new();
     
     println!("\n=== Memory Usage Pattern Analysis ===");
     
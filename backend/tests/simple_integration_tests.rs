@@ -1,5 +1,5 @@
 use cursor_usage_dashboard_backend::{
-    services::{csv_parser::CsvParser, stats_calculator::StatsCalculator},
+    services::{csv_parser::CsvParser, data_processor::DataProcessor, stats_calculator::StatsCalculator},
 };
 
 fn create_test_csv_data() -> String {
@@ -41,18 +41,18 @@ fn test_error_handling_workflow() {
     
     // Test invalid CSV format
     let invalid_csv = "Invalid,CSV,Format\n1,2,3";
-    let result = parser.parse_csv(&invalid_csv);
+    let result = parser.parse_csv(invalid_csv);
     assert!(result.is_err());
     
     // Test empty CSV
     let empty_csv = "";
-    let result = parser.parse_csv(&empty_csv);
+    let result = parser.parse_csv(empty_csv);
     assert!(result.is_err());
     
     // Test CSV with wrong column count
     let wrong_columns = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                         2024-01-01T10:00:00Z,Included,auto,No,100,50,25";
-    let result = parser.parse_csv(&wrong_columns);
+    let result = parser.parse_csv(wrong_columns);
     assert!(result.is_err());
     
     println!("Error handling tests completed successfully");
@@ -110,20 +110,20 @@ fn test_data_validation_edge_cases() {
     // Test with negative numbers (should fail)
     let negative_csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                        2024-01-01T10:00:00Z,Included,auto,No,-100,50,25,75,250,0.05";
-    let result = parser.parse_csv(&negative_csv);
+    let result = parser.parse_csv(negative_csv);
     assert!(result.is_err());
     
     // Test with invalid date format
     let invalid_date_csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                           invalid-date,Included,auto,No,100,50,25,75,250,0.05";
-    let result = parser.parse_csv(&invalid_date_csv);
+    let result = parser.parse_csv(invalid_date_csv);
     // This should still parse (we don't validate date format in parsing, only in stats calculation)
     assert!(result.is_ok());
     
     // Test with token calculation mismatch
     let mismatch_csv = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost\n\
                        2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,300,0.05";
-    let result = parser.parse_csv(&mismatch_csv);
+    let result = parser.parse_csv(mismatch_csv);
     assert!(result.is_err());
     
     println!("Data validation edge cases completed successfully");
@@ -176,4 +176,68 @@ fn test_concurrent_processing() {
     }
     
     println!("Concurrent processing test completed successfully");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_team_export_grouping_by_kind_and_user() {
+    let parser = CsvParser::new();
+    let processor = DataProcessor::new();
+
+    let csv_content = "Date,User,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost,Requests\n\
+                       2024-01-01T10:00:00Z,alice@example.com,Included,auto,No,100,50,25,75,250,0.05,1\n\
+                       2024-01-01T11:00:00Z,alice@example.com,Usage-based,gpt-4,Yes,200,100,50,150,500,0.40,3\n\
+                       2024-01-01T12:00:00Z,bob@example.com,\"Errored, No Charge\",gpt-4,No,0,0,0,0,0,0,0";
+    let usage_data = parser.parse_csv(csv_content).unwrap();
+
+    let summary = processor.calculate_summary(&usage_data);
+
+    assert_eq!(summary.kind_breakdown.len(), 3);
+    assert_eq!(summary.kind_breakdown[0].key, "Usage-based");
+    assert_eq!(summary.kind_breakdown[0].requests, 3.0);
+    let errored = summary.kind_breakdown.iter().find(|stats| stats.key == "Errored, No Charge").unwrap();
+    assert_eq!(errored.errored_count, 1);
+
+    assert_eq!(summary.user_breakdown.len(), 2);
+    assert_eq!(summary.user_breakdown[0].key, "alice@example.com");
+    assert_eq!(summary.user_breakdown[0].record_count, 2);
+    assert_eq!(summary.user_breakdown[0].requests, 4.0);
+    assert!((summary.user_breakdown[0].total_cost - 0.45).abs() < 1e-9);
+    assert_eq!(summary.user_breakdown[1].total_cost, 0.0);
+}
+
+#[test]
+fn test_merge_keeps_repeats_and_drops_reimports() {
+    let parser = CsvParser::new();
+    let processor = DataProcessor::new();
+    let header = "Date,Kind,Model,Max Mode,Input (w/ Cache Write),Input (w/o Cache Write),Cache Read,Output Tokens,Total Tokens,Cost";
+
+    // Two identical requests in one export are both real
+    let january = parser
+        .parse_csv(&format!(
+            "{h}\n2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05",
+            h = header
+        ))
+        .unwrap();
+    let merged = processor.merge_data(Vec::new(), january.clone());
+    assert_eq!(merged.len(), 2);
+
+    // Re-importing the same rows adds nothing
+    let merged = processor.merge_data(merged, january);
+    assert_eq!(merged.len(), 2);
+
+    // An overlapping export adds only its extra repeat and the near-duplicate with another kind
+    let overlapping = parser
+        .parse_csv(&format!(
+            "{h}\n2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+             2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+             2024-01-01T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05\n\
+             2024-01-01T10:00:00Z,Usage-based,auto,No,100,50,25,75,250,0.05\n\
+             2023-12-31T10:00:00Z,Included,auto,No,100,50,25,75,250,0.05",
+            h = header
+        ))
+        .unwrap();
+    let merged = processor.merge_data(merged, overlapping);
+    assert_eq!(merged.len(), 5);
+    assert_eq!(merged[0].date, "2023-12-31T10:00:00Z");
+    assert_eq!(merged.iter().filter(|usage| usage.kind == "Usage-based").count(), 1);
+}
@@ -19,8 +19,10 @@ export default function FileUpload({ onFileUpload, isLoading = false }: FileUplo
 
   const validateFile = (file: File): string | null => {
     // Check file type
-    if (!file.name.endsWith('.csv') && file.type !== 'text/csv') {
-      return 'Please upload a CSV file';
+    const name = file.name.toLowerCase();
+    const supported = ['.csv', '.csv.gz', '.zip', '.json', '.ndjson', '.jsonl'].some((extension) => name.endsWith(extension));
+    if (!supported && file.type !== 'text/csv') {
+      return 'Please upload a CSV file (.csv, .csv.gz or .zip) or JSON usage events (.json, .ndjson)';
     }
 
     // Check file size
@@ -152,7 +154,7 @@ export default function FileUpload({ onFileUpload, isLoading = false }: FileUplo
         <input
           ref={fileInputRef}
           type="file"
-          accept=".csv,text/csv"
+          accept=".csv,.csv.gz,.zip,.json,.ndjson,.jsonl,text/csv,application/json"
           onChange={handleFileInputChange}
           disabled={isDisabled}
           className="hidden"
@@ -1,6 +1,8 @@
 import type {
   UploadResponse,
   ComprehensiveStats,
+  Granularity,
+  TimeSeriesResponse,
   HealthResponse,
   ErrorResponse,
 } from '@/app/types';
@@ -73,6 +75,30 @@ export async function getComprehensiveStats(): Promise<ComprehensiveStats> {
   return data as ComprehensiveStats;
 }
 
+/**
+ * Get token, cost and request totals bucketed by time
+ */
+export async function getTimeSeries(
+  granularity: Granularity,
+  byModel = false
+): Promise<TimeSeriesResponse> {
+  const params = new URLSearchParams({ granularity, by_model: String(byModel) });
+  const response = await fetch(`${API_BASE}/stats/timeseries?${params}`, {
+    method: 'GET',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+  });
+
+  const data = await response.json();
+
+  if (!response.ok) {
+    throw new Error((data as ErrorResponse).error.message);
+  }
+
+  return data as TimeSeriesResponse;
+}
+
 /**
  * Health check
  */
@@ -11,6 +11,9 @@ export interface UsageData {
   output_tokens: number;
   total_tokens: number;
   cost: number;
+  user: string | null;
+  requests: number;
+  import_id?: string;
 }
 
 export interface DateRange {
@@ -34,6 +37,17 @@ export interface UsageSummary {
   most_used_model: string;
   date_range: DateRange;
   model_breakdown: ModelStats[];
+  kind_breakdown: GroupStats[];
+  user_breakdown: GroupStats[];
+}
+
+export interface GroupStats {
+  key: string;
+  record_count: number;
+  requests: number;
+  total_tokens: number;
+  total_cost: number;
+  errored_count: number;
 }
 
 // Comprehensive statistics structures
@@ -48,6 +62,8 @@ export interface CostEfficiencyStats {
   cost_per_token: number;
   cost_per_request: number;
   cache_savings: number;
+  currency: string;
+  unpriced_models: string[];
 }
 
 export interface UsagePercentiles {
@@ -68,11 +84,330 @@ export interface ComprehensiveStats {
   usage_trends: UsageTrendStats;
 }
 
+export type Granularity = 'daily' | 'hourly' | '10min';
+
+export interface TimeSeriesBucket {
+  start: string;
+  input_tokens: number;
+  output_tokens: number;
+  cache_read_tokens: number;
+  total_tokens: number;
+  cost: number;
+  requests: number;
+}
+
+export interface ModelTimeSeries {
+  model: string;
+  buckets: TimeSeriesBucket[];
+}
+
+export interface TimeSeries {
+  granularity: Granularity;
+  timezone: string;
+  buckets: TimeSeriesBucket[];
+  by_model?: ModelTimeSeries[];
+}
+
+export interface CostAuditGroup {
+  date: string;
+  model: string;
+  record_count: number;
+  expected_cost: number;
+  charged_cost: number;
+  difference: number;
+}
+
+export interface CostAuditRow {
+  date: string;
+  model: string;
+  kind: string;
+  max_mode: boolean;
+  total_tokens: number;
+  expected_cost: number;
+  charged_cost: number;
+  difference: number;
+  relative_difference: number | null;
+}
+
+export interface CostAudit {
+  currency: string;
+  tolerance: number;
+  expected_total: number;
+  charged_total: number;
+  difference: number;
+  flagged_count: number;
+  groups: CostAuditGroup[];
+  top_rows: CostAuditRow[];
+  unpriced_records: number;
+  unpriced_models: string[];
+  no_charge_records: number;
+}
+
+export interface SimulationGroup {
+  date: string;
+  model: string;
+  projected_model: string | null;
+  record_count: number;
+  actual_cost: number;
+  projected_cost: number;
+  difference: number;
+}
+
+export interface Simulation {
+  currency: string;
+  actual_cost: number;
+  projected_cost: number;
+  difference: number;
+  substituted_records: number;
+  unpriced_records: number;
+  unpriced_models: string[];
+  groups: SimulationGroup[];
+}
+
+export type ForecastMethod = 'linear' | 'exponential_smoothing';
+
+export interface ProjectedTotal {
+  projected: number;
+  lower: number;
+  upper: number;
+}
+
+export interface ForecastPoint {
+  date: string;
+  cost: number;
+  tokens: number;
+}
+
+export interface MethodForecast {
+  method: ForecastMethod;
+  cost: ProjectedTotal;
+  tokens: ProjectedTotal;
+  daily: ForecastPoint[];
+}
+
+export interface Forecast {
+  period_start: string;
+  period_end: string;
+  as_of: string;
+  days_elapsed: number;
+  days_remaining: number;
+  history_days: number;
+  confidence: number;
+  actual_cost: number;
+  actual_tokens: number;
+  forecasts: MethodForecast[];
+}
+
+export interface BillingCycleConfig {
+  anchor_day: number;
+  timezone?: string;
+}
+
+export interface CycleSummary {
+  start: string;
+  end: string;
+  record_count: number;
+  total_cost: number;
+  total_tokens: number;
+  total_requests: number;
+  summary: UsageSummary;
+}
+
+export interface CycleComparison {
+  as_of: string;
+  current: CycleSummary;
+  current_end: string;
+  previous_to_date: CycleSummary;
+  previous: CycleSummary;
+  cost_change: number | null;
+  tokens_change: number | null;
+  requests_change: number | null;
+}
+
+export type BudgetPeriod = 'daily' | 'weekly' | 'monthly';
+
+export interface BudgetScope {
+  model?: string;
+  kind?: string;
+  user?: string;
+}
+
+export interface Budget {
+  id: string;
+  name: string;
+  amount: number;
+  period: BudgetPeriod;
+  scope: BudgetScope;
+  thresholds: number[];
+  created_at: string;
+}
+
+export interface BudgetStatus {
+  budget_id: string;
+  name: string;
+  period: BudgetPeriod;
+  period_start: string;
+  period_end: string;
+  as_of: string;
+  amount: number;
+  spent: number;
+  percent_used: number;
+  thresholds_crossed: number[];
+  projected_spend: number;
+  projected_overrun: number;
+}
+
+export interface BudgetAlert {
+  budget_id: string;
+  name: string;
+  threshold: number;
+  period_start: string;
+  period_end: string;
+  amount: number;
+  spent: number;
+  percent_used: number;
+  projected_spend: number;
+  projected_overrun: number;
+}
+
+export type WebhookEventKind = 'budget_threshold' | 'anomaly';
+
+export interface Webhook {
+  id: string;
+  url: string;
+  format: 'json' | 'slack';
+  signed: boolean;
+  events: WebhookEventKind[];
+  created_at: string;
+}
+
+export interface WebhookDelivery {
+  id: string;
+  webhook_id: string;
+  event_id: string;
+  event: WebhookEventKind;
+  url: string;
+  success: boolean;
+  attempts: number;
+  status_code: number | null;
+  error: string | null;
+  created_at: string;
+}
+
+export type AnomalyMetric = 'cost' | 'tokens';
+
+export interface DailyAnomaly {
+  date: string;
+  metric: AnomalyMetric;
+  value: number;
+  baseline: number;
+  mad: number;
+  threshold: number;
+  score: number;
+  reason: string;
+}
+
+export interface RequestAnomaly {
+  date: string;
+  model: string;
+  kind: string;
+  max_mode: boolean;
+  metric: AnomalyMetric;
+  value: number;
+  baseline: number;
+  threshold: number;
+  ratio: number;
+  import_id: string | null;
+  reason: string;
+}
+
+export interface AnomalyReport {
+  window_days: number;
+  mad_threshold: number;
+  request_multiplier: number;
+  daily: DailyAnomaly[];
+  requests: RequestAnomaly[];
+  request_count: number;
+}
+
+export interface UsageSession {
+  start: string;
+  end: string;
+  duration_minutes: number;
+  user?: string;
+  record_count: number;
+  requests: number;
+  total_tokens: number;
+  total_cost: number;
+  dominant_model: string;
+  model_count: number;
+}
+
+export interface SessionReport {
+  idle_gap_minutes: number;
+  session_count: number;
+  average_duration_minutes: number;
+  average_cost: number;
+  sessions: UsageSession[];
+  most_expensive: UsageSession[];
+}
+
+export type RankingMetric = 'cost' | 'tokens';
+
+export interface RankedRequest {
+  date: string;
+  model: string;
+  kind: string;
+  max_mode: boolean;
+  user?: string;
+  total_tokens: number;
+  cost: number;
+  import_id: string | null;
+}
+
+export interface RankedPeriod {
+  start: string;
+  record_count: number;
+  requests: number;
+  total_tokens: number;
+  cost: number;
+}
+
+export interface Rankings {
+  metric: RankingMetric;
+  limit: number;
+  requests: RankedRequest[];
+  hours: RankedPeriod[];
+  days: RankedPeriod[];
+}
+
 // API response types
+export interface TimeSeriesResponse {
+  success: boolean;
+  dataset_id: string;
+  record_count: number;
+  timeseries: TimeSeries;
+}
+
 export interface UploadResponse {
   success: boolean;
+  message?: string;
   data: UsageData[];
   summary: UsageSummary;
+  duplicate?: boolean;
+  import?: ImportRecord;
+  duplicate_of?: ImportRecord;
+  budgets?: BudgetStatus[];
+  budget_alerts?: BudgetAlert[];
+}
+
+export interface ImportRecord {
+  id: string;
+  file_name: string;
+  content_hash: string;
+  created_at: string;
+  record_count: number;
+  date_range: DateRange | null;
 }
 
 export interface ErrorDetails {